@@ -3,7 +3,9 @@ For board banana pi f3
 """
 
 from time import sleep
-from tester import PyTty, Serial, SdWirec, Exec, info
+from tester import PyTty, Serial, SdWirec, info
+
+from utils.utils import run_host
 
 
 class BPiF3:
@@ -16,15 +18,14 @@ class BPiF3:
         self.serial_port = serial_port
         self.baud = baud
 
-    def flash(self, shell: Exec, img: str, dsk="/dev/sda"):
+    def flash(self, img: str, dsk="/dev/sda"):
         """
         Flash the board with given image.
         """
         # self.sdwirec.to_ts()
         sleep(0.5)
-        shell.script_sudo(
-            f"dd if={img} of={dsk} status=progress bs=4M ", 600)
-        shell.script_sudo("sync")
+        run_host("sudo", ["dd", f"if={img}", f"of={dsk}", "bs=4M"], 600)
+        run_host("sync", [])
         sleep(0.5)
         # self.sdwirec.to_dut()
         sleep(0.5)
@@ -3,7 +3,9 @@ For board banana pi f3
 """
 
 from time import sleep
-from tester import PyTty, Serial, SdWirec, Exec, info
+from tester import PyTty, Serial, SdWirec, info
+
+from utils.utils import run_host
 
 
 class GenericBoard:
@@ -19,7 +21,7 @@ class GenericBoard:
         self.serial_port = serial_port
         self.baud = baud
 
-    def flash(self, shell: Exec, img: str, dsk="/dev/sda"):
+    def flash(self, img: str, dsk="/dev/sda"):
         """
         Flash the board with given image.
         """
@@ -29,9 +31,8 @@ class GenericBoard:
             info("Please insert the SD card to the tester and continue.")
             sleep(10)
         sleep(0.5)
-        shell.script_sudo(
-            f"dd if={img} of={dsk} status=progress bs=4M ", 600)
-        shell.script_sudo("sync")
+        run_host("sudo", ["dd", f"if={img}", f"of={dsk}", "bs=4M"], 600)
+        run_host("sync", [])
         sleep(0.5)
         if self.sdwirec is not None:
             self.sdwirec.to_dut()
@@ -48,7 +48,7 @@ def default_proc():
 
     info("Begin flashing board...")
 
-    board.flash(e, img, "/dev/sda")
+    board.flash(img, "/dev/sda")
 
     info("Flash board ended...")
 
@@ -43,7 +43,7 @@ def default_proc():
 
     info("Begin flashing board...")
 
-    board.flash(e, img, "/dev/mmcblk0")
+    board.flash(img, "/dev/mmcblk0")
 
     info("Flash board ended...")
 
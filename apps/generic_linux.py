@@ -40,7 +40,7 @@ def default_proc(url: str, work_dir: str, sd: str, username: str, passwd: str):
 
     info("Begin flashing board...")
 
-    board.flash(e, img, sd)
+    board.flash(img, sd)
 
     info("Flash board ended...")
 
@@ -1,9 +1,29 @@
 """
 ...
 """
+from tester import Process, info, err
+
+
 def swap_tty(recorder, tty):
     """
     ...
     """
     recorder.swap(tty)
     return tty
+
+
+def run_host(program, args, timeout=None):
+    """
+    Run a host tool like dd or fastboot, without a shell.
+    Logs its output, raises if it fails.
+    """
+    proc = Process(program, args)
+    code = proc.wait(timeout)
+    out = bytes(proc.read()).decode(errors="replace")
+    errs = bytes(proc.read_stderr()).decode(errors="replace")
+    if out:
+        info(out)
+    if code != 0:
+        err(errs)
+        raise RuntimeError(f"{program} failed with exit code {code}")
+    return out
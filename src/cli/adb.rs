@@ -0,0 +1,590 @@
+//! ADB backend for the [`Tty`] trait.
+//!
+//! The [`Adb`] opens an interactive `shell:` on a device, for boards which only
+//! expose adb over USB or USB-gadget Ethernet. There are two ways to reach
+//! the device, see [`AdbConnect`]:
+//! - Through a local adb server (`adb start-server`), which handles USB and
+//!   the key based authentication.
+//! - Straight to adbd over TCP with the adb wire protocol, so no adb install
+//!   is needed on the tester. Only devices without authentication are
+//!   supported, e.g. `ro.adb.secure=0`.
+//!
+//! Besides the shell, [`Adb::exec`] runs a single command, [`Adb::push`] and
+//! [`Adb::pull`] copy files with the sync protocol. Each of them opens a
+//! stream of its own.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use tester::cli::adb::{Adb, AdbConnect};
+//! # use tester::cli::tty::Tty;
+//! let mut adb = Adb::build(AdbConnect::Tcp {
+//!     addr: "192.168.42.1:5555".to_owned(),
+//! })?;
+//! adb.push("rootfs.tar", "/data/rootfs.tar")?;
+//! let out = adb.exec("ls -l /data")?;
+//! adb.write(b"uname -a\n")?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fs::File,
+    io::{ErrorKind, Read, Write},
+    net::TcpStream,
+    sync::{
+        mpsc::{channel, Sender},
+        Arc, Mutex,
+    },
+    thread::{sleep, spawn, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+
+use crate::{consts::SHELL_DURATION, err, impl_any, info, log};
+
+use super::tty::Tty;
+
+/// The default address of the adb server
+pub const ADB_SERVER: &str = "127.0.0.1:5037";
+
+const A_CNXN: u32 = u32::from_le_bytes(*b"CNXN");
+const A_AUTH: u32 = u32::from_le_bytes(*b"AUTH");
+const A_OPEN: u32 = u32::from_le_bytes(*b"OPEN");
+const A_OKAY: u32 = u32::from_le_bytes(*b"OKAY");
+const A_WRTE: u32 = u32::from_le_bytes(*b"WRTE");
+const A_CLSE: u32 = u32::from_le_bytes(*b"CLSE");
+
+/// The protocol version, adbd of it skips checking the data checksum
+const ADB_VERSION: u32 = 0x0100_0001;
+const MAX_PAYLOAD: usize = 256 * 1024;
+/// The local id of our stream, there's one stream per connection
+const LOCAL_ID: u32 = 1;
+
+/// The data chunk of the sync protocol
+const SYNC_CHUNK: usize = 64 * 1024;
+
+/// How to reach the device
+#[derive(Clone)]
+pub enum AdbConnect {
+    /// Through the adb server at `addr`, `serial` can be omitted if there's
+    /// only one device.
+    Server {
+        addr: String,
+        serial: Option<String>,
+    },
+    /// Straight to adbd at `addr`, usually on port 5555.
+    Tcp { addr: String },
+}
+
+struct Message {
+    command: u32,
+    arg0: u32,
+    arg1: u32,
+    data: Vec<u8>,
+}
+
+fn write_message(
+    stream: &mut TcpStream,
+    command: u32,
+    arg0: u32,
+    arg1: u32,
+    data: &[u8],
+) -> std::io::Result<()> {
+    let check = data.iter().map(|&b| b as u32).fold(0u32, u32::wrapping_add);
+    let mut buf = Vec::with_capacity(24 + data.len());
+    for v in [
+        command,
+        arg0,
+        arg1,
+        data.len() as u32,
+        check,
+        command ^ 0xffff_ffff,
+    ] {
+        buf.extend_from_slice(&v.to_le_bytes());
+    }
+    buf.extend_from_slice(data);
+    stream.write_all(&buf)
+}
+
+/// Read a message, `None` if nothing comes within `timeout`.
+fn read_message(
+    stream: &mut TcpStream,
+    timeout: Option<Duration>,
+) -> Result<Option<Message>, Box<dyn Error>> {
+    let mut head = [0u8; 24];
+    stream.set_read_timeout(timeout)?;
+    // Only the first byte may time out, the rest of the message follows soon
+    let res = stream.read(&mut head[..1]);
+    stream.set_read_timeout(None)?;
+    match res {
+        Ok(0) => return Err("adbd closed the connection".into()),
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+            return Ok(None)
+        }
+        Err(e) => return Err(Box::new(e)),
+    }
+    stream.read_exact(&mut head[1..])?;
+    let word = |i: usize| u32::from_le_bytes(head[i * 4..i * 4 + 4].try_into().unwrap());
+    if word(0) ^ 0xffff_ffff != word(5) {
+        return Err("Bad adb message magic".into());
+    }
+    let mut data = vec![0u8; word(3) as usize];
+    stream.read_exact(&mut data)?;
+    Ok(Some(Message {
+        command: word(0),
+        arg0: word(1),
+        arg1: word(2),
+        data,
+    }))
+}
+
+/// Send a request to the adb server, in its `<hex length><payload>` format
+fn send_request(stream: &mut TcpStream, req: &str) -> Result<(), Box<dyn Error>> {
+    stream.write_all(format!("{:04x}{}", req.len(), req).as_bytes())?;
+    Ok(())
+}
+
+/// Read a `<hex length><payload>` reply of the adb server
+fn read_reply(stream: &mut TcpStream) -> Result<String, Box<dyn Error>> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len)?;
+    let len = usize::from_str_radix(std::str::from_utf8(&len)?, 16)?;
+    let mut data = vec![0u8; len];
+    stream.read_exact(&mut data)?;
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+fn read_status(stream: &mut TcpStream) -> Result<(), Box<dyn Error>> {
+    let mut status = [0u8; 4];
+    stream.read_exact(&mut status)?;
+    match &status {
+        b"OKAY" => Ok(()),
+        b"FAIL" => Err(format!("adb server: {}", read_reply(stream)?).into()),
+        _ => Err("Bad reply of the adb server".into()),
+    }
+}
+
+/// List the devices of the adb server at `addr`, as `(serial, state)`.
+pub fn devices(addr: &str) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let mut stream = TcpStream::connect(addr)?;
+    send_request(&mut stream, "host:devices")?;
+    read_status(&mut stream)?;
+    let list = read_reply(&mut stream)?;
+    Ok(list
+        .lines()
+        .filter_map(|line| {
+            let (serial, state) = line.split_once('\t')?;
+            Some((serial.to_owned(), state.to_owned()))
+        })
+        .collect())
+}
+
+enum Link {
+    /// A raw stream of the adb server, after the service is opened
+    Server(TcpStream),
+    /// The wire protocol straight to adbd
+    Direct {
+        stream: TcpStream,
+        remote: u32,
+        max_payload: usize,
+        /// Data received while waiting for an OKAY
+        pending: VecDeque<Vec<u8>>,
+        closed: bool,
+    },
+}
+
+/// A byte stream to an adb service, e.g. `shell:` or `sync:`
+struct AdbStream {
+    link: Link,
+    /// Received but not consumed by [`AdbStream::read_exact`]
+    leftover: Vec<u8>,
+}
+
+impl AdbStream {
+    fn open(conn: &AdbConnect, service: &str) -> Result<AdbStream, Box<dyn Error>> {
+        let link = match conn {
+            AdbConnect::Server { addr, serial } => {
+                let mut stream = TcpStream::connect(addr)?;
+                let transport = match serial {
+                    Some(serial) => format!("host:transport:{}", serial),
+                    None => "host:transport-any".to_owned(),
+                };
+                send_request(&mut stream, &transport)?;
+                read_status(&mut stream)?;
+                send_request(&mut stream, service)?;
+                read_status(&mut stream)?;
+                Link::Server(stream)
+            }
+            AdbConnect::Tcp { addr } => {
+                let mut stream = TcpStream::connect(addr)?;
+                stream.set_nodelay(true)?;
+                write_message(
+                    &mut stream,
+                    A_CNXN,
+                    ADB_VERSION,
+                    MAX_PAYLOAD as u32,
+                    b"host::\0",
+                )?;
+                let max_payload = loop {
+                    match read_message(&mut stream, None)? {
+                        Some(m) if m.command == A_CNXN => break (m.arg1 as usize).min(MAX_PAYLOAD),
+                        Some(m) if m.command == A_AUTH => {
+                            return Err(
+                                "adbd asks for authentication, go through the adb server instead"
+                                    .into(),
+                            )
+                        }
+                        _ => continue,
+                    }
+                };
+                let mut name = service.as_bytes().to_vec();
+                name.push(0);
+                write_message(&mut stream, A_OPEN, LOCAL_ID, 0, &name)?;
+                let remote = loop {
+                    match read_message(&mut stream, None)? {
+                        Some(m) if m.command == A_OKAY => break m.arg0,
+                        Some(m) if m.command == A_CLSE => {
+                            return Err(format!("adbd refused to open {}", service).into())
+                        }
+                        _ => continue,
+                    }
+                };
+                Link::Direct {
+                    stream,
+                    remote,
+                    max_payload,
+                    pending: VecDeque::new(),
+                    closed: false,
+                }
+            }
+        };
+        log!("Opened adb service {}", service);
+        Ok(AdbStream {
+            link,
+            leftover: Vec::new(),
+        })
+    }
+
+    fn send(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        match &mut self.link {
+            Link::Server(stream) => stream.write_all(data)?,
+            Link::Direct {
+                stream,
+                remote,
+                max_payload,
+                pending,
+                closed,
+            } => {
+                for chunk in data.chunks(*max_payload) {
+                    if *closed {
+                        return Err("The adb stream is closed".into());
+                    }
+                    write_message(stream, A_WRTE, LOCAL_ID, *remote, chunk)?;
+                    // One write in flight at a time, wait for the device to take it
+                    loop {
+                        let m = read_message(stream, None)?.unwrap();
+                        match m.command {
+                            A_OKAY => break,
+                            A_WRTE => {
+                                write_message(stream, A_OKAY, LOCAL_ID, *remote, &[])?;
+                                pending.push_back(m.data);
+                            }
+                            A_CLSE => {
+                                *closed = true;
+                                break;
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Receive some data, `None` if nothing comes within `timeout`, empty if
+    /// the stream is closed.
+    fn recv(&mut self, timeout: Option<Duration>) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        if !self.leftover.is_empty() {
+            return Ok(Some(std::mem::take(&mut self.leftover)));
+        }
+        match &mut self.link {
+            Link::Server(stream) => {
+                let mut buf = [0u8; 4096];
+                stream.set_read_timeout(timeout)?;
+                match stream.read(&mut buf) {
+                    Ok(sz) => Ok(Some(buf[..sz].to_vec())),
+                    Err(e)
+                        if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut =>
+                    {
+                        Ok(None)
+                    }
+                    Err(e) => Err(Box::new(e)),
+                }
+            }
+            Link::Direct {
+                stream,
+                remote,
+                pending,
+                closed,
+                ..
+            } => {
+                if let Some(data) = pending.pop_front() {
+                    return Ok(Some(data));
+                }
+                loop {
+                    if *closed {
+                        return Ok(Some(Vec::new()));
+                    }
+                    let m = match read_message(stream, timeout)? {
+                        Some(m) => m,
+                        None => return Ok(None),
+                    };
+                    match m.command {
+                        A_WRTE => {
+                            write_message(stream, A_OKAY, LOCAL_ID, *remote, &[])?;
+                            if !m.data.is_empty() {
+                                return Ok(Some(m.data));
+                            }
+                        }
+                        A_CLSE => *closed = true,
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /// Receive exactly `len` bytes, for the sync protocol
+    fn read_exact(&mut self, len: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut res = Vec::with_capacity(len);
+        while res.len() < len {
+            let data = self.recv(None)?.unwrap_or_default();
+            if data.is_empty() {
+                return Err("The adb stream closed unexpectedly".into());
+            }
+            res.extend_from_slice(&data);
+        }
+        self.leftover = res.split_off(len);
+        Ok(res)
+    }
+
+    /// Receive everything until the stream closes
+    fn read_to_end(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut res = Vec::new();
+        loop {
+            let data = self.recv(None)?.unwrap_or_default();
+            if data.is_empty() {
+                return Ok(res);
+            }
+            res.extend_from_slice(&data);
+        }
+    }
+
+    fn sync_request(&mut self, id: &[u8; 4], data: &[u8]) -> Result<(), Box<dyn Error>> {
+        let mut buf = id.to_vec();
+        buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        buf.extend_from_slice(data);
+        self.send(&buf)
+    }
+
+    /// Read the `<id><length>` head of a sync reply, the message of a FAIL becomes the error
+    fn sync_reply(&mut self) -> Result<([u8; 4], u32), Box<dyn Error>> {
+        let head = self.read_exact(8)?;
+        let id: [u8; 4] = head[..4].try_into().unwrap();
+        let len = u32::from_le_bytes(head[4..].try_into().unwrap());
+        if &id == b"FAIL" {
+            let msg = self.read_exact(len as usize)?;
+            return Err(format!("adb sync: {}", String::from_utf8_lossy(&msg)).into());
+        }
+        Ok((id, len))
+    }
+}
+
+impl Drop for AdbStream {
+    fn drop(&mut self) {
+        if let Link::Direct {
+            stream,
+            remote,
+            closed: false,
+            ..
+        } = &mut self.link
+        {
+            let _ = write_message(stream, A_CLSE, LOCAL_ID, *remote, &[]);
+        }
+    }
+}
+
+pub struct Adb {
+    conn: AdbConnect,
+    buff: Arc<Mutex<Vec<u8>>>,
+    closed: Arc<Mutex<bool>>,
+    tx: Sender<Vec<u8>>,
+    stop: Arc<Mutex<bool>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Adb {
+    /// Build a new [`Adb`] instance, with an interactive shell on the device.
+    pub fn build(conn: AdbConnect) -> Result<Adb, Box<dyn Error>> {
+        let mut stream = AdbStream::open(&conn, "shell:")?;
+
+        let buff = Arc::new(Mutex::new(Vec::new()));
+        let closed = Arc::new(Mutex::new(false));
+        let stop = Arc::new(Mutex::new(false));
+        let (tx, rx) = channel::<Vec<u8>>();
+
+        let buff_clone = buff.clone();
+        let closed_clone = closed.clone();
+        let stop_clone = stop.clone();
+        let handle = spawn(move || loop {
+            if *stop_clone.lock().unwrap() {
+                return;
+            }
+            while let Ok(data) = rx.try_recv() {
+                if let Err(e) = stream.send(&data) {
+                    err!("Write to adb shell failed. Reason: {}", e);
+                    *closed_clone.lock().unwrap() = true;
+                    return;
+                }
+            }
+            match stream.recv(Some(Duration::from_millis(SHELL_DURATION))) {
+                Ok(Some(data)) if data.is_empty() => {
+                    log!("The adb shell has closed");
+                    *closed_clone.lock().unwrap() = true;
+                    return;
+                }
+                Ok(Some(data)) => buff_clone.lock().unwrap().extend_from_slice(&data),
+                Ok(None) => {}
+                Err(e) => {
+                    err!("Read from adb shell failed. Reason: {}", e);
+                    *closed_clone.lock().unwrap() = true;
+                    return;
+                }
+            }
+        });
+
+        info!("Adb shell opened");
+
+        Ok(Adb {
+            conn,
+            buff,
+            closed,
+            tx,
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    /// Run `cmd` on the device without a PTY, and return its output.
+    pub fn exec(&self, cmd: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        log!("Adb exec: {}", cmd);
+        let mut stream = AdbStream::open(&self.conn, &format!("shell:{}", cmd))?;
+        stream.read_to_end()
+    }
+
+    /// Copy the local file `local` to `remote` on the device, keeping its mode.
+    pub fn push(&self, local: &str, remote: &str) -> Result<(), Box<dyn Error>> {
+        info!("Adb push {} to {}", local, remote);
+        let mut file = File::open(local)?;
+        #[cfg(unix)]
+        let mode = file.metadata()?.permissions().mode() & 0o777;
+        // No mode bits to keep, a plain readable file
+        #[cfg(not(unix))]
+        let mode = 0o644;
+
+        let mut stream = AdbStream::open(&self.conn, "sync:")?;
+        stream.sync_request(
+            b"SEND",
+            format!("{},{}", remote, 0o100000 | mode).as_bytes(),
+        )?;
+        let mut buf = vec![0u8; SYNC_CHUNK];
+        loop {
+            let sz = file.read(&mut buf)?;
+            if sz == 0 {
+                break;
+            }
+            stream.sync_request(b"DATA", &buf[..sz])?;
+        }
+        let mtime = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as u32;
+        let mut done = b"DONE".to_vec();
+        done.extend_from_slice(&mtime.to_le_bytes());
+        stream.send(&done)?;
+        stream.sync_reply()?;
+        stream.sync_request(b"QUIT", &[])?;
+        Ok(())
+    }
+
+    /// Copy `remote` on the device to the local file `local`.
+    pub fn pull(&self, remote: &str, local: &str) -> Result<(), Box<dyn Error>> {
+        info!("Adb pull {} to {}", remote, local);
+        let mut stream = AdbStream::open(&self.conn, "sync:")?;
+        stream.sync_request(b"RECV", remote.as_bytes())?;
+        // Wait for the first reply, so a missing remote doesn't leave an empty local file
+        let (mut id, mut len) = stream.sync_reply()?;
+        let mut file = File::create(local)?;
+        loop {
+            match &id {
+                b"DATA" => file.write_all(&stream.read_exact(len as usize)?)?,
+                b"DONE" => break,
+                _ => return Err("Bad reply of adb sync".into()),
+            }
+            (id, len) = stream.sync_reply()?;
+        }
+        stream.sync_request(b"QUIT", &[])?;
+        Ok(())
+    }
+
+    fn shutdown(&mut self) {
+        *self.stop.lock().unwrap() = true;
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for Adb {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+impl_any!(Adb);
+
+impl Tty for Adb {
+    fn read(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut buff = self.buff.lock().unwrap();
+        if buff.is_empty() && *self.closed.lock().unwrap() {
+            return Err("The adb shell has closed".into());
+        }
+        Ok(std::mem::take(&mut *buff))
+    }
+
+    fn read_line(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        loop {
+            {
+                let mut buff = self.buff.lock().unwrap();
+                if let Some(pos) = buff.iter().position(|&b| b == b'\n') {
+                    return Ok(buff.drain(..=pos).collect());
+                }
+                if *self.closed.lock().unwrap() {
+                    return Err("The adb shell has closed".into());
+                }
+            }
+            sleep(Duration::from_millis(SHELL_DURATION));
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        if *self.closed.lock().unwrap() {
+            return Err("The adb shell has closed".into());
+        }
+        self.tx.send(data.to_vec())?;
+        Ok(())
+    }
+}
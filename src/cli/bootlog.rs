@@ -0,0 +1,262 @@
+//! [`BootLog`] is a wrapper for [`Tty`] that watches the boot log.
+//!
+//! It tracks the boot stages (firmware, bootloader, kernel, init, login) with
+//! timestamps, and turns fatal messages like `Kernel panic` into errors of
+//! [`Tty::read`], so a script waiting for the login prompt fails right away
+//! instead of waiting for the timeout.
+
+use std::{
+    collections::VecDeque,
+    error::Error,
+    time::{Duration, Instant},
+};
+
+use crate::{err, impl_any, info, vendor::strip_ansi_escapes};
+
+use super::tty::{DynTty, Tty, WrapperTty};
+
+/// How many lines before the fatal message to show in the error
+const CONTEXT_LINES: usize = 10;
+
+/// Treat a line longer than this as complete, in case the stream has no newline
+const MAX_LINE: usize = 4096;
+
+/// The prefix of systemd target messages, the target name follows
+const SYSTEMD_TARGET: &str = "Reached target ";
+
+/// A boot stage reached, with the time since the watch began
+#[derive(Clone, Debug)]
+pub struct BootStage {
+    pub name: String,
+    pub at: Duration,
+    /// The line which marks this stage
+    pub line: String,
+}
+
+pub struct BootLog {
+    inner: DynTty,
+    begin: Instant,
+    stage_patterns: Vec<(String, String)>,
+    fatal_patterns: Vec<String>,
+    stages: Vec<BootStage>,
+    fatal: Option<String>,
+    fail_fast: bool,
+    line: Vec<u8>,
+    context: VecDeque<String>,
+}
+
+impl BootLog {
+    /// Build a new [`BootLog`] instance, the clock starts now.
+    ///
+    /// With `fail_fast`, the reads after a fatal message fail, until [`BootLog::reset`].
+    pub fn build(inner: DynTty, fail_fast: bool) -> BootLog {
+        let stage_patterns = [
+            ("OpenSBI v", "OpenSBI"),
+            ("U-Boot SPL ", "U-Boot SPL"),
+            ("U-Boot 20", "U-Boot"),
+            ("Starting kernel", "Starting kernel"),
+            ("Linux version ", "Kernel"),
+            ("Run /sbin/init", "Init"),
+            ("systemd[1]: ", "systemd"),
+            ("Welcome to ", "systemd"),
+            ("login:", "Login"),
+        ];
+        let fatal_patterns = ["Kernel panic", "Oops", "BUG:", "Call Trace"];
+        BootLog {
+            inner,
+            begin: Instant::now(),
+            stage_patterns: stage_patterns
+                .iter()
+                .map(|(p, n)| (p.to_string(), n.to_string()))
+                .collect(),
+            fatal_patterns: fatal_patterns.iter().map(|p| p.to_string()).collect(),
+            stages: Vec::new(),
+            fatal: None,
+            fail_fast,
+            line: Vec::new(),
+            context: VecDeque::new(),
+        }
+    }
+
+    /// Mark a stage named `name` when `pattern` shows up.
+    pub fn add_stage(&mut self, pattern: &str, name: &str) {
+        self.stage_patterns
+            .push((pattern.to_owned(), name.to_owned()));
+    }
+
+    /// Treat `pattern` as a fatal message.
+    pub fn add_fatal(&mut self, pattern: &str) {
+        self.fatal_patterns.push(pattern.to_owned());
+    }
+
+    /// Forget everything and restart the clock, e.g. before a reboot.
+    pub fn reset(&mut self) {
+        self.begin = Instant::now();
+        self.stages.clear();
+        self.fatal = None;
+        self.line.clear();
+        self.context.clear();
+    }
+
+    /// The stages reached so far, in order
+    pub fn stages(&self) -> &[BootStage] {
+        &self.stages
+    }
+
+    /// Whether the stage named `name` is reached
+    pub fn reached(&self, name: &str) -> bool {
+        self.stages.iter().any(|s| s.name == name)
+    }
+
+    /// The fatal error found, with the context
+    pub fn fatal(&self) -> Option<&str> {
+        self.fatal.as_deref()
+    }
+
+    /// A table of the stages, with the time since the previous one.
+    pub fn report(&self) -> String {
+        let mut res = String::from("Boot timing:\n");
+        let mut last = Duration::ZERO;
+        for stage in &self.stages {
+            res += &format!(
+                "{:>9.3}s (+{:.3}s) {}\n",
+                stage.at.as_secs_f64(),
+                (stage.at - last).as_secs_f64(),
+                stage.name
+            );
+            last = stage.at;
+        }
+        if let Some(fatal) = &self.fatal {
+            res += &format!("Failed: {}\n", fatal.lines().next().unwrap_or(""));
+        }
+        res
+    }
+
+    fn check_line(&mut self, line: &str, complete: bool) {
+        let at = self.begin.elapsed();
+
+        if self.fatal.is_none() {
+            if let Some(pattern) = self
+                .fatal_patterns
+                .iter()
+                .find(|p| line.contains(p.as_str()))
+            {
+                let mut msg = format!(
+                    "Fatal boot error {{{}}} at {:.3}s: {}",
+                    pattern,
+                    at.as_secs_f64(),
+                    line.trim()
+                );
+                msg += "\nContext:\n";
+                for l in &self.context {
+                    msg += l;
+                    msg += "\n";
+                }
+                msg += line.trim_end();
+                err!("{}", msg);
+                self.fatal = Some(msg);
+            }
+        }
+
+        let mut found = Vec::new();
+        for (pattern, name) in &self.stage_patterns {
+            if line.contains(pattern.as_str()) && !self.stages.iter().any(|s| &s.name == name) {
+                found.push(name.clone());
+            }
+        }
+        // Each systemd target is a stage of its own, but only when the line is complete
+        if let Some(pos) = line.find(SYSTEMD_TARGET).filter(|_| complete) {
+            let target = line[pos + SYSTEMD_TARGET.len()..]
+                .trim()
+                .trim_end_matches('.');
+            let name = format!("Target {}", target);
+            if !self.stages.iter().any(|s| s.name == name) {
+                found.push(name);
+            }
+        }
+        for name in found {
+            info!(
+                "Boot stage {{{}}} reached at {:.3}s",
+                name,
+                at.as_secs_f64()
+            );
+            self.stages.push(BootStage {
+                name,
+                at,
+                line: line.trim().to_owned(),
+            });
+        }
+
+        if complete {
+            self.context.push_back(line.trim_end().to_owned());
+            if self.context.len() > CONTEXT_LINES {
+                self.context.pop_front();
+            }
+        }
+    }
+
+    fn process(&mut self, data: &[u8]) {
+        self.line.extend_from_slice(data);
+        while let Some(pos) = self
+            .line
+            .iter()
+            .position(|&c| c == b'\n')
+            .or((self.line.len() >= MAX_LINE).then_some(MAX_LINE - 1))
+        {
+            let line: Vec<u8> = self.line.drain(..=pos).collect();
+            let line = strip_ansi_escapes::strip(&line);
+            let line = String::from_utf8_lossy(&line).to_string();
+            self.check_line(&line, true);
+        }
+        // Prompts like `login: ` don't end with a newline
+        if !self.line.is_empty() {
+            let line = strip_ansi_escapes::strip(&self.line);
+            let line = String::from_utf8_lossy(&line).to_string();
+            self.check_line(&line, false);
+        }
+    }
+
+    fn check_fatal(&self) -> Result<(), Box<dyn Error>> {
+        match &self.fatal {
+            Some(msg) if self.fail_fast => Err(msg.clone().into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl_any!(BootLog);
+
+impl Tty for BootLog {
+    fn read(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.check_fatal()?;
+        let data = self.inner.read()?;
+        // A fatal message in `data` fails the next read, so the wrappers outside still log it
+        self.process(&data);
+        Ok(data)
+    }
+
+    fn read_line(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.check_fatal()?;
+        let data = self.inner.read_line()?;
+        self.process(&data);
+        Ok(data)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.inner.write(data)
+    }
+}
+
+impl WrapperTty for BootLog {
+    fn exit(self) -> DynTty {
+        self.inner
+    }
+
+    fn inner_ref(&self) -> &DynTty {
+        &self.inner
+    }
+
+    fn inner_mut(&mut self) -> &mut DynTty {
+        &mut self.inner
+    }
+}
@@ -0,0 +1,375 @@
+//! Share one console between several processes over a local socket.
+//!
+//! Only one process can open a serial port. [`ConsoleServer`] owns the backing
+//! [`Tty`] and serves it on a local socket, so a developer can watch while CI
+//! runs, or attach a second script. [`ConsoleClient`] is the [`Tty`] of the
+//! other end.
+//!
+//! The server is a [`Tty`] itself, the process owning it keeps using it as the
+//! console. Clients attach read-only or read-write. There's one writer at a
+//! time: the local side by default, a read-write client can attach only after
+//! [`ConsoleServer::set_local_writer`] gives it up.
+//!
+//! The protocol is plain: the client sends `ro\n` or `rw\n`, the server
+//! replies `ok\n` or `error: <reason>\n`, then the raw bytes flow both ways.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use tester::cli::console_server::{ConsoleClient, ConsoleServer};
+//! # use tester::cli::shell::Shell;
+//! # use tester::cli::tty::Tty;
+//! let s = Shell::build(Some("bash"))?;
+//! let mut server = ConsoleServer::build(Box::new(s), "tester-doc-console")?;
+//! let mut watcher = ConsoleClient::connect("tester-doc-console", false)?;
+//! server.write(b"echo hello\n")?;
+//! std::thread::sleep(std::time::Duration::from_millis(500));
+//! assert!(watcher.write(b"reboot\n").is_err());
+//! let _ = server.exit();
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::{
+    error::Error,
+    io::{ErrorKind, Read, Write},
+    sync::{Arc, Mutex},
+    thread::{sleep, spawn, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use interprocess::local_socket::{
+    prelude::*, traits::ListenerExt, GenericNamespaced, Listener, ListenerNonblockingMode,
+    ListenerOptions, Stream, ToNsName,
+};
+
+use crate::{consts::SHELL_DURATION, err, impl_any, info, log};
+
+use super::{
+    mux::{Mux, MuxReader, OverflowPolicy},
+    tty::{DynTty, Tty},
+    tunnel::write_all,
+};
+
+/// How much the local side can fall behind before it blocks the console
+const LOCAL_CAPACITY: usize = 16 << 20;
+
+/// How much a client can fall behind, older output is dropped for it
+const CLIENT_CAPACITY: usize = 1 << 20;
+
+/// How long to wait for the handshake of a client
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn read_handshake(stream: &mut Stream) -> Result<String, Box<dyn Error>> {
+    let begin = Instant::now();
+    let mut line = Vec::new();
+    let mut buf = [0u8];
+    while !line.ends_with(b"\n") {
+        match stream.read(&mut buf) {
+            Ok(0) => return Err("Connection closed during the handshake".into()),
+            Ok(_) => line.push(buf[0]),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                if begin.elapsed() > HANDSHAKE_TIMEOUT {
+                    return Err("Handshake timeout".into());
+                }
+                sleep(Duration::from_millis(SHELL_DURATION));
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(Box::new(e)),
+        }
+    }
+    Ok(String::from_utf8_lossy(&line).trim().to_owned())
+}
+
+/// Serve one client until it goes away or the server stops
+fn serve(mut stream: Stream, mux: &Mux, stop: &Arc<Mutex<bool>>) -> Result<(), Box<dyn Error>> {
+    let mode = read_handshake(&mut stream)?;
+    let mut reader = mux.reader(CLIENT_CAPACITY, OverflowPolicy::DropOldest);
+    let writable = match mode.as_str() {
+        "ro" => false,
+        "rw" => match reader.claim_writer() {
+            Ok(_) => true,
+            Err(_) => {
+                write_all(&mut stream, b"error: the console has a writer already\n")?;
+                return Err("Refused a read-write client, the console has a writer".into());
+            }
+        },
+        _ => {
+            write_all(&mut stream, b"error: unknown mode\n")?;
+            return Err(format!("Unknown client mode {{{}}}", mode).into());
+        }
+    };
+    write_all(&mut stream, b"ok\n")?;
+    info!(
+        "Console client attached, {}",
+        if writable { "read-write" } else { "read-only" }
+    );
+
+    let mut buf = [0u8; 4096];
+    loop {
+        if *stop.lock().unwrap() {
+            return Ok(());
+        }
+        let mut idle = true;
+
+        let data = reader.read()?;
+        if !data.is_empty() {
+            write_all(&mut stream, &data)?;
+            idle = false;
+        }
+
+        match stream.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(sz) => {
+                // Input of read-only clients is dropped
+                if writable {
+                    reader.write(&buf[..sz])?;
+                }
+                idle = false;
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(Box::new(e)),
+        }
+
+        if idle {
+            sleep(Duration::from_millis(SHELL_DURATION));
+        }
+    }
+}
+
+pub struct ConsoleServer {
+    mux: Arc<Mux>,
+    local: MuxReader,
+    stop: Arc<Mutex<bool>>,
+    clients: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl ConsoleServer {
+    /// Build a new [`ConsoleServer`] instance and start serving.
+    ///
+    /// # Arguments
+    ///
+    /// - `inner`: The backing Tty, e.g. a serial port.
+    /// - `name`: The name of the local socket, clients connect with it.
+    pub fn build(inner: DynTty, name: &str) -> Result<ConsoleServer, Box<dyn Error>> {
+        let listener = ConsoleServer::listen(name)?;
+        Ok(ConsoleServer::serve(inner, listener))
+    }
+
+    /// Create the local socket `name`, for [`ConsoleServer::serve`].
+    ///
+    /// It's split from the serving, so a name in use doesn't cost the console.
+    pub fn listen(name: &str) -> Result<Listener, Box<dyn Error>> {
+        let sock_name = name.to_ns_name::<GenericNamespaced>()?;
+        let listener: Listener = ListenerOptions::new()
+            .name(sock_name)
+            .nonblocking(ListenerNonblockingMode::Both)
+            .create_sync()?;
+        info!("Serving the console on {}", name);
+        Ok(listener)
+    }
+
+    /// Start serving `inner` on a socket created by [`ConsoleServer::listen`].
+    pub fn serve(inner: DynTty, listener: Listener) -> ConsoleServer {
+        let mux = Arc::new(Mux::build(inner));
+        let mut local = mux.reader(LOCAL_CAPACITY, OverflowPolicy::Block);
+        // Nobody else has claimed it on a new Mux
+        let _ = local.claim_writer();
+
+        let stop = Arc::new(Mutex::new(false));
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let mux_clone = mux.clone();
+        let stop_clone = stop.clone();
+        let clients_clone = clients.clone();
+        let handle = spawn(move || loop {
+            if *stop_clone.lock().unwrap() {
+                break;
+            }
+            match listener.incoming().next() {
+                Some(Ok(stream)) => {
+                    log!("Console client connected");
+                    let mux = mux_clone.clone();
+                    let stop = stop_clone.clone();
+                    let client = spawn(move || {
+                        if let Err(e) = serve(stream, &mux, &stop) {
+                            err!("Console client failed. Reason: {}", e);
+                        }
+                        log!("Console client detached");
+                    });
+                    let mut clients = clients_clone.lock().unwrap();
+                    clients.retain(|c: &JoinHandle<()>| !c.is_finished());
+                    clients.push(client);
+                }
+                Some(Err(e)) if e.kind() == ErrorKind::WouldBlock => {
+                    sleep(Duration::from_millis(SHELL_DURATION));
+                }
+                Some(Err(e)) => {
+                    err!("Accept console client failed. Reason: {}", e);
+                    break;
+                }
+                None => break,
+            }
+        });
+
+        ConsoleServer {
+            mux,
+            local,
+            stop,
+            clients,
+            handle: Some(handle),
+        }
+    }
+
+    /// Whether the local side writes, give it up to let a read-write client attach.
+    pub fn set_local_writer(&mut self, write: bool) -> Result<(), Box<dyn Error>> {
+        if write {
+            self.local.claim_writer()
+        } else {
+            self.local.release_writer();
+            Ok(())
+        }
+    }
+
+    /// How many clients are attached
+    pub fn clients(&self) -> usize {
+        let clients = self.clients.lock().unwrap();
+        clients.iter().filter(|c| !c.is_finished()).count()
+    }
+
+    fn shutdown(&mut self) {
+        *self.stop.lock().unwrap() = true;
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+        for client in self.clients.lock().unwrap().drain(..) {
+            let _ = client.join();
+        }
+    }
+
+    /// Stop serving, detach all clients and take the backing Tty back.
+    pub fn exit(mut self) -> DynTty {
+        self.shutdown();
+        let mux = self.mux.clone();
+        drop(self);
+        match Arc::try_unwrap(mux) {
+            Ok(mux) => mux.exit(),
+            Err(_) => unreachable!("The clients are all joined"),
+        }
+    }
+}
+
+impl Drop for ConsoleServer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+impl_any!(ConsoleServer);
+
+impl Tty for ConsoleServer {
+    fn read(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.local.read()
+    }
+
+    fn read_line(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.local.read_line()
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.local.write(data)
+    }
+}
+
+/// A [`Tty`] attached to a [`ConsoleServer`]
+pub struct ConsoleClient {
+    stream: Stream,
+    writable: bool,
+}
+
+impl ConsoleClient {
+    /// Attach to the [`ConsoleServer`] serving on `name`.
+    ///
+    /// A read-write client is refused if the console has a writer already.
+    pub fn connect(name: &str, read_write: bool) -> Result<ConsoleClient, Box<dyn Error>> {
+        let sock_name = name.to_ns_name::<GenericNamespaced>()?;
+        let mut stream = Stream::connect(sock_name)?;
+        stream.write_all(if read_write { b"rw\n" } else { b"ro\n" })?;
+
+        let mut reply = Vec::new();
+        let mut buf = [0u8];
+        while !reply.ends_with(b"\n") {
+            if stream.read(&mut buf)? == 0 {
+                return Err("The console server closed the connection".into());
+            }
+            reply.push(buf[0]);
+        }
+        let reply = String::from_utf8_lossy(&reply).trim().to_owned();
+        if reply != "ok" {
+            err!("Attach to console {} failed: {}", name, reply);
+            return Err(reply.into());
+        }
+        stream.set_nonblocking(true)?;
+
+        info!(
+            "Attached to console {}, {}",
+            name,
+            if read_write {
+                "read-write"
+            } else {
+                "read-only"
+            }
+        );
+        Ok(ConsoleClient {
+            stream,
+            writable: read_write,
+        })
+    }
+}
+
+impl_any!(ConsoleClient);
+
+impl Tty for ConsoleClient {
+    fn read(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut res = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) if res.is_empty() => return Err("The console server has gone".into()),
+                Ok(0) => return Ok(res),
+                Ok(sz) => res.extend_from_slice(&buf[..sz]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(res),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(Box::new(e)),
+            }
+        }
+    }
+
+    fn read_line(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut res = Vec::new();
+        let mut buf = [0u8];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Err("The console server has gone".into()),
+                Ok(_) => {
+                    res.push(buf[0]);
+                    if buf[0] == b'\n' {
+                        return Ok(res);
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    sleep(Duration::from_millis(SHELL_DURATION))
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(Box::new(e)),
+            }
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        if !self.writable {
+            return Err("This console client is read-only".into());
+        }
+        write_all(&mut self.stream, data)?;
+        Ok(())
+    }
+}
@@ -1,20 +1,214 @@
 //! [`DeANSI`] is a wrapper for [`Tty`] that removes ANSI escape sequences from the input and output.
+//!
+//! The parser state is kept between reads, so a sequence cut off at the end of
+//! one chunk is still removed when the rest comes in the next one.
 
-use std::error::Error;
+use std::{error::Error, mem};
+
+use vte::{Params, Parser, Perform};
 
 use crate::{impl_any, vendor::strip_ansi_escapes};
 
 use super::tty::{DynTty, Tty, WrapperTty};
 
+/// The state of the filter, fed by the [`Parser`]
+#[derive(Default)]
+struct Filter {
+    keep_sgr: bool,
+    cursor_edit: bool,
+    cr_overwrite: bool,
+    /// The filtered output, not returned yet
+    out: Vec<u8>,
+    /// The current line in line edit mode, each char with the SGR sequences before it
+    line: Vec<(String, char)>,
+    cursor: usize,
+    /// How many chars of `line` are already in `out`
+    flushed: usize,
+    /// SGR sequences waiting for the next char
+    sgr: String,
+}
+
+impl Filter {
+    fn line_mode(&self) -> bool {
+        self.cursor_edit || self.cr_overwrite
+    }
+
+    fn emit_line(&mut self) {
+        let start = self.flushed.min(self.line.len());
+        for (sgr, c) in &self.line[start..] {
+            self.out.extend_from_slice(sgr.as_bytes());
+            let mut buf = [0; 4];
+            self.out
+                .extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+        }
+        self.flushed = self.line.len();
+    }
+
+    /// Return the unfinished line, e.g. a prompt. It can't be edited afterwards.
+    fn flush(&mut self) {
+        if self.line_mode() {
+            self.emit_line();
+        }
+    }
+
+    fn newline(&mut self) {
+        if self.line_mode() {
+            self.emit_line();
+            self.out
+                .extend_from_slice(mem::take(&mut self.sgr).as_bytes());
+            self.line.clear();
+            self.cursor = 0;
+            self.flushed = 0;
+        }
+        self.out.push(b'\n');
+    }
+
+    fn pad_to(&mut self, len: usize) {
+        while self.line.len() < len {
+            self.line.push((String::new(), ' '));
+        }
+    }
+
+    fn blank(&mut self, from: usize, to: usize) {
+        let to = to.min(self.line.len());
+        for cell in self.line.iter_mut().take(to).skip(from) {
+            *cell = (String::new(), ' ');
+        }
+    }
+
+    fn edit(&mut self, params: &Params, action: char) {
+        let arg = |default: usize| {
+            params
+                .iter()
+                .next()
+                .and_then(|p| p.first())
+                .map(|&v| v as usize)
+                .filter(|&v| v != 0)
+                .unwrap_or(default)
+        };
+        match action {
+            'C' => self.cursor += arg(1),
+            'D' => self.cursor = self.cursor.saturating_sub(arg(1)),
+            'G' | '`' => self.cursor = arg(1) - 1,
+            'K' => match params.iter().next().and_then(|p| p.first()).unwrap_or(&0) {
+                0 => self.line.truncate(self.cursor),
+                1 => self.blank(0, self.cursor + 1),
+                _ => self.blank(0, self.line.len()),
+            },
+            'X' => self.blank(self.cursor, self.cursor + arg(1)),
+            'P' => {
+                let end = (self.cursor + arg(1)).min(self.line.len());
+                if self.cursor < end {
+                    self.line.drain(self.cursor..end);
+                }
+            }
+            '@' if self.cursor < self.line.len() => {
+                for _ in 0..arg(1) {
+                    self.line.insert(self.cursor, (String::new(), ' '));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Perform for Filter {
+    fn print(&mut self, c: char) {
+        if self.line_mode() {
+            self.pad_to(self.cursor);
+            let cell = (mem::take(&mut self.sgr), c);
+            if self.cursor < self.line.len() {
+                self.line[self.cursor] = cell;
+            } else {
+                self.line.push(cell);
+            }
+            self.cursor += 1;
+        } else {
+            let mut buf = [0; 4];
+            self.out
+                .extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+        }
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            b'\n' => self.newline(),
+            b'\r' if self.cr_overwrite => self.cursor = 0,
+            0x08 if self.cursor_edit => self.cursor = self.cursor.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], ignore: bool, action: char) {
+        if ignore || !intermediates.is_empty() {
+            return;
+        }
+        if action == 'm' && self.keep_sgr {
+            let params: Vec<String> = params
+                .iter()
+                .map(|p| {
+                    p.iter()
+                        .map(|v| v.to_string())
+                        .collect::<Vec<_>>()
+                        .join(":")
+                })
+                .collect();
+            let sgr = format!("\x1b[{}m", params.join(";"));
+            if self.line_mode() {
+                self.sgr += &sgr;
+            } else {
+                self.out.extend_from_slice(sgr.as_bytes());
+            }
+        } else if self.cursor_edit {
+            self.edit(params, action);
+        }
+    }
+}
+
 /// A wrapper for [`Tty`] that removes ANSI escape sequences from the input and output.
+///
+/// By default only the text and newlines are kept. Optionally:
+/// - `keep_sgr`: keep the color and style sequences (`ESC [ ... m`).
+/// - `cursor_edit`: apply cursor movement and erasing inside a line (e.g. `ESC [ K`
+///   and backspace) to the text, instead of dropping them.
+/// - `cr_overwrite`: a `\r` moves back to the start of the line, so the following
+///   text overwrites it, like progress bars on a real terminal.
+///
+/// With `cursor_edit` or `cr_overwrite`, a line is returned once it ends, or when
+/// the inner [`Tty`] has nothing more to read. Edits to the part already returned
+/// are lost.
 pub struct DeANSI {
     inner: DynTty,
+    parser: Parser,
+    filter: Filter,
 }
 
 impl DeANSI {
     /// Build a new [`DeANSI`] instance.
     pub fn build(inner: DynTty) -> DeANSI {
-        DeANSI { inner }
+        DeANSI {
+            inner,
+            parser: Parser::new(),
+            filter: Filter::default(),
+        }
+    }
+
+    pub fn set_keep_sgr(&mut self, keep: bool) {
+        self.filter.keep_sgr = keep;
+    }
+
+    pub fn set_cursor_edit(&mut self, enable: bool) {
+        self.filter.cursor_edit = enable;
+    }
+
+    pub fn set_cr_overwrite(&mut self, enable: bool) {
+        self.filter.cr_overwrite = enable;
+    }
+
+    fn process(&mut self, data: &[u8]) {
+        for &b in data {
+            self.parser.advance(&mut self.filter, b);
+        }
     }
 }
 
@@ -23,23 +217,21 @@ impl_any!(DeANSI);
 impl Tty for DeANSI {
     /// Read data from the Tty
     ///
-    /// Due to the escape sequences may be cut off in the middle of the buffer,
-    /// read the buffer in line is needed.
+    /// Incomplete escape sequences are kept in the parser until the rest is read.
     fn read(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
-        // Due to the escape sequences may be cut off in the middle of the buffer,
-        // read the buffer in line is needed.
-        let data = self.inner.read_line()?;
-        let data = strip_ansi_escapes::strip(&data);
-        Ok(data)
+        let data = self.inner.read()?;
+        self.process(&data);
+        if data.is_empty() {
+            self.filter.flush();
+        }
+        Ok(mem::take(&mut self.filter.out))
     }
 
     /// Read a line from the Tty (terminated by a `\n`)
-    ///
-    /// In DeANSI, the `read_line` and `read` are the same.
     fn read_line(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
         let data = self.inner.read_line()?;
-        let data = strip_ansi_escapes::strip(&data);
-        Ok(data)
+        self.process(&data);
+        Ok(mem::take(&mut self.filter.out))
     }
 
     /// Write data to the Tty
@@ -0,0 +1,852 @@
+//! A small VT100/xterm terminal emulator, keeping a grid of cells.
+//!
+//! It understands what full-screen programs like installers, `menuconfig`
+//! and `htop` use: cursor movement, erasing, scroll regions, insert/delete,
+//! SGR attributes, the alternate screen and the DEC line drawing charset.
+//! Wide characters are treated as one column.
+//!
+//! This is not a [`super::tty::Tty`], look at [`super::vterm::VTerm`] for
+//! the wrapper.
+
+use vte::{Params, Parser, Perform};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Color {
+    #[default]
+    Default,
+    /// The 256 colors palette, 0-15 are the ANSI colors
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct CellAttr {
+    pub fg: Color,
+    pub bg: Color,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub blink: bool,
+    pub inverse: bool,
+    pub hidden: bool,
+    pub strike: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Cell {
+    pub c: char,
+    pub attr: CellAttr,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell {
+            c: ' ',
+            attr: CellAttr::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Charset {
+    Ascii,
+    LineDrawing,
+}
+
+/// Map the DEC special graphics charset to unicode
+fn line_drawing(c: char) -> char {
+    match c {
+        '`' => '◆',
+        'a' => '▒',
+        'f' => '°',
+        'g' => '±',
+        'j' => '┘',
+        'k' => '┐',
+        'l' => '┌',
+        'm' => '└',
+        'n' => '┼',
+        'o' => '⎺',
+        'p' => '⎻',
+        'q' => '─',
+        'r' => '⎼',
+        's' => '⎽',
+        't' => '├',
+        'u' => '┤',
+        'v' => '┴',
+        'w' => '┬',
+        'x' => '│',
+        'y' => '≤',
+        'z' => '≥',
+        '{' => 'π',
+        '|' => '≠',
+        '}' => '£',
+        '~' => '·',
+        _ => c,
+    }
+}
+
+/// The mouse reporting asked by the program
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct MouseMode {
+    /// Report button presses, mode 1000 and up
+    pub tracking: bool,
+    /// Use the SGR encoding, mode 1006
+    pub sgr: bool,
+}
+
+#[derive(Clone, Copy)]
+struct SavedCursor {
+    row: usize,
+    col: usize,
+    attr: CellAttr,
+}
+
+/// The state of the screen, driven by the [`Parser`]
+struct Grid {
+    cols: usize,
+    rows: usize,
+    cells: Vec<Vec<Cell>>,
+    /// The main screen, saved while the alternate screen is in use
+    main: Option<Vec<Vec<Cell>>>,
+    row: usize,
+    col: usize,
+    attr: CellAttr,
+    saved: SavedCursor,
+    top: usize,
+    bottom: usize,
+    /// The cursor is past the last column, the next char wraps
+    wrap_pending: bool,
+    autowrap: bool,
+    cursor_visible: bool,
+    /// DECCKM, cursor keys send `ESC O` instead of `ESC [`
+    app_cursor: bool,
+    mouse: MouseMode,
+    charsets: [Charset; 2],
+    active_charset: usize,
+    title: String,
+    replies: Vec<u8>,
+}
+
+impl Grid {
+    fn new(cols: usize, rows: usize) -> Grid {
+        let cols = cols.max(1);
+        let rows = rows.max(1);
+        Grid {
+            cols,
+            rows,
+            cells: vec![vec![Cell::default(); cols]; rows],
+            main: None,
+            row: 0,
+            col: 0,
+            attr: CellAttr::default(),
+            saved: SavedCursor {
+                row: 0,
+                col: 0,
+                attr: CellAttr::default(),
+            },
+            top: 0,
+            bottom: rows - 1,
+            wrap_pending: false,
+            autowrap: true,
+            cursor_visible: true,
+            app_cursor: false,
+            mouse: MouseMode::default(),
+            charsets: [Charset::Ascii; 2],
+            active_charset: 0,
+            title: String::new(),
+            replies: Vec::new(),
+        }
+    }
+
+    /// An empty cell, keeping the current background like xterm does
+    fn blank(&self) -> Cell {
+        Cell {
+            c: ' ',
+            attr: CellAttr {
+                bg: self.attr.bg,
+                ..CellAttr::default()
+            },
+        }
+    }
+
+    fn blank_line(&self) -> Vec<Cell> {
+        vec![self.blank(); self.cols]
+    }
+
+    fn goto(&mut self, row: usize, col: usize) {
+        self.row = row.min(self.rows - 1);
+        self.col = col.min(self.cols - 1);
+        self.wrap_pending = false;
+    }
+
+    fn scroll_up(&mut self, n: usize) {
+        let n = n.min(self.bottom + 1 - self.top);
+        for _ in 0..n {
+            self.cells.remove(self.top);
+            let line = self.blank_line();
+            self.cells.insert(self.bottom, line);
+        }
+    }
+
+    fn scroll_down(&mut self, n: usize) {
+        let n = n.min(self.bottom + 1 - self.top);
+        for _ in 0..n {
+            self.cells.remove(self.bottom);
+            let line = self.blank_line();
+            self.cells.insert(self.top, line);
+        }
+    }
+
+    fn linefeed(&mut self) {
+        self.wrap_pending = false;
+        if self.row == self.bottom {
+            self.scroll_up(1);
+        } else if self.row < self.rows - 1 {
+            self.row += 1;
+        }
+    }
+
+    fn reverse_index(&mut self) {
+        self.wrap_pending = false;
+        if self.row == self.top {
+            self.scroll_down(1);
+        } else {
+            self.row = self.row.saturating_sub(1);
+        }
+    }
+
+    fn erase(&mut self, row: usize, cols: std::ops::Range<usize>) {
+        let blank = self.blank();
+        let end = cols.end.min(self.cols);
+        for cell in &mut self.cells[row][cols.start.min(end)..end] {
+            *cell = blank;
+        }
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved = SavedCursor {
+            row: self.row,
+            col: self.col,
+            attr: self.attr,
+        };
+    }
+
+    fn restore_cursor(&mut self) {
+        let saved = self.saved;
+        self.attr = saved.attr;
+        self.goto(saved.row, saved.col);
+    }
+
+    fn set_alt_screen(&mut self, on: bool) {
+        if on && self.main.is_none() {
+            let alt = vec![vec![Cell::default(); self.cols]; self.rows];
+            self.main = Some(std::mem::replace(&mut self.cells, alt));
+        } else if !on {
+            if let Some(main) = self.main.take() {
+                self.cells = main;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        let replies = std::mem::take(&mut self.replies);
+        *self = Grid::new(self.cols, self.rows);
+        self.replies = replies;
+    }
+
+    fn resize(&mut self, cols: usize, rows: usize) {
+        let cols = cols.max(1);
+        let rows = rows.max(1);
+        let fit = |cells: &mut Vec<Vec<Cell>>| {
+            for line in cells.iter_mut() {
+                line.resize(cols, Cell::default());
+            }
+            cells.resize(rows, vec![Cell::default(); cols]);
+        };
+        // Keep the cursor line on the screen when shrinking
+        if self.row >= rows {
+            let n = self.row + 1 - rows;
+            self.cells.drain(..n);
+            self.row -= n;
+        }
+        fit(&mut self.cells);
+        if let Some(main) = self.main.as_mut() {
+            fit(main);
+        }
+        self.cols = cols;
+        self.rows = rows;
+        self.top = 0;
+        self.bottom = rows - 1;
+        self.goto(self.row, self.col);
+    }
+
+    fn sgr(&mut self, params: &Params) {
+        let params: Vec<&[u16]> = params.iter().collect();
+        if params.is_empty() {
+            self.attr = CellAttr::default();
+            return;
+        }
+        let mut i = 0;
+        while i < params.len() {
+            let p = params[i];
+            match p[0] {
+                0 => self.attr = CellAttr::default(),
+                1 => self.attr.bold = true,
+                2 => self.attr.dim = true,
+                3 => self.attr.italic = true,
+                4 => self.attr.underline = true,
+                5 | 6 => self.attr.blink = true,
+                7 => self.attr.inverse = true,
+                8 => self.attr.hidden = true,
+                9 => self.attr.strike = true,
+                21 | 22 => {
+                    self.attr.bold = false;
+                    self.attr.dim = false;
+                }
+                23 => self.attr.italic = false,
+                24 => self.attr.underline = false,
+                25 => self.attr.blink = false,
+                27 => self.attr.inverse = false,
+                28 => self.attr.hidden = false,
+                29 => self.attr.strike = false,
+                n @ 30..=37 => self.attr.fg = Color::Indexed((n - 30) as u8),
+                39 => self.attr.fg = Color::Default,
+                n @ 40..=47 => self.attr.bg = Color::Indexed((n - 40) as u8),
+                49 => self.attr.bg = Color::Default,
+                n @ 90..=97 => self.attr.fg = Color::Indexed((n - 90 + 8) as u8),
+                n @ 100..=107 => self.attr.bg = Color::Indexed((n - 100 + 8) as u8),
+                n @ (38 | 48) => {
+                    // Either `38:5:n` in one param, or `38;5;n` in several
+                    let (color, used) = if p.len() > 1 {
+                        (extended_color(&p[1..]), 0)
+                    } else {
+                        let rest: Vec<u16> = params[i + 1..].iter().map(|x| x[0]).collect();
+                        let color = extended_color(&rest);
+                        let used = match rest.first() {
+                            Some(5) => 2,
+                            Some(2) => 4,
+                            _ => 0,
+                        };
+                        (color, used)
+                    };
+                    if let Some(color) = color {
+                        if n == 38 {
+                            self.attr.fg = color;
+                        } else {
+                            self.attr.bg = color;
+                        }
+                    }
+                    i += used;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+}
+
+/// Parse the `5;n` or `2;r;g;b` after 38/48. The colon form may have a colorspace before rgb.
+fn extended_color(p: &[u16]) -> Option<Color> {
+    match p {
+        [5, n, ..] => Some(Color::Indexed(*n as u8)),
+        [2, _, r, g, b] => Some(Color::Rgb(*r as u8, *g as u8, *b as u8)),
+        [2, r, g, b, ..] => Some(Color::Rgb(*r as u8, *g as u8, *b as u8)),
+        _ => None,
+    }
+}
+
+impl Perform for Grid {
+    fn print(&mut self, c: char) {
+        let c = match self.charsets[self.active_charset] {
+            Charset::LineDrawing => line_drawing(c),
+            Charset::Ascii => c,
+        };
+        if self.wrap_pending && self.autowrap {
+            self.col = 0;
+            self.linefeed();
+        }
+        self.cells[self.row][self.col] = Cell { c, attr: self.attr };
+        if self.col + 1 >= self.cols {
+            self.wrap_pending = true;
+        } else {
+            self.col += 1;
+        }
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            0x08 => {
+                self.wrap_pending = false;
+                self.col = self.col.saturating_sub(1);
+            }
+            0x09 => {
+                self.col = ((self.col / 8 + 1) * 8).min(self.cols - 1);
+            }
+            0x0a..=0x0c => self.linefeed(),
+            0x0d => {
+                self.wrap_pending = false;
+                self.col = 0;
+            }
+            0x0e => self.active_charset = 1,
+            0x0f => self.active_charset = 0,
+            _ => {}
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], ignore: bool, action: char) {
+        if ignore {
+            return;
+        }
+        let args: Vec<u16> = params.iter().map(|p| p[0]).collect();
+        // Missing or 0 means the default
+        let arg = |i: usize, default: usize| match args.get(i) {
+            Some(&n) if n != 0 => n as usize,
+            _ => default,
+        };
+        let private = intermediates.first() == Some(&b'?');
+        let (row, col) = (self.row, self.col);
+
+        match (action, private) {
+            ('A', false) => {
+                let min = if row >= self.top { self.top } else { 0 };
+                self.goto(row.saturating_sub(arg(0, 1)).max(min), col);
+            }
+            ('B' | 'e', false) => {
+                let max = if row <= self.bottom {
+                    self.bottom
+                } else {
+                    self.rows - 1
+                };
+                self.goto((row + arg(0, 1)).min(max), col);
+            }
+            ('C' | 'a', false) => self.goto(row, col + arg(0, 1)),
+            ('D', false) => self.goto(row, col.saturating_sub(arg(0, 1))),
+            ('E', false) => self.goto(row + arg(0, 1), 0),
+            ('F', false) => self.goto(row.saturating_sub(arg(0, 1)), 0),
+            ('G' | '`', false) => self.goto(row, arg(0, 1) - 1),
+            ('H' | 'f', false) => self.goto(arg(0, 1) - 1, arg(1, 1) - 1),
+            ('d', false) => self.goto(arg(0, 1) - 1, col),
+            ('J', _) => {
+                let rows = self.rows;
+                match arg(0, 0) {
+                    0 => {
+                        self.erase(row, col..self.cols);
+                        (row + 1..rows).for_each(|r| self.erase(r, 0..self.cols));
+                    }
+                    1 => {
+                        (0..row).for_each(|r| self.erase(r, 0..self.cols));
+                        self.erase(row, 0..col + 1);
+                    }
+                    _ => (0..rows).for_each(|r| self.erase(r, 0..self.cols)),
+                }
+            }
+            ('K', _) => match arg(0, 0) {
+                0 => self.erase(row, col..self.cols),
+                1 => self.erase(row, 0..col + 1),
+                _ => self.erase(row, 0..self.cols),
+            },
+            ('L', false) if (self.top..=self.bottom).contains(&row) => {
+                for _ in 0..arg(0, 1).min(self.bottom + 1 - row) {
+                    self.cells.remove(self.bottom);
+                    let line = self.blank_line();
+                    self.cells.insert(row, line);
+                }
+                self.goto(row, 0);
+            }
+            ('M', false) if (self.top..=self.bottom).contains(&row) => {
+                for _ in 0..arg(0, 1).min(self.bottom + 1 - row) {
+                    self.cells.remove(row);
+                    let line = self.blank_line();
+                    self.cells.insert(self.bottom, line);
+                }
+                self.goto(row, 0);
+            }
+            ('@', false) => {
+                let blank = self.blank();
+                let line = &mut self.cells[row];
+                for _ in 0..arg(0, 1).min(self.cols - col) {
+                    line.pop();
+                    line.insert(col, blank);
+                }
+            }
+            ('P', false) => {
+                let blank = self.blank();
+                let line = &mut self.cells[row];
+                for _ in 0..arg(0, 1).min(self.cols - col) {
+                    line.remove(col);
+                    line.push(blank);
+                }
+            }
+            ('X', false) => self.erase(row, col..col + arg(0, 1)),
+            ('S', false) => self.scroll_up(arg(0, 1)),
+            ('T', false) => self.scroll_down(arg(0, 1)),
+            ('r', false) => {
+                let top = arg(0, 1) - 1;
+                let bottom = arg(1, self.rows).min(self.rows) - 1;
+                if top < bottom {
+                    self.top = top;
+                    self.bottom = bottom;
+                    self.goto(0, 0);
+                }
+            }
+            ('m', false) => self.sgr(params),
+            ('s', false) => self.save_cursor(),
+            ('u', false) => self.restore_cursor(),
+            ('h' | 'l', true) => {
+                let on = action == 'h';
+                for mode in &args {
+                    match mode {
+                        1 => self.app_cursor = on,
+                        7 => self.autowrap = on,
+                        25 => self.cursor_visible = on,
+                        1000 | 1002 | 1003 => self.mouse.tracking = on,
+                        1006 => self.mouse.sgr = on,
+                        47 | 1047 => self.set_alt_screen(on),
+                        1049 => {
+                            if on {
+                                self.save_cursor();
+                                self.set_alt_screen(true);
+                            } else {
+                                self.set_alt_screen(false);
+                                self.restore_cursor();
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            ('n', false) => match arg(0, 0) {
+                5 => self.replies.extend_from_slice(b"\x1b[0n"),
+                6 => self
+                    .replies
+                    .extend(format!("\x1b[{};{}R", row + 1, col + 1).into_bytes()),
+                _ => {}
+            },
+            ('c', false) if intermediates.is_empty() => {
+                self.replies.extend_from_slice(b"\x1b[?1;2c");
+            }
+            _ => {}
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], ignore: bool, byte: u8) {
+        if ignore {
+            return;
+        }
+        match (intermediates, byte) {
+            ([], b'7') => self.save_cursor(),
+            ([], b'8') => self.restore_cursor(),
+            ([], b'D') => self.linefeed(),
+            ([], b'E') => {
+                self.col = 0;
+                self.linefeed();
+            }
+            ([], b'M') => self.reverse_index(),
+            ([], b'c') => self.reset(),
+            ([b'('], b'0') => self.charsets[0] = Charset::LineDrawing,
+            ([b'('], _) => self.charsets[0] = Charset::Ascii,
+            ([b')'], b'0') => self.charsets[1] = Charset::LineDrawing,
+            ([b')'], _) => self.charsets[1] = Charset::Ascii,
+            _ => {}
+        }
+    }
+
+    fn osc_dispatch(&mut self, params: &[&[u8]], _bell_terminated: bool) {
+        if let [b"0" | b"2", title, ..] = params {
+            self.title = String::from_utf8_lossy(title).to_string();
+        }
+    }
+}
+
+/// A terminal emulator, feed it with the output of the terminal.
+pub struct Emulator {
+    parser: Parser,
+    grid: Grid,
+}
+
+impl Emulator {
+    pub fn new(cols: usize, rows: usize) -> Emulator {
+        Emulator {
+            parser: Parser::new(),
+            grid: Grid::new(cols, rows),
+        }
+    }
+
+    /// Process the output of the terminal. Escape sequences may be split across calls.
+    pub fn feed(&mut self, data: &[u8]) {
+        for byte in data {
+            self.parser.advance(&mut self.grid, *byte);
+        }
+    }
+
+    /// Take the answers to the terminal queries, e.g. the cursor position report.
+    ///
+    /// They should be written back to the terminal, some programs wait for them.
+    pub fn take_replies(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.grid.replies)
+    }
+
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        self.grid.resize(cols, rows);
+    }
+
+    /// `(cols, rows)`
+    pub fn size(&self) -> (usize, usize) {
+        (self.grid.cols, self.grid.rows)
+    }
+
+    /// `(row, col)`, both from 0
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.grid.row, self.grid.col)
+    }
+
+    pub fn cursor_visible(&self) -> bool {
+        self.grid.cursor_visible
+    }
+
+    /// Whether the cursor keys should be sent in the application mode (`ESC O A`)
+    pub fn app_cursor(&self) -> bool {
+        self.grid.app_cursor
+    }
+
+    pub fn mouse_mode(&self) -> MouseMode {
+        self.grid.mouse
+    }
+
+    /// Whether the alternate screen (used by full-screen programs) is shown
+    pub fn is_alt_screen(&self) -> bool {
+        self.grid.main.is_some()
+    }
+
+    /// The title set by `OSC 0` or `OSC 2`
+    pub fn title(&self) -> &str {
+        &self.grid.title
+    }
+
+    pub fn cell(&self, row: usize, col: usize) -> Option<&Cell> {
+        self.grid.cells.get(row).and_then(|line| line.get(col))
+    }
+
+    /// All the lines of the screen
+    pub fn lines(&self) -> &[Vec<Cell>] {
+        &self.grid.cells
+    }
+
+    /// The text of a row, without the trailing spaces
+    pub fn row_text(&self, row: usize) -> String {
+        self.grid
+            .cells
+            .get(row)
+            .map(|line| line.iter().map(|c| c.c).collect::<String>())
+            .unwrap_or_default()
+            .trim_end()
+            .to_owned()
+    }
+
+    /// `len` chars from `(row, col)`, not wrapping to the next row
+    pub fn text_at(&self, row: usize, col: usize, len: usize) -> String {
+        self.grid
+            .cells
+            .get(row)
+            .map(|line| line.iter().skip(col).take(len).map(|c| c.c).collect())
+            .unwrap_or_default()
+    }
+
+    /// The text of the whole screen, one line per row
+    pub fn text(&self) -> String {
+        (0..self.grid.rows)
+            .map(|r| self.row_text(r))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Find `text` on the screen, return `(row, col)` of the first match
+    pub fn find(&self, text: &str) -> Option<(usize, usize)> {
+        (0..self.grid.rows).find_map(|r| {
+            let line: Vec<char> = self.grid.cells[r].iter().map(|c| c.c).collect();
+            let target: Vec<char> = text.chars().collect();
+            if target.is_empty() || target.len() > line.len() {
+                return None;
+            }
+            line.windows(target.len())
+                .position(|w| w == target.as_slice())
+                .map(|c| (r, c))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emulator(cols: usize, rows: usize, data: &[u8]) -> Emulator {
+        let mut emu = Emulator::new(cols, rows);
+        emu.feed(data);
+        emu
+    }
+
+    /// Fill every row with a line of its own digit
+    fn filled(cols: usize, rows: usize) -> Emulator {
+        let mut emu = Emulator::new(cols, rows);
+        for r in 0..rows {
+            let line = r.to_string().repeat(cols);
+            emu.feed(format!("\x1b[{};1H{}", r + 1, line).as_bytes());
+        }
+        emu
+    }
+
+    #[test]
+    fn test_cursor_position() {
+        let mut emu = emulator(20, 5, b"\x1b[3;5Hab");
+        assert_eq!(emu.row_text(2), "    ab");
+        assert_eq!(emu.cursor(), (2, 6));
+
+        // Missing params are 1, out of range is clamped
+        emu.feed(b"\x1b[H");
+        assert_eq!(emu.cursor(), (0, 0));
+        emu.feed(b"\x1b[99;99H");
+        assert_eq!(emu.cursor(), (4, 19));
+        emu.feed(b"\x1b[2;3f\x1b[2A\x1b[4C");
+        assert_eq!(emu.cursor(), (0, 6));
+    }
+
+    #[test]
+    fn test_erase_display() {
+        let mut emu = filled(4, 3);
+        emu.feed(b"\x1b[2;3H\x1b[J");
+        assert_eq!(emu.text(), "0000\n11\n");
+
+        let mut emu = filled(4, 3);
+        emu.feed(b"\x1b[2;3H\x1b[1J");
+        assert_eq!(emu.text(), "\n   1\n2222");
+
+        let mut emu = filled(4, 3);
+        emu.feed(b"\x1b[2;3H\x1b[2J");
+        assert_eq!(emu.text(), "\n\n");
+        // ED doesn't move the cursor
+        assert_eq!(emu.cursor(), (1, 2));
+    }
+
+    #[test]
+    fn test_erase_line() {
+        let emu = emulator(6, 1, b"abcdef\x1b[1;3H\x1b[K");
+        assert_eq!(emu.row_text(0), "ab");
+        let emu = emulator(6, 1, b"abcdef\x1b[1;3H\x1b[1K");
+        assert_eq!(emu.row_text(0), "   def");
+        let emu = emulator(6, 1, b"abcdef\x1b[1;3H\x1b[2K");
+        assert_eq!(emu.row_text(0), "");
+
+        // Erased cells keep the current background
+        let emu = emulator(6, 1, b"abcdef\x1b[44m\x1b[2K");
+        assert_eq!(emu.cell(0, 0).unwrap().attr.bg, Color::Indexed(4));
+        assert!(!emu.cell(0, 0).unwrap().attr.bold);
+    }
+
+    #[test]
+    fn test_scroll_region() {
+        let mut emu = filled(3, 5);
+        // Lines 2-4 scroll, 1 and 5 stay
+        emu.feed(b"\x1b[2;4r");
+        assert_eq!(emu.cursor(), (0, 0));
+        emu.feed(b"\x1b[4;1H\n");
+        assert_eq!(emu.text(), "000\n222\n333\n\n444");
+        assert_eq!(emu.cursor(), (3, 0));
+
+        // Reverse index at the top of the region scrolls down
+        emu.feed(b"\x1b[2;1H\x1bM");
+        assert_eq!(emu.text(), "000\n\n222\n333\n444");
+        assert_eq!(emu.cursor(), (1, 0));
+
+        // Below the region, linefeed stops at the last row without scrolling
+        emu.feed(b"\x1b[5;1H\n");
+        assert_eq!(emu.text(), "000\n\n222\n333\n444");
+        assert_eq!(emu.cursor(), (4, 0));
+
+        // Reset to the full screen
+        emu.feed(b"\x1b[r\x1b[5;1H\n");
+        assert_eq!(emu.text(), "\n222\n333\n444\n");
+    }
+
+    #[test]
+    fn test_wrap() {
+        // The cursor stays on the last column until the next char
+        let mut emu = emulator(5, 2, b"abcde");
+        assert_eq!(emu.cursor(), (0, 4));
+        emu.feed(b"f");
+        assert_eq!(emu.text(), "abcde\nf");
+        assert_eq!(emu.cursor(), (1, 1));
+
+        // CR LF after a full line doesn't leave an empty line
+        let emu = emulator(5, 3, b"abcde\r\nf");
+        assert_eq!(emu.text(), "abcde\nf\n");
+
+        // Wrapping on the last row scrolls
+        let emu = emulator(5, 2, b"\x1b[2;1Habcdefg");
+        assert_eq!(emu.text(), "abcde\nfg");
+
+        // Without autowrap the last column is overwritten
+        let emu = emulator(5, 2, b"\x1b[?7labcdefg");
+        assert_eq!(emu.text(), "abcdg\n");
+    }
+
+    #[test]
+    fn test_alt_screen() {
+        let mut emu = emulator(10, 3, b"main\x1b[2;3H");
+        emu.feed(b"\x1b[?1049h");
+        assert!(emu.is_alt_screen());
+        assert_eq!(emu.text(), "\n\n");
+        emu.feed(b"\x1b[Halt");
+        assert_eq!(emu.row_text(0), "alt");
+
+        emu.feed(b"\x1b[?1049l");
+        assert!(!emu.is_alt_screen());
+        assert_eq!(emu.text(), "main\n\n");
+        assert_eq!(emu.cursor(), (1, 2));
+    }
+
+    #[test]
+    fn test_sgr() {
+        let emu = emulator(
+            10,
+            1,
+            b"\x1b[1;4;31ma\x1b[0mb\x1b[38;5;208mc\x1b[48:2:1:2:3md",
+        );
+        let a = emu.cell(0, 0).unwrap().attr;
+        assert!(a.bold && a.underline);
+        assert_eq!(a.fg, Color::Indexed(1));
+        assert_eq!(emu.cell(0, 1).unwrap().attr, CellAttr::default());
+        assert_eq!(emu.cell(0, 2).unwrap().attr.fg, Color::Indexed(208));
+        let d = emu.cell(0, 3).unwrap().attr;
+        assert_eq!(d.fg, Color::Indexed(208));
+        assert_eq!(d.bg, Color::Rgb(1, 2, 3));
+    }
+
+    #[test]
+    fn test_split_feed() {
+        let data = "\x1b[2;3H\x1b[1;32mok\x1b[0m \x1b]2;tïtle\x07é".as_bytes();
+        let whole = emulator(10, 3, data);
+
+        // Split at every byte, inside the CSI, OSC and the UTF-8 chars
+        let mut emu = Emulator::new(10, 3);
+        for byte in data {
+            emu.feed(&[*byte]);
+        }
+        assert_eq!(emu.text(), whole.text());
+        assert_eq!(emu.text(), "\n  ok é\n");
+        assert_eq!(emu.cursor(), whole.cursor());
+        assert_eq!(emu.lines(), whole.lines());
+        assert_eq!(emu.title(), "tïtle");
+        assert!(emu.cell(1, 2).unwrap().attr.bold);
+    }
+
+    #[test]
+    fn test_replies_and_charset() {
+        let mut emu = emulator(10, 3, b"\x1b[2;3H\x1b[6n\x1b[c");
+        assert_eq!(emu.take_replies(), b"\x1b[2;3R\x1b[?1;2c");
+        assert!(emu.take_replies().is_empty());
+
+        emu.feed(b"\x1b[H\x1b(0lqk\x1b(Bq");
+        assert_eq!(emu.row_text(0), "┌─┐q");
+    }
+}
@@ -0,0 +1,120 @@
+//! Hand the console to a human in the middle of a test.
+//!
+//! [`interact`] puts the local terminal in raw mode and pipes it to a [`Tty`],
+//! like `screen` or `minicom`. Everything goes through the [`Tty`] given, so
+//! the recorders and tees wrapped inside keep recording the session.
+//!
+//! Press the escape key (Ctrl-] by default) then:
+//! - `c` to leave and resume the test
+//! - `q` to leave and abort the test, [`interact`] returns an error
+//! - the escape key again to send it to the console
+
+use std::{
+    error::Error,
+    io::{stdin, stdout, IsTerminal, Read, Write},
+};
+
+use nix::sys::termios::{
+    cfmakeraw, tcgetattr, tcsetattr, SetArg, SpecialCharacterIndices, Termios,
+};
+
+use crate::{info, warn};
+
+use super::tty::Tty;
+
+/// Ctrl-], as in telnet
+pub const DEFAULT_ESCAPE: u8 = 0x1d;
+
+/// Restore the terminal mode when dropped, even on errors
+struct RawMode {
+    orig: Termios,
+}
+
+impl RawMode {
+    fn enter() -> Result<RawMode, Box<dyn Error>> {
+        let orig = tcgetattr(stdin())?;
+        let mut raw = orig.clone();
+        cfmakeraw(&mut raw);
+        // Return from read after 100ms without input, so the output keeps flowing
+        raw.control_chars[SpecialCharacterIndices::VMIN as usize] = 0;
+        raw.control_chars[SpecialCharacterIndices::VTIME as usize] = 1;
+        tcsetattr(stdin(), SetArg::TCSANOW, &raw)?;
+        Ok(RawMode { orig })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = tcsetattr(stdin(), SetArg::TCSANOW, &self.orig);
+    }
+}
+
+/// The name of a control key, e.g. `Ctrl-]`
+fn key_name(key: u8) -> String {
+    if key < 0x20 {
+        format!("Ctrl-{}", (key + 0x40) as char)
+    } else {
+        (key as char).to_string()
+    }
+}
+
+/// Pipe the local terminal to `tty` until the user leaves.
+///
+/// Returns an error if the user aborts, or stdin isn't a terminal.
+pub fn interact(tty: &mut dyn Tty, escape: u8) -> Result<(), Box<dyn Error>> {
+    if !stdin().is_terminal() {
+        return Err("Interactive mode needs a terminal on stdin".into());
+    }
+    let name = key_name(escape);
+    info!(
+        "Entering interactive mode, press {} then c to resume, or {} then q to abort",
+        name, name
+    );
+
+    let raw = RawMode::enter()?;
+    let mut out = stdout();
+    let mut buf = [0u8; 1024];
+    let mut escaped = false;
+    let res = loop {
+        let sz = stdin().read(&mut buf)?;
+        let mut input = Vec::with_capacity(sz);
+        let mut leave = None;
+        for &b in &buf[..sz] {
+            if escaped {
+                escaped = false;
+                match b {
+                    b'c' | b'C' => leave = Some(Ok(())),
+                    b'q' | b'Q' => leave = Some(Err("Aborted in interactive mode".into())),
+                    b if b == escape => input.push(b),
+                    _ => {}
+                }
+                if leave.is_some() {
+                    break;
+                }
+            } else if b == escape {
+                escaped = true;
+            } else {
+                input.push(b);
+            }
+        }
+        if !input.is_empty() {
+            tty.write(&input)?;
+        }
+        if let Some(res) = leave {
+            break res;
+        }
+
+        let data = tty.read()?;
+        if !data.is_empty() {
+            out.write_all(&data)?;
+            out.flush()?;
+        }
+    };
+    drop(raw);
+
+    match &res {
+        Ok(_) => info!("Leaving interactive mode, resume the test"),
+        Err(_) => warn!("Leaving interactive mode, abort the test"),
+    }
+    res
+}
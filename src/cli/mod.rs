@@ -26,8 +26,19 @@ pub mod tty;
 pub mod serial;
 pub mod shell;
 pub mod ssh;
+pub mod tunnel;
 pub mod asciicast;
 pub mod asciicast_multi;
 pub mod recorder;
 pub mod tee;
-pub mod deansi;
\ No newline at end of file
+pub mod deansi;
+pub mod bootlog;
+pub mod emulator;
+pub mod vterm;
+pub mod adb;
+pub mod transform;
+pub mod mux;
+pub mod console_server;
+#[cfg(unix)]
+pub mod interact;
+pub mod process;
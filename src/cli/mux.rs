@@ -0,0 +1,352 @@
+//! [`Mux`] fans one [`Tty`] out to several readers.
+//!
+//! A [`Tty`] has one owner and each read drains it, so the GUI terminal, a log
+//! and the test executor can't all read the same serial console. [`Mux`] owns
+//! the [`Tty`] and reads it in a background thread. Each [`MuxReader`] handed
+//! out is a [`Tty`] of its own, with an independent cursor into the output, so
+//! every reader sees everything read since it was created.
+//!
+//! Only one reader can write at a time, it must [`MuxReader::claim_writer`]
+//! first. A reader which falls behind by more than its capacity is handled by
+//! its [`OverflowPolicy`].
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use tester::cli::mux::{Mux, OverflowPolicy};
+//! # use tester::cli::shell::Shell;
+//! # use tester::cli::tty::Tty;
+//! let s = Shell::build(Some("bash"))?;
+//! let mux = Mux::build(Box::new(s));
+//! let mut exec = mux.reader(1 << 20, OverflowPolicy::Block);
+//! let mut log = mux.reader(1 << 20, OverflowPolicy::DropOldest);
+//! exec.claim_writer()?;
+//! exec.write(b"echo hello\n")?;
+//! std::thread::sleep(std::time::Duration::from_millis(500));
+//! assert_eq!(exec.read()?, log.read()?);
+//! let _ = mux.exit();
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    sync::{Arc, Mutex},
+    thread::{sleep, spawn, JoinHandle},
+    time::Duration,
+};
+
+use crate::{consts::DURATION, err, impl_any, info, warn};
+
+use super::tty::{DynTty, Tty};
+
+/// What to do when a reader falls behind by more than its capacity
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OverflowPolicy {
+    /// Stop reading the inner [`Tty`] until the reader catches up, so the
+    /// backpressure goes to the device. Other readers wait as well.
+    Block,
+    /// Skip the oldest data of this reader, see [`MuxReader::dropped`].
+    DropOldest,
+    /// Disconnect the reader, its next read fails.
+    Disconnect,
+}
+
+struct ReaderState {
+    /// The absolute offset of the next byte to read
+    cursor: u64,
+    capacity: usize,
+    policy: OverflowPolicy,
+    dropped: u64,
+    disconnected: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    buf: VecDeque<u8>,
+    /// The absolute offset of `buf[0]`
+    base: u64,
+    readers: HashMap<u64, ReaderState>,
+    next_id: u64,
+    writer: Option<u64>,
+    /// Set when the inner Tty fails or the Mux exits
+    closed: Option<String>,
+}
+
+impl Shared {
+    fn end(&self) -> u64 {
+        self.base + self.buf.len() as u64
+    }
+
+    /// Whether a blocking reader is full, then the pump waits
+    fn blocked(&self) -> bool {
+        let end = self.end();
+        self.readers.values().any(|r| {
+            r.policy == OverflowPolicy::Block
+                && !r.disconnected
+                && end - r.cursor >= r.capacity as u64
+        })
+    }
+
+    fn push(&mut self, data: &[u8]) {
+        self.buf.extend(data);
+        let end = self.end();
+        for (id, r) in self.readers.iter_mut() {
+            if r.disconnected || end - r.cursor <= r.capacity as u64 {
+                continue;
+            }
+            match r.policy {
+                // Can overflow by one read at most
+                OverflowPolicy::Block => {}
+                OverflowPolicy::DropOldest => {
+                    let skip = end - r.cursor - r.capacity as u64;
+                    r.cursor += skip;
+                    r.dropped += skip;
+                }
+                OverflowPolicy::Disconnect => {
+                    warn!("Mux reader {} is too slow, disconnected", id);
+                    r.disconnected = true;
+                }
+            }
+        }
+        self.trim();
+    }
+
+    /// Drop the data every reader has read
+    fn trim(&mut self) {
+        let min = self
+            .readers
+            .values()
+            .filter(|r| !r.disconnected)
+            .map(|r| r.cursor)
+            .min()
+            .unwrap_or(self.end());
+        let drain = (min - self.base) as usize;
+        self.buf.drain(..drain);
+        self.base = min;
+    }
+}
+
+/// The hub, owns the inner [`Tty`] and hands out [`MuxReader`]s
+pub struct Mux {
+    inner: Arc<Mutex<Option<DynTty>>>,
+    shared: Arc<Mutex<Shared>>,
+    stop: Arc<Mutex<bool>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Mux {
+    /// Build a new [`Mux`] instance, the inner [`Tty`] is read from now on.
+    ///
+    /// Output read while there's no reader is dropped.
+    pub fn build(inner: DynTty) -> Mux {
+        let inner = Arc::new(Mutex::new(Some(inner)));
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let stop = Arc::new(Mutex::new(false));
+
+        let inner_clone = inner.clone();
+        let shared_clone = shared.clone();
+        let stop_clone = stop.clone();
+        let handle = spawn(move || loop {
+            if *stop_clone.lock().unwrap() {
+                return;
+            }
+            if shared_clone.lock().unwrap().blocked() {
+                sleep(Duration::from_millis(DURATION));
+                continue;
+            }
+            let data = {
+                let mut inner = inner_clone.lock().unwrap();
+                match inner.as_mut().map(|inner| inner.read()) {
+                    Some(Ok(data)) => data,
+                    Some(Err(e)) => {
+                        err!("Mux failed to read: {}", e);
+                        shared_clone.lock().unwrap().closed = Some(e.to_string());
+                        return;
+                    }
+                    None => return,
+                }
+            };
+            if data.is_empty() {
+                sleep(Duration::from_millis(DURATION));
+                continue;
+            }
+            shared_clone.lock().unwrap().push(&data);
+        });
+
+        info!("Create a Mux to share the Tty.");
+
+        Mux {
+            inner,
+            shared,
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    /// Hand out a new reader, it sees the output from now on.
+    ///
+    /// # Arguments
+    ///
+    /// - `capacity`: How many unread bytes the reader can fall behind.
+    /// - `policy`: What to do when it falls behind further.
+    pub fn reader(&self, capacity: usize, policy: OverflowPolicy) -> MuxReader {
+        let mut shared = self.shared.lock().unwrap();
+        let id = shared.next_id;
+        shared.next_id += 1;
+        let cursor = shared.end();
+        shared.readers.insert(
+            id,
+            ReaderState {
+                cursor,
+                capacity: capacity.max(1),
+                policy,
+                dropped: 0,
+                disconnected: false,
+            },
+        );
+        MuxReader {
+            id,
+            inner: self.inner.clone(),
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Stop reading and take the inner [`Tty`] back, the readers get closed.
+    ///
+    /// The data not read by the readers yet can still be read.
+    pub fn exit(mut self) -> DynTty {
+        self.shutdown();
+        self.inner
+            .lock()
+            .unwrap()
+            .take()
+            .expect("The inner Tty is taken only once")
+    }
+
+    fn shutdown(&mut self) {
+        *self.stop.lock().unwrap() = true;
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+        let mut shared = self.shared.lock().unwrap();
+        shared.closed.get_or_insert("The Mux has exited".to_owned());
+    }
+}
+
+impl Drop for Mux {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// A reader of a [`Mux`], with its own cursor
+pub struct MuxReader {
+    id: u64,
+    inner: Arc<Mutex<Option<DynTty>>>,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl MuxReader {
+    /// Become the writer, fails if another reader is.
+    pub fn claim_writer(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut shared = self.shared.lock().unwrap();
+        match shared.writer {
+            Some(id) if id != self.id => Err(format!("Mux reader {} is the writer", id).into()),
+            _ => {
+                shared.writer = Some(self.id);
+                Ok(())
+            }
+        }
+    }
+
+    /// Give up writing, so another reader can claim it.
+    pub fn release_writer(&mut self) {
+        let mut shared = self.shared.lock().unwrap();
+        if shared.writer == Some(self.id) {
+            shared.writer = None;
+        }
+    }
+
+    pub fn is_writer(&self) -> bool {
+        self.shared.lock().unwrap().writer == Some(self.id)
+    }
+
+    /// How many bytes were skipped with [`OverflowPolicy::DropOldest`]
+    pub fn dropped(&self) -> u64 {
+        self.shared.lock().unwrap().readers[&self.id].dropped
+    }
+
+    /// How many bytes are waiting to be read
+    pub fn lag(&self) -> usize {
+        let shared = self.shared.lock().unwrap();
+        (shared.end() - shared.readers[&self.id].cursor) as usize
+    }
+
+    /// Take the unread data, up to and including `until` if it's found.
+    fn take(&mut self, until: Option<u8>) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        let mut shared = self.shared.lock().unwrap();
+        let base = shared.base;
+        let r = &shared.readers[&self.id];
+        if r.disconnected {
+            return Err("This Mux reader was too slow and got disconnected".into());
+        }
+        let start = (r.cursor - base) as usize;
+        let len = match until {
+            Some(c) => match shared.buf.range(start..).position(|&b| b == c) {
+                Some(pos) => pos + 1,
+                None if shared.closed.is_some() => shared.buf.len() - start,
+                None => return Ok(None),
+            },
+            None => shared.buf.len() - start,
+        };
+        if len == 0 {
+            if let Some(reason) = &shared.closed {
+                return Err(reason.clone().into());
+            }
+        }
+        let data: Vec<u8> = shared.buf.range(start..start + len).copied().collect();
+        shared.readers.get_mut(&self.id).unwrap().cursor += len as u64;
+        shared.trim();
+        Ok(Some(data))
+    }
+}
+
+impl_any!(MuxReader);
+
+impl Tty for MuxReader {
+    fn read(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(self.take(None)?.unwrap_or_default())
+    }
+
+    fn read_line(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        loop {
+            if let Some(line) = self.take(Some(b'\n'))? {
+                return Ok(line);
+            }
+            sleep(Duration::from_millis(DURATION));
+        }
+    }
+
+    /// Write to the inner [`Tty`], only the writer can.
+    fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        if !self.is_writer() {
+            return Err("This Mux reader isn't the writer, claim it first".into());
+        }
+        match self.inner.lock().unwrap().as_mut() {
+            Some(inner) => inner.write(data),
+            None => Err("The Mux has exited".into()),
+        }
+    }
+}
+
+impl Drop for MuxReader {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.readers.remove(&self.id);
+        if shared.writer == Some(self.id) {
+            shared.writer = None;
+        }
+        shared.trim();
+    }
+}
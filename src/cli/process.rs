@@ -0,0 +1,288 @@
+//! Run a host tool as a [`Tty`], without a PTY.
+//!
+//! A [`super::shell::Shell`] is interactive, its echoes and prompts get mixed
+//! into the output. [`Process`] spawns one command with piped stdin, stdout and
+//! stderr instead, for tools like `dd`, `fastboot` or `bmaptool`.
+//!
+//! [`Tty::read`] returns stdout only, stderr is kept apart and read with
+//! [`Process::read_stderr`]. The process is killed when dropped.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use std::time::Duration;
+//! # use tester::cli::process::ProcessBuilder;
+//! # use tester::cli::tty::Tty;
+//! let mut p = ProcessBuilder::new("sh")
+//!     .args(["-c", "echo out; echo err >&2; exit 3"])
+//!     .build()?;
+//! let status = p.wait(Some(Duration::from_secs(5)))?;
+//! assert_eq!(status.code(), Some(3));
+//! assert_eq!(p.read()?, b"out\n");
+//! assert_eq!(p.read_stderr(), b"err\n");
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::{
+    error::Error,
+    io::{Read, Write},
+    process::{Child, ChildStdin, Command, ExitStatus, Stdio},
+    sync::{Arc, Mutex},
+    thread::{sleep, spawn, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{consts::SHELL_DURATION, err, impl_any, info, log};
+
+use super::tty::Tty;
+
+/// How long to wait for the pipes to close after the process exits
+const JOIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Output of one pipe, filled by a reader thread
+#[derive(Default)]
+struct Pipe {
+    buf: Vec<u8>,
+    closed: bool,
+}
+
+fn pump<R: Read + Send + 'static>(
+    mut from: R,
+    to: Arc<Mutex<Pipe>>,
+    name: &'static str,
+) -> JoinHandle<()> {
+    spawn(move || {
+        let mut buf = [0u8; 4096];
+        loop {
+            match from.read(&mut buf) {
+                Ok(0) => break,
+                Ok(sz) => to.lock().unwrap().buf.extend_from_slice(&buf[..sz]),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    err!("Failed to read {} of process. Reason: {}", name, e);
+                    break;
+                }
+            }
+        }
+        log!("Process {} closed", name);
+        to.lock().unwrap().closed = true;
+    })
+}
+
+/// A builder of [`Process`], for the arguments and environment.
+pub struct ProcessBuilder {
+    program: String,
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    cwd: Option<String>,
+}
+
+impl ProcessBuilder {
+    pub fn new(program: &str) -> ProcessBuilder {
+        ProcessBuilder {
+            program: program.to_owned(),
+            args: Vec::new(),
+            env: Vec::new(),
+            cwd: None,
+        }
+    }
+
+    pub fn arg(mut self, arg: &str) -> ProcessBuilder {
+        self.args.push(arg.to_owned());
+        self
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> ProcessBuilder
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.args
+            .extend(args.into_iter().map(|a| a.as_ref().to_owned()));
+        self
+    }
+
+    /// Set an environment variable, on top of the ones inherited.
+    pub fn env(mut self, key: &str, value: &str) -> ProcessBuilder {
+        self.env.push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    pub fn cwd(mut self, dir: &str) -> ProcessBuilder {
+        self.cwd = Some(dir.to_owned());
+        self
+    }
+
+    pub fn build(self) -> Result<Process, Box<dyn Error>> {
+        info!("Spawn process: {} {}", self.program, self.args.join(" "));
+
+        let mut cmd = Command::new(&self.program);
+        cmd.args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        for (key, value) in &self.env {
+            cmd.env(key, value);
+        }
+        if let Some(cwd) = &self.cwd {
+            cmd.current_dir(cwd);
+        }
+        let mut child = cmd.spawn().map_err(|e| {
+            err!("Failed to spawn {}. Reason: {}", self.program, e);
+            e
+        })?;
+
+        let stdout = Arc::new(Mutex::new(Pipe::default()));
+        let stderr = Arc::new(Mutex::new(Pipe::default()));
+        let handles = vec![
+            pump(child.stdout.take().unwrap(), stdout.clone(), "stdout"),
+            pump(child.stderr.take().unwrap(), stderr.clone(), "stderr"),
+        ];
+
+        Ok(Process {
+            stdin: child.stdin.take(),
+            child,
+            status: None,
+            stdout,
+            stderr,
+            handles,
+        })
+    }
+}
+
+pub struct Process {
+    child: Child,
+    status: Option<ExitStatus>,
+    stdin: Option<ChildStdin>,
+    stdout: Arc<Mutex<Pipe>>,
+    stderr: Arc<Mutex<Pipe>>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl Process {
+    /// Spawn `program` with `args`, see [`ProcessBuilder`] for more options.
+    pub fn build(program: &str, args: &[&str]) -> Result<Process, Box<dyn Error>> {
+        ProcessBuilder::new(program).args(args).build()
+    }
+
+    /// Take what the process wrote to stderr since the last call
+    pub fn read_stderr(&mut self) -> Vec<u8> {
+        let mut stderr = self.stderr.lock().unwrap();
+        std::mem::take(&mut stderr.buf)
+    }
+
+    /// Close stdin, for tools reading it until EOF
+    pub fn close_stdin(&mut self) {
+        self.stdin = None;
+    }
+
+    /// Whether the process is still running
+    pub fn is_alive(&mut self) -> bool {
+        self.exit_status().is_none()
+    }
+
+    /// The exit status, `None` while the process is running
+    pub fn exit_status(&mut self) -> Option<ExitStatus> {
+        if self.status.is_none() {
+            if let Ok(Some(status)) = self.child.try_wait() {
+                self.status = Some(status);
+            }
+        }
+        self.status
+    }
+
+    /// Wait for the process to exit, forever if `timeout` is `None`.
+    ///
+    /// The output is still buffered while waiting, read it afterwards.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> Result<ExitStatus, Box<dyn Error>> {
+        let begin = Instant::now();
+        loop {
+            if let Some(status) = self.exit_status() {
+                self.join();
+                return Ok(status);
+            }
+            if timeout.is_some_and(|t| begin.elapsed() > t) {
+                return Err("Timeout waiting for the process to exit".into());
+            }
+            sleep(Duration::from_millis(SHELL_DURATION));
+        }
+    }
+
+    /// Kill the process and reap it, nothing happens if it has exited.
+    pub fn kill(&mut self) {
+        if self.is_alive() {
+            log!("Kill process {}", self.child.id());
+            let _ = self.child.kill();
+            match self.child.wait() {
+                Ok(status) => self.status = Some(status),
+                Err(e) => err!("Failed to wait for process. Reason: {}", e),
+            }
+        }
+        self.join();
+    }
+
+    /// Join the reader threads once the pipes close, so all the output is buffered.
+    ///
+    /// A child of the process may keep the pipes open, don't hang on it.
+    fn join(&mut self) {
+        let begin = Instant::now();
+        while self.handles.iter().any(|h| !h.is_finished()) {
+            if begin.elapsed() > JOIN_TIMEOUT {
+                log!("The pipes of the process are still open, leave them");
+                return;
+            }
+            sleep(Duration::from_millis(SHELL_DURATION));
+        }
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl_any!(Process);
+
+impl Tty for Process {
+    /// Take what the process wrote to stdout since the last read
+    fn read(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut stdout = self.stdout.lock().unwrap();
+        Ok(std::mem::take(&mut stdout.buf))
+    }
+
+    /// Read a line of stdout, the last line may miss the `\n` if stdout closes.
+    fn read_line(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        loop {
+            {
+                let mut stdout = self.stdout.lock().unwrap();
+                if let Some(pos) = stdout.buf.iter().position(|&b| b == b'\n') {
+                    return Ok(stdout.buf.drain(..=pos).collect());
+                }
+                if stdout.closed {
+                    if stdout.buf.is_empty() {
+                        return Err("The process closed its stdout".into());
+                    }
+                    return Ok(std::mem::take(&mut stdout.buf));
+                }
+            }
+            sleep(Duration::from_millis(SHELL_DURATION));
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        let stdin = match self.stdin.as_mut() {
+            Some(stdin) => stdin,
+            None => return Err("The stdin of the process is closed".into()),
+        };
+        stdin.write_all(data).map_err(|e| {
+            err!("Write to process failed. Reason: {}", e);
+            e
+        })?;
+        stdin.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
@@ -41,10 +41,8 @@ impl Tty for Serial {
         loop {
             let mut buff = [0u8];
             match self.inner.read(&mut buff) {
+                Ok(0) => return Ok(buf),
                 Ok(_) => {
-                    if buff[0] == 0x0 {
-                        return Ok(buf);
-                    }
                     buf.extend_from_slice(&buff);
                 }
                 Err(e) if e.kind() == ErrorKind::Interrupted => continue,
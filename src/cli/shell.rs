@@ -1,52 +1,140 @@
-use portable_pty::{native_pty_system, Child, CommandBuilder, PtyPair, PtySize};
+#[cfg(unix)]
+use nix::{
+    errno::Errno,
+    sys::signal::{kill, Signal},
+    unistd::Pid,
+};
+use portable_pty::{native_pty_system, Child, CommandBuilder, MasterPty, PtySize};
 use std::io::{BufReader, Read};
 use std::ops::DerefMut;
 use std::{
     error::Error,
-    io::Write,
+    io::{self, Write},
     sync::{Arc, Mutex},
     thread::{sleep, spawn, JoinHandle},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use crate::impl_any;
 use crate::util::util::try_read;
-use crate::{consts::SHELL_DURATION, err, info, log};
+use crate::{consts::SHELL_DURATION, err, info, log, warn};
+
+pub use portable_pty::ExitStatus;
 
 use super::tty::Tty;
 
-#[allow(dead_code)]
-pub struct Shell {
-    buff: Arc<Mutex<Vec<u8>>>,
-    pty: PtyPair,                        // unused: As holder
-    child: Box<dyn Child + Send + Sync>, // unused: As holder
-    reader: Arc<Mutex<Box<dyn Read + Send>>>,
-    writer: Arc<Mutex<Box<dyn Write + Send>>>,
-    handle: Option<JoinHandle<()>>,
-    stop: Arc<Mutex<bool>>,
+/// How long to wait for the shell to exit after SIGHUP, before killing it
+#[cfg(unix)]
+const KILL_GRACE: Duration = Duration::from_secs(1);
+
+/// How long to wait for the reader thread to finish on shutdown
+const JOIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A builder of [`Shell`], for the arguments, environment and PTY size.
+///
+/// # Example
+///
+/// ```no_run
+/// # use tester::cli::shell::ShellBuilder;
+/// let mut shell = ShellBuilder::new("bash")
+///     .args(["--norc", "-i"])
+///     .env("LANG", "C")
+///     .cwd("/tmp")
+///     .size(120, 40)
+///     .build()?;
+/// assert!(shell.is_alive());
+/// shell.shutdown();
+/// assert!(!shell.is_alive());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct ShellBuilder {
+    shell: String,
+    args: Option<Vec<String>>,
+    env: Vec<(String, String)>,
+    cwd: Option<String>,
+    cols: u16,
+    rows: u16,
 }
 
-impl Shell {
-    pub fn build(shell: Option<&str>) -> Result<Shell, Box<dyn Error>> {
-        let shell = shell.unwrap_or("/bin/sh");
+impl ShellBuilder {
+    pub fn new(shell: &str) -> ShellBuilder {
+        ShellBuilder {
+            shell: shell.to_owned(),
+            args: None,
+            env: Vec::new(),
+            cwd: None,
+            cols: 80,
+            rows: 24,
+        }
+    }
 
-        info!("Spawn shell process: {}", shell);
+    /// Append an argument, the default `-i` is dropped once any is given.
+    pub fn arg(mut self, arg: &str) -> ShellBuilder {
+        self.args.get_or_insert_with(Vec::new).push(arg.to_owned());
+        self
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> ShellBuilder
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let list = self.args.get_or_insert_with(Vec::new);
+        list.extend(args.into_iter().map(|a| a.as_ref().to_owned()));
+        self
+    }
+
+    /// Set an environment variable, on top of the ones inherited.
+    pub fn env(mut self, key: &str, value: &str) -> ShellBuilder {
+        self.env.push((key.to_owned(), value.to_owned()));
+        self
+    }
 
-        let mut cmd = CommandBuilder::new(shell);
-        cmd.arg("-i");
+    pub fn cwd(mut self, dir: &str) -> ShellBuilder {
+        self.cwd = Some(dir.to_owned());
+        self
+    }
+
+    pub fn size(mut self, cols: u16, rows: u16) -> ShellBuilder {
+        self.cols = cols;
+        self.rows = rows;
+        self
+    }
+
+    pub fn build(self) -> Result<Shell, Box<dyn Error>> {
+        let args = self.args.unwrap_or_else(|| vec!["-i".to_owned()]);
+
+        info!("Spawn shell process: {} {}", self.shell, args.join(" "));
+
+        let mut cmd = CommandBuilder::new(&self.shell);
+        cmd.args(&args);
+        for (key, value) in &self.env {
+            cmd.env(key, value);
+        }
+        if let Some(cwd) = &self.cwd {
+            cmd.cwd(cwd);
+        }
         let pty_system = native_pty_system();
-        let pty = pty_system.openpty(PtySize::default())?;
+        let pty = pty_system.openpty(PtySize {
+            rows: self.rows,
+            cols: self.cols,
+            ..Default::default()
+        })?;
 
         let child = pty.slave.spawn_command(cmd)?;
+        // Close our end of the slave, so reading fails once the shell is gone
+        drop(pty.slave);
+        let master = pty.master;
 
-        let reader = pty.master.try_clone_reader()?;
+        let reader = master.try_clone_reader()?;
 
-        let writer = pty.master.take_writer()?;
+        let writer = master.take_writer()?;
 
         let mut res = Shell {
             buff: Arc::new(Mutex::new(Vec::new())),
-            pty,
+            master,
             child,
+            status: None,
             reader: Arc::new(Mutex::new(reader)),
             writer: Arc::new(Mutex::new(writer)),
             handle: None,
@@ -71,12 +159,18 @@ impl Shell {
                 let mut r = BufReader::new(reader.deref_mut());
                 let sz = try_read(&mut r, &mut buf);
                 if let Err(e) = sz {
-                    err!("Failed to read from shell process. Reason: {}", e);
+                    let closed = e.downcast_ref::<io::Error>().is_some_and(output_closed);
+                    if closed {
+                        log!("Shell process output closed");
+                    } else {
+                        err!("Failed to read from shell process. Reason: {}", e);
+                    }
                     return;
                 }
                 let sz = sz.unwrap();
                 if sz == 0 {
-                    continue;
+                    log!("Shell process output closed");
+                    return;
                 }
             }
             {
@@ -89,6 +183,61 @@ impl Shell {
 
         Ok(res)
     }
+}
+
+/// EIO is how a unix PTY reports the shell has gone
+#[cfg(unix)]
+fn output_closed(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(Errno::EIO as i32)
+}
+
+/// ConPTY reports the shell has gone with a broken pipe
+#[cfg(not(unix))]
+fn output_closed(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::BrokenPipe
+}
+
+pub struct Shell {
+    buff: Arc<Mutex<Vec<u8>>>,
+    master: Box<dyn MasterPty + Send>,
+    child: Box<dyn Child + Send + Sync>,
+    status: Option<ExitStatus>,
+    reader: Arc<Mutex<Box<dyn Read + Send>>>,
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+    handle: Option<JoinHandle<()>>,
+    stop: Arc<Mutex<bool>>,
+}
+
+impl Shell {
+    /// Spawn `shell` (default `/bin/sh`) with `-i`, see [`ShellBuilder`] for more options.
+    pub fn build(shell: Option<&str>) -> Result<Shell, Box<dyn Error>> {
+        ShellBuilder::new(shell.unwrap_or("/bin/sh")).build()
+    }
+
+    /// Change the PTY size, the shell gets a SIGWINCH.
+    pub fn resize(&mut self, cols: u16, rows: u16) -> Result<(), Box<dyn Error>> {
+        self.master.resize(PtySize {
+            rows,
+            cols,
+            ..Default::default()
+        })?;
+        Ok(())
+    }
+
+    /// Whether the shell process is still running
+    pub fn is_alive(&mut self) -> bool {
+        self.exit_status().is_none()
+    }
+
+    /// The exit status, `None` while the shell is running
+    pub fn exit_status(&mut self) -> Option<ExitStatus> {
+        if self.status.is_none() {
+            if let Ok(Some(status)) = self.child.try_wait() {
+                self.status = Some(status);
+            }
+        }
+        self.status.clone()
+    }
 
     fn __stop(&mut self) {
         let stop = self.stop.lock();
@@ -101,13 +250,58 @@ impl Shell {
             return;
         }
         *stop = true;
+        drop(stop);
         log!("Try to stop shell process");
-        // writeln!(self.pty.master.take_writer().unwrap(), "exit").unwrap();
-        // self.child.kill().unwrap();
-        // if let Some(handle) = self.handle.take() {
-        //     handle.join().unwrap();
-        //     self.inner.wait().unwrap();
-        // } // workaround for stopping shell process
+
+        if self.is_alive() {
+            self.hangup();
+            if self.is_alive() {
+                if let Err(e) = self.child.kill() {
+                    err!("Failed to kill shell process. Reason: {}", e);
+                }
+            }
+            match self.child.wait() {
+                Ok(status) => self.status = Some(status),
+                Err(e) => err!("Failed to wait for shell process. Reason: {}", e),
+            }
+        }
+
+        // Background jobs of the shell may keep the PTY open, don't hang on them
+        if let Some(handle) = self.handle.take() {
+            let begin = Instant::now();
+            while !handle.is_finished() && begin.elapsed() < JOIN_TIMEOUT {
+                sleep(Duration::from_millis(SHELL_DURATION));
+            }
+            if handle.is_finished() {
+                let _ = handle.join();
+            } else {
+                warn!("Shell reader thread is still blocked, leave it");
+            }
+        }
+    }
+
+    /// Send SIGHUP like a closed terminal does, and give the shell time to exit.
+    #[cfg(unix)]
+    fn hangup(&mut self) {
+        if let Some(pid) = self.child.process_id() {
+            let _ = kill(Pid::from_raw(pid as i32), Signal::SIGHUP);
+        }
+        let begin = Instant::now();
+        while self.is_alive() && begin.elapsed() < KILL_GRACE {
+            sleep(Duration::from_millis(SHELL_DURATION));
+        }
+        if self.is_alive() {
+            warn!("Shell process ignored SIGHUP, kill it");
+        }
+    }
+
+    /// There's no hangup signal, the shell is killed straight away.
+    #[cfg(not(unix))]
+    fn hangup(&mut self) {}
+
+    /// Stop the shell: SIGHUP on unix, then kill it if it doesn't exit in time, and reap it.
+    pub fn shutdown(&mut self) {
+        self.__stop();
     }
 
     pub fn stop(mut self) {
@@ -1,130 +1,571 @@
+//! SSH backend for the [`Tty`] trait.
+//!
+//! The [`Ssh`] opens an interactive shell on a remote host. Besides the
+//! plain password and key file authentication, it also supports:
+//! - ssh-agent and keyboard-interactive authentication
+//! - known_hosts verification, see [`HostKeyCheck`]
+//! - PTY type and size settings
+//! - Keepalive and reconnecting when the connection drops
+//! - Running single commands and copying files, see [`Ssh::exec`], [`Ssh::upload`]
+//!   and [`Ssh::download`]
+//! - Jumping through bastions and local port forwards, see [`super::tunnel`]
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use tester::cli::ssh::{Ssh, SshConf, SshPass};
+//! # use tester::cli::tty::Tty;
+//! let mut conf = SshConf::new("192.168.1.2", 22, "root", SshPass::Agent);
+//! conf.keepalive = 10;
+//! let mut s = Ssh::build_conf(conf)?;
+//! s.write(b"uname -a\n")?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
 use std::{
+    env,
     error::Error,
+    fs::{create_dir_all, File},
     io::{ErrorKind, Read, Write},
-    net::TcpStream,
-    path::Path,
+    iter::once,
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
     thread::{sleep, spawn, JoinHandle},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use ssh2::Channel;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
 
-use crate::{
-    consts::SHELL_DURATION, err, impl_any, log
-};
+use ssh2::{CheckResult, KeyboardInteractivePrompt, KnownHostFileKind, Prompt, Session};
 
-use super::tty::Tty;
+use crate::{consts::SHELL_DURATION, err, impl_any, info, log, warn};
+
+use super::{
+    tty::Tty,
+    tunnel::{copy, open_direct, socket_pair, ssh_retry, LocalForward, Tunnel},
+};
 
+/// How to authenticate to the SSH server
 #[derive(Clone)]
 pub enum SshPass {
     Password(String),
     Key(String), // Path to private key
+    /// Use identities from the running ssh-agent
+    Agent,
+    /// Answer every keyboard-interactive prompt with the given password
+    KeyboardInteractive(String),
+}
+
+/// How to verify the host key of the server against the known_hosts file
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum HostKeyCheck {
+    /// Refuse unknown and mismatched hosts
+    Strict,
+    /// Add unknown hosts to the known_hosts file, refuse mismatched hosts
+    AcceptNew,
+    /// Don't check the host key at all
+    Off,
+}
+
+/// Connection settings for [`Ssh`]
+#[derive(Clone)]
+pub struct SshConf {
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+    pub pass: SshPass,
+    /// Host key checking policy, default to [`HostKeyCheck::AcceptNew`]
+    pub host_key_check: HostKeyCheck,
+    /// Path to the known_hosts file, default to `~/.ssh/known_hosts`
+    pub known_hosts: Option<String>,
+    /// The `TERM` of the remote PTY
+    pub term: String,
+    pub cols: u32,
+    pub rows: u32,
+    /// Keepalive interval in seconds, 0 to disable
+    pub keepalive: u32,
+    /// How many times to reconnect after the connection drops, 0 to disable
+    pub reconnect: u32,
+    /// Timeout for connecting and authenticating, in milliseconds
+    pub timeout: u32,
+    /// Bastions to hop through before reaching this host, like `ProxyJump`.
+    ///
+    /// The first one is connected directly, every next one (and finally this
+    /// host) is reached through a tunnel on the previous one. The `jump` of
+    /// the bastions themselves are ignored.
+    pub jump: Vec<SshConf>,
+}
+
+impl SshConf {
+    pub fn new(host: &str, port: u16, user: &str, pass: SshPass) -> SshConf {
+        SshConf {
+            host: host.to_owned(),
+            port,
+            user: user.to_owned(),
+            pass,
+            host_key_check: HostKeyCheck::AcceptNew,
+            known_hosts: None,
+            term: "xterm".to_owned(),
+            cols: 80,
+            rows: 24,
+            keepalive: 0,
+            reconnect: 0,
+            timeout: 10000,
+            jump: Vec::new(),
+        }
+    }
+}
+
+struct KbdPrompt<'a> {
+    answer: &'a str,
+}
+
+impl KeyboardInteractivePrompt for KbdPrompt<'_> {
+    fn prompt<'b>(
+        &mut self,
+        _username: &str,
+        _instructions: &str,
+        prompts: &[Prompt<'b>],
+    ) -> Vec<String> {
+        prompts.iter().map(|_| self.answer.to_owned()).collect()
+    }
+}
+
+/// Output of [`Ssh::exec`]
+pub struct SshExecOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub status: i32,
+}
+
+struct SshInner {
+    sess: Session,
+    channel: ssh2::Channel,
+    _jumps: Vec<Tunnel>, // unused: As holder
 }
 
-#[allow(dead_code)]
 pub struct Ssh {
-    // sess: Session,
-    channel: Arc<Mutex<Channel>>,
+    conf: SshConf,
+    inner: Arc<Mutex<SshInner>>,
     buff: Arc<Mutex<Vec<u8>>>,
     stop: Arc<Mutex<bool>>,
     handle: Option<JoinHandle<()>>,
+    forwards: Vec<LocalForward>,
 }
 
 impl Ssh {
-    fn connect(
-        host: &str,
-        port: u16,
-        user: &str,
-        pass: SshPass,
-    ) -> Result<ssh2::Session, Box<dyn Error>> {
-        let tcp = TcpStream::connect(format!("{}:{}", host, port))?;
-        let mut sess = ssh2::Session::new()?;
-        sess.set_tcp_stream(tcp);
-        sess.handshake()?;
-        match pass {
-            SshPass::Password(ref pass) => {
+    fn known_hosts_path(conf: &SshConf) -> PathBuf {
+        if let Some(path) = &conf.known_hosts {
+            return PathBuf::from(path);
+        }
+        let home = env::var("HOME").unwrap_or_else(|_| ".".to_owned());
+        Path::new(&home).join(".ssh").join("known_hosts")
+    }
+
+    fn check_host_key(sess: &Session, conf: &SshConf) -> Result<(), Box<dyn Error>> {
+        if conf.host_key_check == HostKeyCheck::Off {
+            warn!("Host key checking for {} is disabled.", conf.host);
+            return Ok(());
+        }
+        let (key, key_type) = sess.host_key().ok_or("Server didn't send a host key")?;
+
+        let path = Self::known_hosts_path(conf);
+        let mut known = sess.known_hosts()?;
+        if path.exists() {
+            known.read_file(&path, KnownHostFileKind::OpenSSH)?;
+        }
+
+        match known.check_port(&conf.host, conf.port, key) {
+            CheckResult::Match => Ok(()),
+            CheckResult::Mismatch => Err(format!(
+                "Host key for {} does NOT match the one in {}!",
+                conf.host,
+                path.display()
+            )
+            .into()),
+            CheckResult::NotFound if conf.host_key_check == HostKeyCheck::AcceptNew => {
+                let name = if conf.port == 22 {
+                    conf.host.clone()
+                } else {
+                    format!("[{}]:{}", conf.host, conf.port)
+                };
+                known.add(&name, key, "", key_type.into())?;
+                if let Some(dir) = path.parent() {
+                    create_dir_all(dir)?;
+                }
+                known.write_file(&path, KnownHostFileKind::OpenSSH)?;
+                info!("Added {} to known hosts {}", name, path.display());
+                Ok(())
+            }
+            CheckResult::NotFound => Err(format!(
+                "Host {} is not in {}, refuse to connect.",
+                conf.host,
+                path.display()
+            )
+            .into()),
+            CheckResult::Failure => Err("Failed to check host key.".into()),
+        }
+    }
+
+    fn auth(sess: &Session, conf: &SshConf) -> Result<(), Box<dyn Error>> {
+        let user = conf.user.as_str();
+        match &conf.pass {
+            SshPass::Password(pass) => {
                 sess.userauth_password(user, pass)?;
             }
-            SshPass::Key(ref key) => {
-                sess.userauth_pubkey_file(user, None, Path::new(&key), None)?;
+            SshPass::Key(key) => {
+                sess.userauth_pubkey_file(user, None, Path::new(key), None)?;
+            }
+            SshPass::Agent => {
+                let mut agent = sess.agent()?;
+                agent.connect()?;
+                agent.list_identities()?;
+                for identity in agent.identities()? {
+                    if agent.userauth(user, &identity).is_ok() {
+                        break;
+                    }
+                    log!("Agent identity {} rejected.", identity.comment());
+                }
+            }
+            SshPass::KeyboardInteractive(pass) => {
+                let mut prompt = KbdPrompt { answer: pass };
+                sess.userauth_keyboard_interactive(user, &mut prompt)?;
             }
         }
-        Ok(sess)
+        if !sess.authenticated() {
+            return Err(format!("Authentication as {} failed.", user).into());
+        }
+        Ok(())
     }
 
-    pub fn build(host: &str, port: u16, user: &str, pass: SshPass) -> Ssh {
-        let sess = Self::connect(host, port, user, pass);
-        if let Err(e) = sess {
-            panic!("Failed to connect to SSH server. Reason: {}", e);
+    fn handshake(stream: TcpStream, conf: &SshConf) -> Result<Session, Box<dyn Error>> {
+        let mut sess = Session::new()?;
+        sess.set_tcp_stream(stream);
+        sess.set_timeout(conf.timeout);
+        sess.handshake()?;
+
+        Self::check_host_key(&sess, conf)?;
+        Self::auth(&sess, conf)?;
+
+        if conf.keepalive > 0 {
+            sess.set_keepalive(false, conf.keepalive);
         }
-        let sess = sess.unwrap();
+        Ok(sess)
+    }
 
-        let channel = sess.channel_session();
-        if let Err(e) = channel {
-            panic!("Failed to open SSH channel. Reason: {}", e);
+    /// Connect to the target, hopping through every host in `conf.jump` first.
+    fn connect(conf: &SshConf) -> Result<(Session, Vec<Tunnel>), Box<dyn Error>> {
+        let mut tunnels = Vec::new();
+        let mut prev: Option<Session> = None;
+        for hop in conf.jump.iter().chain(once(conf)) {
+            let sess = match prev.take() {
+                None => {
+                    let timeout = Duration::from_millis(hop.timeout as u64);
+                    let addr = (hop.host.as_str(), hop.port)
+                        .to_socket_addrs()?
+                        .next()
+                        .ok_or(format!("Can't resolve host {}", hop.host))?;
+                    let tcp = TcpStream::connect_timeout(&addr, timeout)?;
+                    Self::handshake(tcp, hop)?
+                }
+                Some(prev) => {
+                    // The bastion is shared by the tunnel thread from now on
+                    prev.set_blocking(false);
+                    let channel = open_direct(&prev, &hop.host, hop.port)?;
+                    let (local, remote) = socket_pair()?;
+                    remote.set_nonblocking(true)?;
+                    tunnels.push(Tunnel::build(channel, remote));
+                    Self::handshake(local, hop)?
+                }
+            };
+            if !std::ptr::eq(hop, conf) {
+                info!("SSH jumped through {}@{}:{}", hop.user, hop.host, hop.port);
+            }
+            prev = Some(sess);
         }
-        let mut channel = channel.unwrap();
+        Ok((prev.unwrap(), tunnels))
+    }
+
+    fn open(conf: &SshConf) -> Result<SshInner, Box<dyn Error>> {
+        let (sess, jumps) = Self::connect(conf)?;
+
+        let mut channel = sess.channel_session()?;
+        channel.request_pty(&conf.term, None, Some((conf.cols, conf.rows, 0, 0)))?;
+        channel.shell()?;
+
+        // The reader thread must not hold the lock while waiting for data
+        sess.set_blocking(false);
+
+        info!("SSH connected to {}@{}:{}", conf.user, conf.host, conf.port);
+        Ok(SshInner {
+            sess,
+            channel,
+            _jumps: jumps,
+        })
+    }
+
+    /// Build a new `Ssh` instance with default settings.
+    pub fn build(host: &str, port: u16, user: &str, pass: SshPass) -> Result<Ssh, Box<dyn Error>> {
+        Self::build_conf(SshConf::new(host, port, user, pass))
+    }
+
+    /// Build a new `Ssh` instance with the given [`SshConf`].
+    pub fn build_conf(conf: SshConf) -> Result<Ssh, Box<dyn Error>> {
+        let inner = Self::open(&conf).map_err(|e| {
+            err!("Failed to connect to SSH server. Reason: {}", e);
+            e
+        })?;
 
-        let e = channel.shell();
-        if let Err(e) = e {
-            err!("Failed to open SSH shell. Reason: {}", e);
+        let mut res = Ssh {
+            conf,
+            inner: Arc::new(Mutex::new(inner)),
+            buff: Arc::new(Mutex::new(Vec::new())),
+            stop: Arc::new(Mutex::new(false)),
+            handle: None,
+            forwards: Vec::new(),
+        };
+
+        let conf = res.conf.clone();
+        let inner = res.inner.clone();
+        let buff = res.buff.clone();
+        let stop = res.stop.clone();
+        let handle = spawn(move || {
+            let mut retry = conf.reconnect;
+            loop {
+                {
+                    let stop = stop.lock().unwrap();
+                    if *stop {
+                        log!("Stop SSH shell.");
+                        break;
+                    }
+                }
+
+                let mut buf = [0u8; 4096];
+                let res = {
+                    let mut inner = inner.lock().unwrap();
+                    if conf.keepalive > 0 {
+                        let _ = inner.sess.keepalive_send();
+                    }
+                    match inner.channel.read(&mut buf) {
+                        Ok(0) if inner.channel.eof() => Err("Remote closed the channel".into()),
+                        Ok(sz) => Ok(sz),
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(0),
+                        Err(e) if e.kind() == ErrorKind::Interrupted => Ok(0),
+                        Err(e) => Err(e.to_string()),
+                    }
+                };
+
+                match res {
+                    Ok(0) => sleep(Duration::from_millis(SHELL_DURATION)),
+                    Ok(sz) => {
+                        let mut buff = buff.lock().unwrap();
+                        buff.extend_from_slice(&buf[..sz]);
+                    }
+                    Err(e) if retry > 0 => {
+                        retry -= 1;
+                        warn!("SSH connection lost: {}. Reconnecting...", e);
+                        sleep(Duration::from_millis(SHELL_DURATION));
+                        match Self::open(&conf) {
+                            Ok(new_inner) => {
+                                *inner.lock().unwrap() = new_inner;
+                                retry = conf.reconnect;
+                            }
+                            Err(e) => err!("Reconnect failed. Reason: {}", e),
+                        }
+                    }
+                    Err(e) => {
+                        err!("Read from SSH channel failed. Reason: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+
+        res.handle = Some(handle);
+
+        Ok(res)
+    }
+
+    /// Change the size of the remote PTY
+    pub fn resize(&mut self, cols: u32, rows: u32) -> Result<(), Box<dyn Error>> {
+        self.conf.cols = cols;
+        self.conf.rows = rows;
+        loop {
+            let mut inner = self.inner.lock().unwrap();
+            match inner.channel.request_pty_size(cols, rows, None, None) {
+                Ok(_) => return Ok(()),
+                Err(e) => {
+                    let e = std::io::Error::from(e);
+                    if e.kind() != ErrorKind::WouldBlock {
+                        return Err(Box::new(e));
+                    }
+                }
+            }
+            drop(inner);
+            sleep(Duration::from_millis(SHELL_DURATION));
         }
+    }
 
-        let channel = Arc::new(Mutex::new(channel));
-        let buff = Arc::new(Mutex::new(Vec::new()));
-        let stop = Arc::new(Mutex::new(false));
+    /// Forward a local port to `remote_host:remote_port` seen from the remote side,
+    /// just like `ssh -L`. Give `local_port` 0 to pick a random port.
+    ///
+    /// The forward lives as long as this `Ssh`. Returns the local address to connect to.
+    pub fn forward_local(
+        &mut self,
+        local_port: u16,
+        remote_host: &str,
+        remote_port: u16,
+    ) -> Result<SocketAddr, Box<dyn Error>> {
+        let sess = self.session();
+        let bind = format!("127.0.0.1:{}", local_port);
+        let forward = LocalForward::build(sess, &bind, remote_host, remote_port)?;
+        let addr = forward.local_addr();
+        self.forwards.push(forward);
+        Ok(addr)
+    }
 
-        let channel_clone = channel.clone();
-        let buff_clone = buff.clone();
-        let stop_clone = stop.clone();
+    fn session(&self) -> Session {
+        self.inner.lock().unwrap().sess.clone()
+    }
 
-        let handle = spawn(move || loop {
-            let stop = stop_clone.lock().unwrap();
-            if *stop {
-                log!("Stop SSH shell.");
+    /// Run a single command in a new exec channel, beside the interactive shell.
+    ///
+    /// Returns the stdout, stderr and exit status of the command, see [`SshExecOutput`].
+    pub fn exec(&mut self, cmd: &str, timeout: u32) -> Result<SshExecOutput, Box<dyn Error>> {
+        let sess = self.session();
+        let mut channel = ssh_retry(|| sess.channel_session())?;
+        ssh_retry(|| channel.exec(cmd))?;
+        info!("SSH exec: {}", cmd);
+
+        let begin = Instant::now();
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let mut idle = true;
+            for (id, out) in [(0, &mut stdout), (1, &mut stderr)] {
+                match channel.stream(id).read(&mut buf) {
+                    Ok(0) => {}
+                    Ok(sz) => {
+                        out.extend_from_slice(&buf[..sz]);
+                        idle = false;
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                    Err(e) => return Err(Box::new(e)),
+                }
+            }
+            if idle && channel.eof() {
                 break;
             }
+            if begin.elapsed().as_secs() > timeout as u64 {
+                let _ = ssh_retry(|| channel.close());
+                err!("Timeout! SSH exec: {}", cmd);
+                return Err("Timeout".into());
+            }
+            if idle {
+                sleep(Duration::from_millis(SHELL_DURATION));
+            }
+        }
+        ssh_retry(|| channel.wait_close())?;
+        let status = channel.exit_status()?;
+        Ok(SshExecOutput {
+            stdout,
+            stderr,
+            status,
+        })
+    }
 
-            let mut channel = channel_clone.lock().unwrap();
-            let mut buf = [0u8];
-            let sz = channel.read(&mut buf);
-            if let Err(e) = sz {
-                err!("Read from SSH channel failed. Reason: {}", e);
-                break;
+    /// Copy a local file to the remote host, returns the size copied.
+    ///
+    /// SFTP is used if the server supports it, otherwise fall back to SCP,
+    /// which is often the only choice on dropbear.
+    pub fn upload(&mut self, local: &str, remote: &str) -> Result<u64, Box<dyn Error>> {
+        let sess = self.session();
+        let mut file = File::open(local)?;
+        let size = match ssh_retry(|| sess.sftp()) {
+            Ok(sftp) => {
+                let mut target = ssh_retry(|| sftp.create(Path::new(remote)))?;
+                let size = copy(&mut file, &mut target)?;
+                ssh_retry(|| target.close())?;
+                size
             }
-            if buf[0] == 0x0 {
-                continue;
+            Err(e) => {
+                log!("SFTP unavailable, fall back to SCP. Reason: {}", e);
+                let meta = file.metadata()?;
+                #[cfg(unix)]
+                let mode = (meta.permissions().mode() & 0o777) as i32;
+                // No mode bits to keep, a plain readable file
+                #[cfg(not(unix))]
+                let mode = 0o644;
+                let mut channel =
+                    ssh_retry(|| sess.scp_send(Path::new(remote), mode, meta.len(), None))?;
+                let size = copy(&mut file, &mut channel)?;
+                ssh_retry(|| channel.send_eof())?;
+                ssh_retry(|| channel.wait_eof())?;
+                ssh_retry(|| channel.close())?;
+                ssh_retry(|| channel.wait_close())?;
+                size
             }
-            let mut buff = buff_clone.lock().unwrap();
-            buff.extend_from_slice(&buf);
-        });
+        };
+        info!("Uploaded {} to {} ({} bytes)", local, remote, size);
+        Ok(size)
+    }
 
-        Ssh {
-            // sess: sess,
-            channel,
-            buff,
-            stop,
-            handle: Some(handle),
-        }
+    /// Copy a remote file to the local host, returns the size copied.
+    ///
+    /// Just like [`Ssh::upload`], SCP is the fallback of SFTP.
+    pub fn download(&mut self, remote: &str, local: &str) -> Result<u64, Box<dyn Error>> {
+        let sess = self.session();
+        // Open the remote file first, so a missing one doesn't leave an empty local file
+        let size = match ssh_retry(|| sess.sftp()) {
+            Ok(sftp) => {
+                let mut source = ssh_retry(|| sftp.open(Path::new(remote)))?;
+                let mut file = File::create(local)?;
+                let size = copy(&mut source, &mut file)?;
+                ssh_retry(|| source.close())?;
+                size
+            }
+            Err(e) => {
+                log!("SFTP unavailable, fall back to SCP. Reason: {}", e);
+                let (mut channel, stat) = ssh_retry(|| sess.scp_recv(Path::new(remote)))?;
+                let mut file = File::create(local)?;
+                let size = copy(&mut (&mut channel).take(stat.size()), &mut file)?;
+                ssh_retry(|| channel.close())?;
+                ssh_retry(|| channel.wait_close())?;
+                size
+            }
+        };
+        info!("Downloaded {} to {} ({} bytes)", remote, local, size);
+        Ok(size)
     }
 
-    pub fn exit(self) {
-        let stop = self.stop.lock();
-        if let Err(e) = stop {
-            err!("Failed to lock stop mutex. Reason: {}", e);
-            return;
-        }
-        let mut stop = stop.unwrap();
-        if *stop {
-            return;
+    fn __stop(&mut self) {
+        {
+            let stop = self.stop.lock();
+            if let Err(e) = stop {
+                err!("Failed to lock stop mutex. Reason: {}", e);
+                return;
+            }
+            let mut stop = stop.unwrap();
+            if *stop {
+                return;
+            }
+            *stop = true;
         }
-        *stop = true;
         log!("Try to stop SSH shell.");
-        // if let Some(handle) = self.handle.take() {
-        //     handle.join().unwrap();
-        //     self.inner.wait().unwrap();
-        // } // workaround for stopping shell process
+        self.forwards.clear();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.sess.set_blocking(true);
+        let _ = inner.channel.close();
+    }
+
+    pub fn exit(mut self) {
+        self.__stop();
     }
 }
 
@@ -162,24 +603,42 @@ impl Tty for Ssh {
         Ok(res)
     }
     fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
-        loop {
-            sleep(Duration::from_millis(SHELL_DURATION));
-            let mut channel = self.channel.lock().unwrap();
-            match channel.write_all(data) {
-                Ok(_) => break,
-                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+        let mut written = 0;
+        while written < data.len() {
+            let mut inner = self.inner.lock().unwrap();
+            match inner.channel.write(&data[written..]) {
+                Ok(sz) => {
+                    written += sz;
+                    continue;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
                 Err(e) => {
-                    err!("Write to shell process failed. Reason: {}", e);
+                    err!("Write to SSH channel failed. Reason: {}", e);
                     return Err(Box::new(e));
                 }
             }
+            drop(inner);
+            sleep(Duration::from_millis(SHELL_DURATION));
         }
-        let mut channel = self.channel.lock().unwrap();
-        let res = channel.flush();
-        if let Err(e) = res {
-            err!("Flush to shell process failed. Reason: {}", e);
-            return Err(Box::<dyn Error>::from(e));
+        loop {
+            let mut inner = self.inner.lock().unwrap();
+            match inner.channel.flush() {
+                Ok(_) => return Ok(()),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => {
+                    err!("Flush to SSH channel failed. Reason: {}", e);
+                    return Err(Box::new(e));
+                }
+            }
+            drop(inner);
+            sleep(Duration::from_millis(SHELL_DURATION));
         }
-        Ok(())
+    }
+}
+
+impl Drop for Ssh {
+    fn drop(&mut self) {
+        self.__stop();
     }
 }
@@ -1,14 +1,23 @@
 //! Tee is a Tty wrapper that writes all output to a file, in addition to passing it to the inner Tty.
 //!
-//! # Example
+//! In transcript mode both directions are recorded, one chunk per line with a
+//! direction marker (`<` read from the Tty, `>` written to it) and the seconds
+//! since the Tee was built, so the log shows exactly what was sent and when:
 //!
+//! ```text
+//! [    0.512034] > echo hello\n
+//! [    0.530210] < echo hello\r\nhello\r\n$
 //! ```
+//!
+//! # Example
+//!
+//! ```no_run
 //! # use tester::cli::shell::Shell;
 //! # use tester::cli::tee::Tee;
 //! # use tester::cli::tty::Tty;
 //! # use tester::cli::tty::WrapperTty;
 //! let s = Shell::build(Some("bash"))?;
-//! let mut t = Tee::build(Box::new(s), "/tmp/output.log");
+//! let mut t = Tee::build(Box::new(s), "/tmp/output.log")?;
 //! t.write(b"echo hello\n");
 //! t.read();
 //! let _ = t.exit();
@@ -16,30 +25,190 @@
 //! ```
 //!
 
-use std::{fs::File, io::Write};
+use std::{
+    error::Error,
+    fmt::Write as _,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 
 use crate::{impl_any, info};
 
 use super::tty::{DynTty, Tty, WrapperTty};
 
+/// The options of [`Tee`]
+#[derive(Clone, Debug)]
+pub struct TeeOptions {
+    /// Record both directions with timestamps, instead of the raw output only
+    pub transcript: bool,
+    /// Append to the file instead of truncating it
+    pub append: bool,
+    /// Rotate the file when it would grow beyond this size in bytes
+    pub max_size: Option<u64>,
+    /// How many rotated files (`<path>.1`, `<path>.2`, ...) to keep
+    pub keep: usize,
+}
+
+impl Default for TeeOptions {
+    fn default() -> Self {
+        TeeOptions {
+            transcript: false,
+            append: false,
+            max_size: None,
+            keep: 3,
+        }
+    }
+}
+
+/// The log file of a [`Tee`], opened before the inner Tty is handed over,
+/// so a bad path doesn't cost the console.
+pub struct TeeFile {
+    path: String,
+    file: File,
+    options: TeeOptions,
+    begin: Instant,
+    size: u64,
+}
+
 pub struct Tee {
     inner: DynTty,
-    file: File,
+    file: TeeFile,
+}
+
+fn open(path: &str, append: bool) -> Result<File, Box<dyn Error>> {
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    Ok(file)
+}
+
+/// Escape the data to fit in one line, valid UTF-8 is kept as it is
+fn escape(data: &[u8]) -> String {
+    let mut res = String::new();
+    for chunk in data.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\n' => res += "\\n",
+                '\r' => res += "\\r",
+                '\t' => res += "\\t",
+                '\\' => res += "\\\\",
+                c if c.is_control() => {
+                    let _ = write!(res, "\\x{:02x}", c as u32);
+                }
+                c => res.push(c),
+            }
+        }
+        for b in chunk.invalid() {
+            let _ = write!(res, "\\x{:02x}", b);
+        }
+    }
+    res
+}
+
+impl TeeFile {
+    /// Open the file at `path` with the given options.
+    pub fn open(path: &str, options: TeeOptions) -> Result<TeeFile, Box<dyn Error>> {
+        info!("Teeing to file {}...", path);
+        let file = open(path, options.append)?;
+        let size = file.metadata()?.len();
+        let mut res = TeeFile {
+            path: path.to_owned(),
+            file,
+            options,
+            begin: Instant::now(),
+            size,
+        };
+        if res.options.transcript {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+            res.record(
+                format!("# Transcript started at {} (unix time)\n", now.as_secs()).as_bytes(),
+            )?;
+        }
+        Ok(res)
+    }
+
+    /// Move `<path>` to `<path>.1`, `<path>.1` to `<path>.2` and so on, then start a new file.
+    fn rotate(&mut self) -> Result<(), Box<dyn Error>> {
+        self.file.flush()?;
+        if self.options.keep == 0 {
+            let _ = fs::remove_file(&self.path);
+        } else {
+            for i in (1..self.options.keep).rev() {
+                let from = format!("{}.{}", self.path, i);
+                if fs::metadata(&from).is_ok() {
+                    fs::rename(&from, format!("{}.{}", self.path, i + 1))?;
+                }
+            }
+            fs::rename(&self.path, format!("{}.1", self.path))?;
+        }
+        info!("Rotated tee file {}", self.path);
+        self.file = open(&self.path, false)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    fn record(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        if let Some(max) = self.options.max_size {
+            if self.size > 0 && self.size + data.len() as u64 > max {
+                self.rotate()?;
+            }
+        }
+        self.file.write_all(data)?;
+        self.size += data.len() as u64;
+        Ok(())
+    }
+
+    fn record_read(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        if self.options.transcript {
+            self.record_line('<', data)
+        } else {
+            self.record(data)
+        }
+    }
+
+    fn record_line(&mut self, direction: char, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let line = format!(
+            "[{:>12.6}] {} {}\n",
+            self.begin.elapsed().as_secs_f64(),
+            direction,
+            escape(data)
+        );
+        self.record(line.as_bytes())
+    }
 }
 
 impl Tee {
-    /// Build a new `Tee` instance.
+    /// Build a new `Tee` instance, recording the raw output to a new file.
     ///
     /// # Arguments
     ///
     /// - `inner`: The inner Tty instance.
     /// - `path`: The path to the file to write to.
-    pub fn build(inner: DynTty, path: &str) -> Tee {
-        info!("Teeing to file {}...", path);
-        Tee {
-            inner,
-            file: File::create(path).unwrap(),
-        }
+    pub fn build(inner: DynTty, path: &str) -> Result<Tee, Box<dyn Error>> {
+        Tee::build_with(inner, path, TeeOptions::default())
+    }
+
+    /// Build a new `Tee` instance with the given options.
+    pub fn build_with(
+        inner: DynTty,
+        path: &str,
+        options: TeeOptions,
+    ) -> Result<Tee, Box<dyn Error>> {
+        let file = TeeFile::open(path, options)?;
+        Ok(Tee::with_file(inner, file))
+    }
+
+    /// Build a new `Tee` instance on a file opened with [`TeeFile::open`].
+    pub fn with_file(inner: DynTty, file: TeeFile) -> Tee {
+        Tee { inner, file }
     }
 }
 
@@ -48,17 +217,20 @@ impl_any!(Tee);
 impl Tty for Tee {
     fn read(&mut self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
         let res: Vec<u8> = self.inner.read()?;
-        self.file.write_all(&res)?;
+        self.file.record_read(&res)?;
         Ok(res)
     }
     fn read_line(&mut self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
         let res: Vec<u8> = self.inner.read_line()?;
-        self.file.write_all(&res)?;
+        self.file.record_read(&res)?;
         Ok(res)
     }
     fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
         self.inner.write(data)?;
-        // self.file.write_all(data)?; // tee should not write to file, but for log purpose...
+        // The raw mode records what the Tty shows only, the echo is in there already
+        if self.file.options.transcript {
+            self.file.record_line('>', data)?;
+        }
         Ok(())
     }
 }
@@ -0,0 +1,251 @@
+//! [`Transform`] is a wrapper for [`Tty`] that adapts the data to the console.
+//!
+//! - Line ending translation, separately for both directions.
+//! - Typing delays, per character or per chunk, for boards whose UART drops
+//!   characters when a whole command comes at once.
+//! - A maximum write rate in bytes per second.
+//! - Character set conversion, for consoles which aren't UTF-8.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use std::time::Duration;
+//! # use tester::cli::shell::Shell;
+//! # use tester::cli::transform::{LineEnding, Transform, TransformOptions};
+//! # use tester::cli::tty::Tty;
+//! let s = Shell::build(Some("bash"))?;
+//! let mut t = Transform::build(
+//!     Box::new(s),
+//!     TransformOptions {
+//!         write_eol: LineEnding::CrLf,
+//!         char_delay: Duration::from_millis(5),
+//!         ..Default::default()
+//!     },
+//! );
+//! t.write(b"echo hello\n")?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::{error::Error, thread::sleep, time::Duration};
+
+use crate::impl_any;
+
+use super::tty::{DynTty, Tty, WrapperTty};
+
+/// The line ending to translate to, a line ends with `\r\n` or `\n`.
+///
+/// A lone `\r` is left alone, it's how progress bars redraw a line.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum LineEnding {
+    /// Don't translate
+    #[default]
+    Keep,
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl LineEnding {
+    fn bytes(&self) -> &'static [u8] {
+        match self {
+            LineEnding::Keep | LineEnding::Lf => b"\n",
+            LineEnding::CrLf => b"\r\n",
+            LineEnding::Cr => b"\r",
+        }
+    }
+}
+
+/// The character set of the console, the test side is always UTF-8.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Charset {
+    #[default]
+    Utf8,
+    /// ISO-8859-1, chars beyond it are written as `?`
+    Latin1,
+    /// 7-bit ASCII, other chars are written as `?` and read as U+FFFD
+    Ascii,
+}
+
+/// The options of [`Transform`], the default changes nothing.
+#[derive(Clone, Debug, Default)]
+pub struct TransformOptions {
+    /// The line ending of the data written to the console
+    pub write_eol: LineEnding,
+    /// The line ending of the data read from the console
+    pub read_eol: LineEnding,
+    /// The delay between characters written
+    pub char_delay: Duration,
+    /// Write in chunks of this many bytes, 0 for no chunking. Ignored with `char_delay`.
+    pub chunk_size: usize,
+    /// The delay between chunks
+    pub chunk_delay: Duration,
+    /// The maximum write rate in bytes per second
+    pub max_rate: Option<u32>,
+    pub charset: Charset,
+}
+
+pub struct Transform {
+    inner: DynTty,
+    options: TransformOptions,
+    /// A `\r` at the end of the last read, it may be the start of a `\r\n`
+    pending_cr: bool,
+}
+
+/// Translate the line endings, a trailing `\r` is kept in `cr` unless `flush`.
+fn translate(data: &[u8], eol: LineEnding, cr: &mut bool, flush: bool) -> Vec<u8> {
+    let mut res = Vec::with_capacity(data.len());
+    for &b in data {
+        if *cr {
+            *cr = false;
+            if b == b'\n' {
+                res.extend_from_slice(eol.bytes());
+                continue;
+            }
+            res.push(b'\r');
+        }
+        match b {
+            b'\r' => *cr = true,
+            b'\n' => res.extend_from_slice(eol.bytes()),
+            _ => res.push(b),
+        }
+    }
+    if *cr && flush {
+        *cr = false;
+        res.push(b'\r');
+    }
+    res
+}
+
+/// Split the data into the units written at once
+fn split_units(data: &[u8], size: usize, utf8: bool) -> Vec<&[u8]> {
+    let mut res = Vec::new();
+    let mut start = 0;
+    while start < data.len() {
+        let mut end = (start + size).min(data.len());
+        // Don't cut a UTF-8 char in half
+        while utf8 && end < data.len() && data[end] & 0xc0 == 0x80 {
+            end += 1;
+        }
+        res.push(&data[start..end]);
+        start = end;
+    }
+    res
+}
+
+impl Transform {
+    /// Build a new [`Transform`] instance.
+    pub fn build(inner: DynTty, options: TransformOptions) -> Transform {
+        Transform {
+            inner,
+            options,
+            pending_cr: false,
+        }
+    }
+
+    pub fn options(&self) -> &TransformOptions {
+        &self.options
+    }
+
+    pub fn options_mut(&mut self) -> &mut TransformOptions {
+        &mut self.options
+    }
+
+    fn encode(&self, data: &[u8]) -> Vec<u8> {
+        let max = match self.options.charset {
+            Charset::Utf8 => return data.to_vec(),
+            Charset::Latin1 => 0xff,
+            Charset::Ascii => 0x7f,
+        };
+        String::from_utf8_lossy(data)
+            .chars()
+            .map(|c| if (c as u32) <= max { c as u8 } else { b'?' })
+            .collect()
+    }
+
+    fn decode(&self, data: Vec<u8>) -> Vec<u8> {
+        match self.options.charset {
+            Charset::Utf8 => data,
+            Charset::Latin1 => data.iter().map(|&b| b as char).collect::<String>().into(),
+            Charset::Ascii => data
+                .iter()
+                .map(|&b| if b < 0x80 { b as char } else { '\u{fffd}' })
+                .collect::<String>()
+                .into(),
+        }
+    }
+
+    fn process_read(&mut self, data: Vec<u8>) -> Vec<u8> {
+        let data = self.decode(data);
+        if self.options.read_eol == LineEnding::Keep {
+            return data;
+        }
+        // Hold a trailing `\r` back until the next read, unless nothing more comes
+        let flush = data.is_empty();
+        translate(&data, self.options.read_eol, &mut self.pending_cr, flush)
+    }
+}
+
+impl_any!(Transform);
+
+impl Tty for Transform {
+    fn read(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let data = self.inner.read()?;
+        Ok(self.process_read(data))
+    }
+
+    fn read_line(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let data = self.inner.read_line()?;
+        Ok(self.process_read(data))
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        let data = self.encode(data);
+        let data = match self.options.write_eol {
+            LineEnding::Keep => data,
+            eol => translate(&data, eol, &mut false, true),
+        };
+
+        let opts = &self.options;
+        let (size, delay) = if !opts.char_delay.is_zero() {
+            (1, opts.char_delay)
+        } else if opts.chunk_size > 0 {
+            (opts.chunk_size, opts.chunk_delay)
+        } else if let Some(rate) = opts.max_rate {
+            // Spread the data over time instead of sending a burst then waiting
+            ((rate as usize / 50).max(1), Duration::ZERO)
+        } else {
+            (data.len().max(1), Duration::ZERO)
+        };
+        let rate = opts.max_rate.filter(|&r| r > 0);
+
+        let units = split_units(&data, size, opts.charset == Charset::Utf8);
+        let count = units.len();
+        for (i, unit) in units.into_iter().enumerate() {
+            self.inner.write(unit)?;
+            let mut wait = rate
+                .map(|r| Duration::from_secs_f64(unit.len() as f64 / r as f64))
+                .unwrap_or_default();
+            if i + 1 < count {
+                wait = wait.max(delay);
+            }
+            if !wait.is_zero() {
+                sleep(wait);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl WrapperTty for Transform {
+    fn exit(self) -> DynTty {
+        self.inner
+    }
+
+    fn inner_ref(&self) -> &DynTty {
+        &self.inner
+    }
+
+    fn inner_mut(&mut self) -> &mut DynTty {
+        &mut self.inner
+    }
+}
@@ -0,0 +1,279 @@
+//! Tunnels through an SSH session.
+//!
+//! A [`Tunnel`] pumps bytes between a local stream and a `direct-tcpip`
+//! channel of an SSH session. It's the building block for:
+//! - ProxyJump-style chained sessions, where the next hop is handshaked
+//!   over one end of a loopback socket pair, see [`super::ssh::SshConf::jump`]
+//! - Local port forwards, see [`LocalForward`]
+//!
+//! The session must be in non-blocking mode, so several tunnels and the
+//! shell channel can share one session without blocking each other.
+
+use std::{
+    error::Error,
+    io::{self, ErrorKind, Read, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread::{sleep, spawn, JoinHandle},
+    time::Duration,
+};
+
+use ssh2::{Channel, Session};
+
+use crate::{consts::TUNNEL_DURATION, err, info, log};
+
+/// Retry a libssh2 call until it doesn't return `EAGAIN` any more.
+pub(crate) fn ssh_retry<T>(
+    mut func: impl FnMut() -> Result<T, ssh2::Error>,
+) -> Result<T, Box<dyn Error>> {
+    loop {
+        match func() {
+            Ok(v) => return Ok(v),
+            Err(e) => {
+                let e = io::Error::from(e);
+                if e.kind() != ErrorKind::WouldBlock {
+                    return Err(Box::new(e));
+                }
+            }
+        }
+        sleep(Duration::from_millis(TUNNEL_DURATION));
+    }
+}
+
+/// Like [`Write::write_all`], but wait on `WouldBlock` of non-blocking streams.
+pub(crate) fn write_all<W: Write>(w: &mut W, mut data: &[u8]) -> io::Result<()> {
+    while !data.is_empty() {
+        match w.write(data) {
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(sz) => data = &data[sz..],
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                sleep(Duration::from_millis(TUNNEL_DURATION))
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Like [`io::copy`], but wait on `WouldBlock` of non-blocking streams.
+pub(crate) fn copy<R: Read, W: Write>(r: &mut R, w: &mut W) -> io::Result<u64> {
+    let mut buf = [0u8; 32768];
+    let mut total = 0;
+    loop {
+        match r.read(&mut buf) {
+            Ok(0) => return Ok(total),
+            Ok(sz) => {
+                write_all(w, &buf[..sz])?;
+                total += sz as u64;
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                sleep(Duration::from_millis(TUNNEL_DURATION))
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// A connected pair of loopback TCP streams, a portable stand-in for `socketpair`.
+pub(crate) fn socket_pair() -> io::Result<(TcpStream, TcpStream)> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let local = TcpStream::connect(listener.local_addr()?)?;
+    // Another local process may race us to the port, only take our own connection
+    loop {
+        let (remote, peer) = listener.accept()?;
+        if peer == local.local_addr()? {
+            local.set_nodelay(true)?;
+            remote.set_nodelay(true)?;
+            return Ok((local, remote));
+        }
+    }
+}
+
+/// Open a `direct-tcpip` channel to `host:port` on the remote side of `sess`.
+pub fn open_direct(sess: &Session, host: &str, port: u16) -> Result<Channel, Box<dyn Error>> {
+    ssh_retry(|| sess.channel_direct_tcpip(host, port, None))
+}
+
+/// Pump data between a local stream and an SSH channel in a background thread.
+pub struct Tunnel {
+    stop: Arc<Mutex<bool>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Tunnel {
+    /// Build a new `Tunnel` instance.
+    ///
+    /// # Arguments
+    ///
+    /// - `channel`: The SSH channel, its session must be non-blocking.
+    /// - `sock`: The local side, must be non-blocking.
+    pub fn build<S: Read + Write + Send + 'static>(mut channel: Channel, mut sock: S) -> Tunnel {
+        let stop = Arc::new(Mutex::new(false));
+        let stop_clone = stop.clone();
+        let handle = spawn(move || {
+            let mut buf = [0u8; 16384];
+            loop {
+                {
+                    let stop = stop_clone.lock().unwrap();
+                    if *stop {
+                        break;
+                    }
+                }
+                let mut idle = true;
+
+                match channel.read(&mut buf) {
+                    Ok(0) if channel.eof() => break,
+                    Ok(0) => {}
+                    Ok(sz) => {
+                        if let Err(e) = write_all(&mut sock, &buf[..sz]) {
+                            log!("Tunnel local side closed. Reason: {}", e);
+                            break;
+                        }
+                        idle = false;
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                    Err(e) => {
+                        err!("Read from tunnel channel failed. Reason: {}", e);
+                        break;
+                    }
+                }
+
+                match sock.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(sz) => {
+                        if let Err(e) = write_all(&mut channel, &buf[..sz]) {
+                            err!("Write to tunnel channel failed. Reason: {}", e);
+                            break;
+                        }
+                        idle = false;
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => {
+                        log!("Tunnel local side closed. Reason: {}", e);
+                        break;
+                    }
+                }
+
+                if idle {
+                    sleep(Duration::from_millis(TUNNEL_DURATION));
+                }
+            }
+            let _ = ssh_retry(|| channel.send_eof());
+            let _ = ssh_retry(|| channel.close());
+        });
+        Tunnel {
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    /// Whether the tunnel is still pumping data
+    pub fn is_alive(&self) -> bool {
+        self.handle.as_ref().is_some_and(|h| !h.is_finished())
+    }
+}
+
+impl Drop for Tunnel {
+    fn drop(&mut self) {
+        *self.stop.lock().unwrap() = true;
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Forward a local TCP port to `remote_host:remote_port` through an SSH session,
+/// just like `ssh -L`.
+pub struct LocalForward {
+    local: SocketAddr,
+    stop: Arc<Mutex<bool>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl LocalForward {
+    /// Build a new `LocalForward` instance.
+    ///
+    /// # Arguments
+    ///
+    /// - `sess`: The SSH session, must be non-blocking.
+    /// - `bind`: The local address to listen on, e.g. `127.0.0.1:0` for a random port.
+    /// - `remote_host`, `remote_port`: Where to connect to, seen from the remote side.
+    pub fn build(
+        sess: Session,
+        bind: &str,
+        remote_host: &str,
+        remote_port: u16,
+    ) -> Result<LocalForward, Box<dyn Error>> {
+        let listener = TcpListener::bind(bind)?;
+        listener.set_nonblocking(true)?;
+        let local = listener.local_addr()?;
+        info!(
+            "Forward {} to {}:{} through SSH",
+            local, remote_host, remote_port
+        );
+
+        let stop = Arc::new(Mutex::new(false));
+        let stop_clone = stop.clone();
+        let remote_host = remote_host.to_owned();
+        let handle = spawn(move || {
+            let mut tunnels: Vec<Tunnel> = Vec::new();
+            loop {
+                {
+                    let stop = stop_clone.lock().unwrap();
+                    if *stop {
+                        break;
+                    }
+                }
+                tunnels.retain(|t| t.is_alive());
+                match listener.accept() {
+                    Ok((sock, addr)) => {
+                        log!("Accept forward connection from {}", addr);
+                        if let Err(e) = sock.set_nonblocking(true) {
+                            err!("Failed to set forward socket nonblocking. Reason: {}", e);
+                            continue;
+                        }
+                        match open_direct(&sess, &remote_host, remote_port) {
+                            Ok(channel) => tunnels.push(Tunnel::build(channel, sock)),
+                            Err(e) => err!(
+                                "Failed to open channel to {}:{}. Reason: {}",
+                                remote_host,
+                                remote_port,
+                                e
+                            ),
+                        }
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                        sleep(Duration::from_millis(TUNNEL_DURATION * 10));
+                    }
+                    Err(e) => {
+                        err!("Accept forward connection failed. Reason: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(LocalForward {
+            local,
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    /// The local address to connect to
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local
+    }
+}
+
+impl Drop for LocalForward {
+    fn drop(&mut self) {
+        *self.stop.lock().unwrap() = true;
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
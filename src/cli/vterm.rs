@@ -0,0 +1,142 @@
+//! [`VTerm`] is a wrapper for [`Tty`] that keeps a virtual screen of the output.
+//!
+//! Full-screen programs redraw parts of the screen with escape sequences, so
+//! their byte stream can't be matched directly. [`VTerm`] feeds everything it
+//! reads through an [`Emulator`], then the screen can be asserted by text and
+//! position.
+
+use std::{
+    error::Error,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use crate::{consts::DURATION, err, impl_any, info};
+
+use super::{
+    emulator::Emulator,
+    tty::{DynTty, Tty, WrapperTty},
+};
+
+pub struct VTerm {
+    inner: DynTty,
+    emulator: Emulator,
+}
+
+impl VTerm {
+    /// Build a new [`VTerm`] instance, the size should match the remote terminal.
+    pub fn build(inner: DynTty, cols: usize, rows: usize) -> VTerm {
+        VTerm {
+            inner,
+            emulator: Emulator::new(cols, rows),
+        }
+    }
+
+    pub fn emulator(&self) -> &Emulator {
+        &self.emulator
+    }
+
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        self.emulator.resize(cols, rows);
+    }
+
+    fn process(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.emulator.feed(data);
+        let replies = self.emulator.take_replies();
+        if !replies.is_empty() {
+            self.inner.write(&replies)?;
+        }
+        Ok(())
+    }
+
+    /// Read what's available and update the screen, the data is dropped.
+    pub fn update(&mut self) -> Result<(), Box<dyn Error>> {
+        self.read()?;
+        Ok(())
+    }
+
+    /// The text of the whole screen, one line per row
+    pub fn screen_text(&self) -> String {
+        self.emulator.text()
+    }
+
+    /// Check the text at `(row, col)`, both from 0.
+    pub fn assert_text_at(&self, row: usize, col: usize, text: &str) -> Result<(), Box<dyn Error>> {
+        let actual = self.emulator.text_at(row, col, text.chars().count());
+        if actual != text {
+            err!(
+                "Text at ({}, {}) mismatch! Expected: {}, Actual: {}",
+                row,
+                col,
+                text,
+                actual
+            );
+            return Err(format!(
+                "Expected {{{}}} at ({}, {}), got {{{}}}",
+                text, row, col, actual
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Wait until the screen shows `text`, return its `(row, col)`.
+    pub fn wait_screen(
+        &mut self,
+        text: &str,
+        timeout: u32,
+    ) -> Result<(usize, usize), Box<dyn Error>> {
+        let begin = Instant::now();
+        info!("Waiting for screen text {{{}}}", text);
+        loop {
+            self.update()?;
+            if let Some(pos) = self.emulator.find(text) {
+                info!("Matched screen text {{{}}} at {:?}", text, pos);
+                return Ok(pos);
+            }
+            if begin.elapsed().as_secs() > timeout as u64 {
+                err!(
+                    "Timeout! Expected screen text: {}, Actual screen:\n{}",
+                    text,
+                    self.screen_text()
+                );
+                return Err("Timeout".into());
+            }
+            sleep(Duration::from_millis(DURATION));
+        }
+    }
+}
+
+impl_any!(VTerm);
+
+impl Tty for VTerm {
+    fn read(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let data = self.inner.read()?;
+        self.process(&data)?;
+        Ok(data)
+    }
+
+    fn read_line(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let data = self.inner.read_line()?;
+        self.process(&data)?;
+        Ok(data)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.inner.write(data)
+    }
+}
+
+impl WrapperTty for VTerm {
+    fn exit(self) -> DynTty {
+        self.inner
+    }
+
+    fn inner_ref(&self) -> &DynTty {
+        &self.inner
+    }
+
+    fn inner_mut(&mut self) -> &mut DynTty {
+        &mut self.inner
+    }
+}
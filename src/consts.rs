@@ -1,4 +1,6 @@
 pub const DURATION: u64 = 100;
 pub const SHELL_DURATION: u64 = 50;
+pub const TUNNEL_DURATION: u64 = 2;
+pub const GUI_DURATION: u64 = 100; // Default interval between two screenshots when waiting
 
-pub const SHELL_PROMPT: &str = ""; // I don't know why it doesn't echo back the prompt... Add this as a workaround
\ No newline at end of file
+pub const PROMPT_PREFIX: &str = "TESTER_PS1_"; // The prefix of the prompt set by the prompt-sync mode
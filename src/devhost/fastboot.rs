@@ -0,0 +1,183 @@
+//! A fastboot client, for flashing partitions of boards in the bootloader.
+//!
+//! Only fastboot over TCP is spoken (what `fastboot -s tcp:<host>` does):
+//! a `FB01` handshake, then packets prefixed with their big-endian length.
+//! Sparse images aren't supported, an image must fit in `max-download-size`.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use tester::devhost::fastboot::Fastboot;
+//! let mut fb = Fastboot::connect_tcp("192.168.42.1")?;
+//! println!("{}", fb.getvar("product")?);
+//! fb.flash_file("boot", "boot.img")?;
+//! fb.reboot()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::{
+    error::Error,
+    fs,
+    io::{Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use crate::{info, log};
+
+/// The default port of fastboot over TCP
+pub const FASTBOOT_PORT: u16 = 5554;
+
+/// Default timeout of a command, flashing a big partition takes a while
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Data per packet when downloading
+const DOWNLOAD_CHUNK: usize = 1 << 20;
+
+enum Response {
+    Okay(String),
+    Data(usize),
+}
+
+pub struct Fastboot {
+    stream: TcpStream,
+}
+
+impl Fastboot {
+    /// Connect to `addr`, the port defaults to [`FASTBOOT_PORT`].
+    pub fn connect_tcp(addr: &str) -> Result<Fastboot, Box<dyn Error>> {
+        let addr = if addr.contains(':') {
+            addr.to_owned()
+        } else {
+            format!("{}:{}", addr, FASTBOOT_PORT)
+        };
+        let mut stream = TcpStream::connect(&addr)?;
+        stream.set_read_timeout(Some(DEFAULT_TIMEOUT))?;
+        stream.write_all(b"FB01")?;
+        let mut reply = [0u8; 4];
+        stream.read_exact(&mut reply)?;
+        if &reply[..2] != b"FB" {
+            return Err("Not a fastboot device".into());
+        }
+        info!("Connected to fastboot at {}", addr);
+        Ok(Fastboot { stream })
+    }
+
+    /// How long to wait for the reply of a command
+    pub fn set_timeout(&mut self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        self.stream.set_read_timeout(Some(timeout))?;
+        Ok(())
+    }
+
+    fn send_packet(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.stream.write_all(&(data.len() as u64).to_be_bytes())?;
+        self.stream.write_all(data)?;
+        Ok(())
+    }
+
+    fn read_packet(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut len = [0u8; 8];
+        self.stream.read_exact(&mut len)?;
+        let mut data = vec![0u8; u64::from_be_bytes(len) as usize];
+        self.stream.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    /// Read replies until the final one, INFO and TEXT are logged.
+    fn response(&mut self) -> Result<Response, Box<dyn Error>> {
+        loop {
+            let packet = self.read_packet()?;
+            if packet.len() < 4 {
+                return Err("Bad fastboot reply".into());
+            }
+            let msg = String::from_utf8_lossy(&packet[4..]).into_owned();
+            match &packet[..4] {
+                b"OKAY" => return Ok(Response::Okay(msg)),
+                b"FAIL" => return Err(format!("fastboot: {}", msg).into()),
+                b"DATA" => return Ok(Response::Data(usize::from_str_radix(&msg, 16)?)),
+                b"INFO" => info!("fastboot: {}", msg),
+                b"TEXT" => log!("fastboot: {}", msg),
+                _ => return Err("Bad fastboot reply".into()),
+            }
+        }
+    }
+
+    /// Send a raw command, returns what follows the OKAY.
+    pub fn command(&mut self, cmd: &str) -> Result<String, Box<dyn Error>> {
+        log!("fastboot command: {}", cmd);
+        self.send_packet(cmd.as_bytes())?;
+        match self.response()? {
+            Response::Okay(msg) => Ok(msg),
+            Response::Data(_) => Err("Unexpected fastboot DATA reply".into()),
+        }
+    }
+
+    pub fn getvar(&mut self, name: &str) -> Result<String, Box<dyn Error>> {
+        self.command(&format!("getvar:{}", name))
+    }
+
+    /// Download `data` to the device, for a following flash or boot
+    pub fn download(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.send_packet(format!("download:{:08x}", data.len()).as_bytes())?;
+        match self.response()? {
+            Response::Data(len) if len == data.len() => {}
+            Response::Data(len) => {
+                return Err(format!("fastboot wants {} bytes, not {}", len, data.len()).into())
+            }
+            Response::Okay(_) => return Err("Unexpected fastboot OKAY reply".into()),
+        }
+        for chunk in data.chunks(DOWNLOAD_CHUNK) {
+            self.send_packet(chunk)?;
+        }
+        match self.response()? {
+            Response::Okay(_) => Ok(()),
+            Response::Data(_) => Err("Unexpected fastboot DATA reply".into()),
+        }
+    }
+
+    /// Write `data` to `partition`
+    pub fn flash(&mut self, partition: &str, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        if let Ok(max) = self.getvar("max-download-size") {
+            let max = max.trim_start_matches("0x");
+            if let Ok(max) = usize::from_str_radix(max, 16) {
+                if max > 0 && data.len() > max {
+                    return Err(format!(
+                        "The image is larger than max-download-size {:#x}, sparse images aren't supported",
+                        max
+                    )
+                    .into());
+                }
+            }
+        }
+        info!("fastboot: flashing {} bytes to {}", data.len(), partition);
+        self.download(data)?;
+        self.command(&format!("flash:{}", partition))?;
+        Ok(())
+    }
+
+    pub fn flash_file(&mut self, partition: &str, path: &str) -> Result<(), Box<dyn Error>> {
+        let data = fs::read(path)?;
+        self.flash(partition, &data)
+    }
+
+    pub fn erase(&mut self, partition: &str) -> Result<(), Box<dyn Error>> {
+        self.command(&format!("erase:{}", partition))?;
+        Ok(())
+    }
+
+    pub fn reboot(&mut self) -> Result<(), Box<dyn Error>> {
+        self.command("reboot")?;
+        Ok(())
+    }
+
+    pub fn reboot_bootloader(&mut self) -> Result<(), Box<dyn Error>> {
+        self.command("reboot-bootloader")?;
+        Ok(())
+    }
+
+    /// Leave fastboot and continue booting
+    pub fn continue_boot(&mut self) -> Result<(), Box<dyn Error>> {
+        self.command("continue")?;
+        Ok(())
+    }
+}
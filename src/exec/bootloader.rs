@@ -0,0 +1,283 @@
+//! Talk to bootloaders like U-Boot and GRUB. Look at [`BootloaderTester`] for more information.
+//!
+//! Bootloader consoles are not POSIX shells, so the `&& echo` trick of
+//! [`CliTester`] doesn't work there. Instead the end of each command is the
+//! next prompt, and the result is read with `echo $?`, which both U-Boot
+//! (hush parser) and GRUB understand.
+
+use std::{
+    error::Error,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    cli::tty::{DynTty, Tty, WrapperTty},
+    consts::DURATION,
+    err, impl_any, info,
+};
+
+use super::{cli_api::CliTestApi, cli_exec::CliTester};
+
+/// Ctrl-U, erase the current line in both U-Boot and GRUB
+const ERASE_LINE: &[u8] = b"\x15";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BootloaderKind {
+    UBoot,
+    Grub,
+}
+
+impl BootloaderKind {
+    pub fn default_prompt(&self) -> &'static str {
+        match self {
+            BootloaderKind::UBoot => "=> ",
+            BootloaderKind::Grub => "grub> ",
+        }
+    }
+
+    /// The key to stop autoboot. For GRUB it opens the command line from the menu.
+    pub fn default_interrupt_key(&self) -> &'static str {
+        match self {
+            BootloaderKind::UBoot => " ",
+            BootloaderKind::Grub => "c",
+        }
+    }
+
+    /// Messages meaning the command failed, even if `$?` says otherwise
+    pub fn default_error_patterns(&self) -> Vec<String> {
+        let patterns: &[&str] = match self {
+            BootloaderKind::UBoot => &["Unknown command", "## Error"],
+            BootloaderKind::Grub => &["error: "],
+        };
+        patterns.iter().map(|s| s.to_string()).collect()
+    }
+}
+
+pub struct BootloaderTester {
+    inner: CliTester,
+    kind: BootloaderKind,
+    interrupt_key: String,
+    error_patterns: Vec<String>,
+    check_status: bool,
+}
+
+impl BootloaderTester {
+    pub fn build(inner: DynTty, kind: BootloaderKind) -> BootloaderTester {
+        let mut inner = CliTester::build(inner);
+        inner.set_prompt(kind.default_prompt(), false);
+        BootloaderTester {
+            inner,
+            kind,
+            interrupt_key: kind.default_interrupt_key().to_owned(),
+            error_patterns: kind.default_error_patterns(),
+            check_status: true,
+        }
+    }
+
+    pub fn kind(&self) -> BootloaderKind {
+        self.kind
+    }
+
+    /// Use another prompt, e.g. a board with `CONFIG_SYS_PROMPT` changed.
+    pub fn set_prompt(&mut self, prompt: &str) {
+        self.inner.set_prompt(prompt, false);
+    }
+
+    /// Use another key to stop autoboot, e.g. for `CONFIG_AUTOBOOT_KEYED`.
+    pub fn set_interrupt_key(&mut self, key: &str) {
+        self.interrupt_key = key.to_owned();
+    }
+
+    /// Treat the output containing `pattern` as a failure.
+    pub fn add_error_pattern(&mut self, pattern: &str) {
+        self.error_patterns.push(pattern.to_owned());
+    }
+
+    /// See [`CliTester::add_fail_pattern`].
+    pub fn add_fail_pattern(&mut self, pattern: &str) {
+        self.inner.add_fail_pattern(pattern);
+    }
+
+    /// Whether to check `echo $?` after each command. Turn it off for U-Boot
+    /// built without the hush parser.
+    pub fn set_check_status(&mut self, check_status: bool) {
+        self.check_status = check_status;
+    }
+
+    fn prompt(&self) -> String {
+        self.inner
+            .prompt()
+            .map(|p| p.prompt.clone())
+            .unwrap_or_else(|| self.kind.default_prompt().to_owned())
+    }
+
+    /// Spam the interrupt key until the prompt shows up, then erase what's typed.
+    ///
+    /// Call this right after powering on the board.
+    pub fn interrupt_autoboot(&mut self, timeout: u32) -> Result<(), Box<dyn Error>> {
+        let prompt = self.prompt();
+        let begin = Instant::now();
+        let mut buf = Vec::new();
+        info!("Interrupt autoboot, waiting for prompt {{{}}}", prompt);
+        loop {
+            self.inner.write(self.interrupt_key.as_bytes())?;
+            sleep(Duration::from_millis(DURATION));
+            buf.extend(self.inner.read()?);
+            if buf.windows(prompt.len()).any(|w| w == prompt.as_bytes()) {
+                break;
+            }
+            if begin.elapsed().as_secs() > timeout as u64 {
+                err!(
+                    "Timeout! Autoboot not interrupted, Actual: {}",
+                    String::from_utf8_lossy(&buf)
+                );
+                return Err("Timeout".into());
+            }
+        }
+        self.inner.write(ERASE_LINE)?;
+        sleep(Duration::from_millis(DURATION));
+        self.inner.read()?;
+        info!("Autoboot interrupted");
+        Ok(())
+    }
+
+    /// Run a command, return the output and the result of `echo $?`.
+    pub fn run(&mut self, cmd: &str, timeout: u32) -> Result<(String, i32), Box<dyn Error>> {
+        let out = self.inner.script_run(cmd, timeout)?;
+        if !self.check_status {
+            return Ok((out, 0));
+        }
+        let status = self.inner.script_run("echo $?", timeout)?;
+        let status = status
+            .trim()
+            .parse::<i32>()
+            .map_err(|_| format!("Can't get the result of {{{}}}: {}", cmd, status.trim()))?;
+        Ok((out, status))
+    }
+
+    /// Get a variable, `None` if it's not defined.
+    pub fn printenv(&mut self, name: &str) -> Result<Option<String>, Box<dyn Error>> {
+        check_name(name)?;
+        match self.kind {
+            BootloaderKind::UBoot => {
+                let (out, status) = self.run(&format!("printenv {}", name), 30)?;
+                if status != 0 {
+                    return Ok(None);
+                }
+                let key = format!("{}=", name);
+                Ok(out
+                    .lines()
+                    .find_map(|l| l.trim_start().strip_prefix(&key))
+                    .map(|v| v.trim_end_matches('\r').to_owned()))
+            }
+            BootloaderKind::Grub => {
+                let (out, _) = self.run(&format!("echo \"${{{}}}\"", name), 30)?;
+                let value = out.trim_end_matches(['\r', '\n']);
+                Ok((!value.is_empty()).then(|| value.to_owned()))
+            }
+        }
+    }
+
+    /// Set a variable, or delete it if `value` is empty.
+    ///
+    /// The value is single quoted, so `${...}` in it is kept as is, e.g. for `bootcmd`.
+    pub fn setenv(&mut self, name: &str, value: &str) -> Result<(), Box<dyn Error>> {
+        check_name(name)?;
+        if value.contains('\'') {
+            return Err("Value can't contain single quotes".into());
+        }
+        let cmd = match (self.kind, value.is_empty()) {
+            (BootloaderKind::UBoot, true) => format!("setenv {}", name),
+            (BootloaderKind::UBoot, false) => format!("setenv {} '{}'", name, value),
+            (BootloaderKind::Grub, true) => format!("unset {}", name),
+            (BootloaderKind::Grub, false) => format!("set {}='{}'", name, value),
+        };
+        self.script_run(&cmd, 30)?;
+        Ok(())
+    }
+
+    /// Save the environment to the storage, U-Boot only.
+    pub fn saveenv(&mut self, timeout: u32) -> Result<(), Box<dyn Error>> {
+        if self.kind != BootloaderKind::UBoot {
+            return Err("Only U-Boot has saveenv".into());
+        }
+        self.script_run("saveenv", timeout)?;
+        Ok(())
+    }
+
+    /// Boot the system with `cmd`, or the default boot command. Won't wait for anything.
+    pub fn boot(&mut self, cmd: Option<&str>) -> Result<(), Box<dyn Error>> {
+        self.inner.writeln(cmd.unwrap_or("boot"))
+    }
+}
+
+fn check_name(name: &str) -> Result<(), Box<dyn Error>> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+    {
+        return Err(format!("Invalid variable name: {}", name).into());
+    }
+    Ok(())
+}
+
+impl_any!(BootloaderTester);
+
+impl Tty for BootloaderTester {
+    // Note: This will SKIP the logic in the tester
+    fn read(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.inner.read()
+    }
+    // Note: This will SKIP the logic in the tester
+    fn read_line(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.inner.read_line()
+    }
+    // Note: This will SKIP the logic in the tester
+    fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.inner.write(data)
+    }
+}
+
+impl WrapperTty for BootloaderTester {
+    fn exit(self) -> DynTty {
+        self.inner.exit()
+    }
+
+    fn inner_ref(&self) -> &DynTty {
+        self.inner.inner_ref()
+    }
+
+    fn inner_mut(&mut self) -> &mut DynTty {
+        self.inner.inner_mut()
+    }
+}
+
+impl CliTestApi for BootloaderTester {
+    fn wait_serial(&mut self, expected: &str, timeout: u32) -> Result<String, Box<dyn Error>> {
+        self.inner.wait_serial(expected, timeout)
+    }
+    fn script_run(&mut self, script: &str, timeout: u32) -> Result<String, Box<dyn Error>> {
+        let (out, status) = self.run(script, timeout)?;
+        if let Some(pattern) = self
+            .error_patterns
+            .iter()
+            .find(|p| out.contains(p.as_str()))
+        {
+            err!("Command {{{}}} failed, found {{{}}}", script, pattern);
+            return Err(format!("Command failed: {}", out.trim()).into());
+        }
+        if status != 0 {
+            err!("Command {{{}}} exited with status {}", script, status);
+            return Err(format!("Command exited with status {}", status).into());
+        }
+        Ok(out)
+    }
+    fn background_script_run(&mut self, _script: &str) -> Result<(), Box<dyn Error>> {
+        Err("Bootloaders can't run background commands".into())
+    }
+    fn writeln(&mut self, script: &str) -> Result<(), Box<dyn Error>> {
+        self.inner.writeln(script)
+    }
+}
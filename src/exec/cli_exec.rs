@@ -1,6 +1,7 @@
 //! The implementation of the CLI tester. Look at [`CliTestApi`] for more information.
 
 use std::{
+    collections::VecDeque,
     error::Error,
     thread::sleep,
     time::{Duration, Instant},
@@ -8,26 +9,175 @@ use std::{
 
 use crate::{
     cli::tty::{DynTty, Tty, WrapperTty},
-    consts::DURATION,
+    consts::{DURATION, PROMPT_PREFIX},
     err, impl_any, info,
     util::util::rand_string,
 };
 
 use super::cli_api::{CliTestApi, SudoCliTestApi};
 
+/// A rule to answer interactive prompts while waiting for the terminal
+///
+/// When `pattern` shows up in the output, `reply` is written back. This is
+/// checked inside [`CliTestApi::script_run`] and [`CliTestApi::wait_serial`],
+/// so password prompts, confirmations and pagers don't block the script.
+#[derive(Clone)]
+pub struct ResponseRule {
+    /// The string to look for in the output
+    pub pattern: String,
+    /// What to write back, include the `\n` if needed
+    pub reply: String,
+    /// How many times this rule may fire in one wait, `None` for no limit
+    pub max_times: Option<u32>,
+    /// Don't show the reply in the log, e.g. for passwords
+    pub secret: bool,
+}
+
+impl ResponseRule {
+    pub fn new(pattern: &str, reply: &str, max_times: Option<u32>) -> ResponseRule {
+        ResponseRule {
+            pattern: pattern.to_owned(),
+            reply: reply.to_owned(),
+            max_times,
+            secret: false,
+        }
+    }
+
+    /// Answer the sudo password prompt once per wait, so a wrong password won't loop.
+    pub fn sudo(password: &str) -> ResponseRule {
+        ResponseRule {
+            pattern: "[sudo] password for".to_owned(),
+            reply: password.to_owned() + "\n",
+            max_times: Some(1),
+            secret: true,
+        }
+    }
+
+    /// Common rules: confirm `(y/N)` and `[Y/n]` questions and page through `--More--`.
+    pub fn presets() -> Vec<ResponseRule> {
+        vec![
+            ResponseRule::new("(y/N)", "y\n", None),
+            ResponseRule::new("[y/N]", "y\n", None),
+            ResponseRule::new("[Y/n]", "y\n", None),
+            ResponseRule::new("--More--", " ", None),
+        ]
+    }
+}
+
+/// The prompt to wait for after each command, see [`CliTester::sync_prompt`]
+#[derive(Clone)]
+pub struct PromptSync {
+    /// The exact prompt printed by the console when it's idle
+    pub prompt: String,
+    /// Run `echo $?` after each command and fail if it's not 0
+    pub check_status: bool,
+}
+
+/// Treat a line longer than this as complete for the fail-on patterns
+const FAIL_LINE_MAX: usize = 4096;
+/// How many lines around a fail-on pattern to show in the error
+const FAIL_CONTEXT: usize = 5;
+
 pub struct CliTester {
     inner: DynTty,
+    responses: Vec<ResponseRule>,
+    prompt: Option<PromptSync>,
+    fail_patterns: Vec<String>,
+    fail_line: Vec<u8>,
+    fail_context: VecDeque<String>,
+    last_command: String,
 }
 
 impl CliTester {
     pub fn build(inner: DynTty) -> CliTester {
-        CliTester { inner }
+        CliTester {
+            inner,
+            responses: Vec::new(),
+            prompt: None,
+            fail_patterns: Vec::new(),
+            fail_line: Vec::new(),
+            fail_context: VecDeque::new(),
+            last_command: String::new(),
+        }
+    }
+
+    /// Fail the running command right away once `pattern` shows up in the output,
+    /// e.g. `segfault` or `Out of memory`, instead of waiting for the timeout.
+    ///
+    /// Output arriving between commands is checked by the next command.
+    pub fn add_fail_pattern(&mut self, pattern: &str) {
+        info!("Add fail-on pattern {{{}}}", pattern);
+        self.fail_patterns.push(pattern.to_owned());
+    }
+
+    /// Remove all fail-on patterns.
+    pub fn clear_fail_patterns(&mut self) {
+        self.fail_patterns.clear();
+    }
+
+    /// Set a unique prompt for the shell, and wait for it after each command.
+    ///
+    /// By default [`CliTestApi::script_run`] appends `&& echo <random>` to the
+    /// command and waits for the echo, which breaks on consoles that mangle the
+    /// echo back. With a unique `PS1` the end of each command is just the next
+    /// prompt. Works on POSIX shells, including BusyBox ash. Return the prompt.
+    pub fn sync_prompt(&mut self, timeout: u32) -> Result<String, Box<dyn Error>> {
+        let mark = rand_string(8);
+        let prompt = format!("{}{}# ", PROMPT_PREFIX, mark);
+        // Split the prompt with '' so the echo of the command itself won't match.
+        // Bracketed paste of bash wraps the output with escapes, turn it off too
+        let cmd = format!(
+            "PS1='{}''{}# '; PS2=''; unset PROMPT_COMMAND; \
+             bind 'set enable-bracketed-paste off' 2>/dev/null\n",
+            PROMPT_PREFIX, mark
+        );
+        self.run_command(&cmd)?;
+        self.do_wait_serial(&prompt, timeout, None)?;
+        info!("Prompt synced: {{{}}}", prompt);
+        self.prompt = Some(PromptSync {
+            prompt: prompt.clone(),
+            check_status: true,
+        });
+        Ok(prompt)
+    }
+
+    /// Use a known prompt instead of setting one, e.g. `=> ` for U-Boot.
+    ///
+    /// Set `check_status` only if the console understands `echo $?`.
+    pub fn set_prompt(&mut self, prompt: &str, check_status: bool) {
+        info!("Use prompt {{{}}}", prompt);
+        self.prompt = Some(PromptSync {
+            prompt: prompt.to_owned(),
+            check_status,
+        });
+    }
+
+    /// Go back to the echo mode.
+    pub fn clear_prompt(&mut self) {
+        self.prompt = None;
+    }
+
+    /// The prompt in use, `None` in the echo mode
+    pub fn prompt(&self) -> Option<&PromptSync> {
+        self.prompt.as_ref()
+    }
+
+    /// Add a rule to answer interactive prompts, see [`ResponseRule`].
+    pub fn add_response(&mut self, rule: ResponseRule) {
+        info!("Add response rule for {{{}}}", rule.pattern);
+        self.responses.push(rule);
+    }
+
+    /// Remove all response rules.
+    pub fn clear_responses(&mut self) {
+        self.responses.clear();
     }
 }
 
 impl CliTester {
     fn run_command(&mut self, command: &String) -> Result<(), Box<dyn Error>> {
         info!("Write to shell: {}", command);
+        self.last_command = command.clone();
         sleep(Duration::from_millis(DURATION));
         self.inner.write(command.as_bytes())
     }
@@ -126,18 +276,186 @@ impl CliTester {
         res
     }
 
+    /// Check the response rules against the new data, write the reply if matched.
+    ///
+    /// `tail` holds the data not yet matched, so a prompt split across reads still fires,
+    /// while a prompt already answered won't fire again.
+    fn do_responses(
+        &mut self,
+        tail: &mut Vec<u8>,
+        fired: &mut [u32],
+        data: &[u8],
+    ) -> Result<(), Box<dyn Error>> {
+        if self.responses.is_empty() {
+            return Ok(());
+        }
+        tail.extend_from_slice(data);
+        // The first match of every rule, as (start, end, rule)
+        let mut matches = Vec::new();
+        for (i, (rule, fired)) in self.responses.iter().zip(fired.iter()).enumerate() {
+            if rule.max_times.is_some_and(|x| *fired >= x) {
+                continue;
+            }
+            let pattern = rule.pattern.as_bytes();
+            if pattern.is_empty() {
+                continue;
+            }
+            if let Some(pos) = tail.windows(pattern.len()).position(|w| w == pattern) {
+                matches.push((pos, pos + pattern.len(), i));
+            }
+        }
+        // Answer in the order the prompts appeared, on a tie the earlier rule wins.
+        // A match overlapping one already answered is the same prompt, skip it.
+        matches.sort_by_key(|&(start, _, i)| (start, i));
+        let mut consumed = 0;
+        for (start, end, i) in matches {
+            if start < consumed {
+                continue;
+            }
+            consumed = end;
+            let rule = &self.responses[i];
+            fired[i] += 1;
+            if rule.secret {
+                info!("Respond to {{{}}} with ***", rule.pattern);
+            } else {
+                info!(
+                    "Respond to {{{}}} with {{{}}}",
+                    rule.pattern,
+                    rule.reply.trim_end()
+                );
+            }
+            self.inner.write(rule.reply.as_bytes())?;
+        }
+        tail.drain(..consumed);
+        let keep = self
+            .responses
+            .iter()
+            .map(|r| r.pattern.len())
+            .max()
+            .unwrap_or(1)
+            .saturating_sub(1);
+        if tail.len() > keep {
+            tail.drain(..tail.len() - keep);
+        }
+        Ok(())
+    }
+
+    /// Check the fail-on patterns against the new data, fail with the context if matched.
+    ///
+    /// Only complete lines are checked, so the echo of the command itself can be skipped.
+    fn do_fail_check(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        if self.fail_patterns.is_empty() {
+            return Ok(());
+        }
+        self.fail_line.extend_from_slice(data);
+
+        let mut lines = Vec::new();
+        while let Some(pos) = self.fail_line.iter().position(|&c| c == b'\n').or((self
+            .fail_line
+            .len()
+            >= FAIL_LINE_MAX)
+            .then_some(FAIL_LINE_MAX - 1))
+        {
+            let line: Vec<u8> = self.fail_line.drain(..=pos).collect();
+            lines.push(String::from_utf8_lossy(&line).trim_end().to_owned());
+        }
+
+        let prompt = self.prompt.as_ref().map(|p| p.prompt.as_str());
+        for (i, line) in lines.iter().enumerate() {
+            let is_echo = self
+                .last_command
+                .lines()
+                .map(|l| l.trim())
+                .any(|l| !l.is_empty() && is_command_echo(line, l, prompt));
+            let pattern = self
+                .fail_patterns
+                .iter()
+                .find(|p| !p.is_empty() && line.contains(p.as_str()));
+            if let (false, Some(pattern)) = (is_echo, pattern) {
+                let mut context: Vec<&str> = self.fail_context.iter().map(|l| l.as_str()).collect();
+                context.extend(
+                    lines
+                        .iter()
+                        .skip(i)
+                        .take(FAIL_CONTEXT + 1)
+                        .map(|l| l.as_str()),
+                );
+                let context = context.join("\n");
+                err!(
+                    "Fail-on pattern {{{}}} matched! Context:\n{}",
+                    pattern,
+                    context
+                );
+                let msg = format!("Fail-on pattern {{{}}} matched:\n{}", pattern, context);
+                self.fail_context.clear();
+                return Err(msg.into());
+            }
+            self.fail_context.push_back(line.clone());
+            if self.fail_context.len() > FAIL_CONTEXT {
+                self.fail_context.pop_front();
+            }
+        }
+        Ok(())
+    }
+
+    /// Run a command in the prompt mode, return the output between the echo and the prompt.
+    fn prompt_run(
+        &mut self,
+        sync: &PromptSync,
+        script: &str,
+        timeout: u32,
+    ) -> Result<String, Box<dyn Error>> {
+        // Drop the stale output, or an old prompt may end the command early
+        let stale = self.inner.read()?;
+        self.do_fail_check(&stale)?;
+        self.run_command(&(script.to_owned() + "\n"))?;
+        let (out, _) = self.do_wait_until(&sync.prompt, timeout, None)?;
+        let out = skip_lines(&out, script.lines().count().max(1));
+
+        if sync.check_status {
+            self.run_command(&"echo $?\n".to_owned())?;
+            let (status, _) = self.do_wait_until(&sync.prompt, timeout, None)?;
+            let status = skip_lines(&status, 1);
+            let status = status
+                .lines()
+                .map(|l| l.trim())
+                .rfind(|l| !l.is_empty())
+                .unwrap_or("");
+            if status != "0" {
+                err!("Command {{{}}} exited with status {}", script, status);
+                return Err(format!("Command exited with status {}", status).into());
+            }
+        }
+        Ok(out)
+    }
+
     fn do_wait_serial(
         &mut self,
         expected: &str,
         timeout: u32,
         filter_echo_back: Option<&str>,
     ) -> Result<String, Box<dyn Error>> {
+        let (_, res) = self.do_wait_until(expected, timeout, filter_echo_back)?;
+        Ok(res)
+    }
+
+    /// Wait for `expected`, return the output before and after it.
+    fn do_wait_until(
+        &mut self,
+        expected: &str,
+        timeout: u32,
+        filter_echo_back: Option<&str>,
+    ) -> Result<(String, String), Box<dyn Error>> {
         let begin = Instant::now();
         let mut buf = Vec::new();
+        let mut tail = Vec::new();
+        let mut fired = vec![0u32; self.responses.len()];
         info!("Waiting for string {{{}}}", expected);
         loop {
             sleep(Duration::from_millis(DURATION));
             let res = self.inner.read()?;
+            self.do_fail_check(&res)?;
+            self.do_responses(&mut tail, &mut fired, &res)?;
             buf.extend_from_slice(&res);
             if let Some(filter) = filter_echo_back {
                 self.filter_assert_echo(filter, &mut buf)?;
@@ -148,8 +466,9 @@ impl CliTester {
                 info!("Matched string {{{}}}", expected);
                 let res = buf.split_off(pos + target.len());
                 let res = String::from_utf8(res)?;
-                buf.drain(0..pos + target.len());
-                return Ok(res);
+                buf.truncate(pos);
+                let before = String::from_utf8_lossy(&buf).to_string();
+                return Ok((before, res));
             }
             if begin.elapsed().as_secs() > timeout as u64 {
                 err!(
@@ -163,11 +482,31 @@ impl CliTester {
     }
 }
 
+/// Whether `line` is the echo of the command line `cmd`: alone, as the tty echoes
+/// type-ahead, or after a prompt like `user@host:~$ `. Output which merely ends
+/// with a short command, e.g. `Error: failed in make`, isn't.
+fn is_command_echo(line: &str, cmd: &str, prompt: Option<&str>) -> bool {
+    let Some(before) = line.strip_suffix(cmd) else {
+        return false;
+    };
+    before.trim().is_empty()
+        || prompt.is_some_and(|p| before.ends_with(p))
+        || ["$ ", "# ", "> ", "% "].iter().any(|p| before.ends_with(p))
+}
+
+/// Skip the first `n` lines, i.e. the echo of the command.
+fn skip_lines(s: &str, n: usize) -> String {
+    s.splitn(n + 1, '\n').nth(n).unwrap_or("").to_owned()
+}
+
 impl CliTestApi for CliTester {
     fn wait_serial(&mut self, expected: &str, timeout: u32) -> Result<String, Box<dyn Error>> {
         self.do_wait_serial(expected, timeout, None)
     }
     fn script_run(&mut self, script: &str, timeout: u32) -> Result<String, Box<dyn Error>> {
+        if let Some(sync) = self.prompt.clone() {
+            return self.prompt_run(&sync, script, timeout);
+        }
         let mut cmd = script.to_owned();
         let echo_content_rand = rand_string(8);
 
@@ -201,6 +540,51 @@ impl SudoCliTester {
             inner: CliTester::build(inner),
         }
     }
+
+    /// Answer the sudo password prompt with the given password, see [`ResponseRule::sudo`].
+    pub fn set_sudo_password(&mut self, password: &str) {
+        self.inner.add_response(ResponseRule::sudo(password));
+    }
+
+    /// Add a rule to answer interactive prompts, see [`ResponseRule`].
+    pub fn add_response(&mut self, rule: ResponseRule) {
+        self.inner.add_response(rule);
+    }
+
+    /// Remove all response rules.
+    pub fn clear_responses(&mut self) {
+        self.inner.clear_responses();
+    }
+
+    /// See [`CliTester::add_fail_pattern`].
+    pub fn add_fail_pattern(&mut self, pattern: &str) {
+        self.inner.add_fail_pattern(pattern);
+    }
+
+    /// Remove all fail-on patterns.
+    pub fn clear_fail_patterns(&mut self) {
+        self.inner.clear_fail_patterns();
+    }
+
+    /// See [`CliTester::sync_prompt`].
+    pub fn sync_prompt(&mut self, timeout: u32) -> Result<String, Box<dyn Error>> {
+        self.inner.sync_prompt(timeout)
+    }
+
+    /// See [`CliTester::set_prompt`].
+    pub fn set_prompt(&mut self, prompt: &str, check_status: bool) {
+        self.inner.set_prompt(prompt, check_status);
+    }
+
+    /// Go back to the echo mode.
+    pub fn clear_prompt(&mut self) {
+        self.inner.clear_prompt();
+    }
+
+    /// The prompt in use, `None` in the echo mode
+    pub fn prompt(&self) -> Option<&PromptSync> {
+        self.inner.prompt()
+    }
 }
 
 impl_any!(SudoCliTester);
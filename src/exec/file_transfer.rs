@@ -0,0 +1,474 @@
+//! File transfer over a CLI console, for boards without network.
+//!
+//! The transfer is done on top of any [`CliTestApi`], that is, only the
+//! console itself is needed. Two modes are supported:
+//! - [`TransferMode::Base64`]: Chunked base64 heredocs, only needs `base64`,
+//!   `dd` and `sha256sum` on the target, which even BusyBox has.
+//! - [`TransferMode::Xmodem`]: XMODEM-1K with CRC, driving `rz`/`sz` from
+//!   lrzsz on the target. Much faster, but the console must be 8-bit clean,
+//!   so don't put a [`crate::cli::deansi::DeANSI`] in between.
+//!
+//! Every transfer is verified with the sha256 of the file afterwards, and the
+//! progress is reported to the logger.
+
+use std::{
+    error::Error,
+    fs,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sha2::{Digest, Sha256};
+
+use crate::{consts::DURATION, info, log, util::util::rand_string, warn};
+
+use super::cli_api::CliTestApi;
+
+/// How to transfer the file, see [`crate::exec::file_transfer`]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TransferMode {
+    /// Use XMODEM if `rz`/`sz` exist on the target, otherwise base64
+    Auto,
+    Base64,
+    Xmodem,
+}
+
+/// Raw bytes per heredoc, which is 4096 bytes after base64
+const CHUNK_SIZE: usize = 3072;
+/// Line width of the base64 heredoc
+const LINE_SIZE: usize = 76;
+
+const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+const CRC: u8 = b'C';
+const XMODEM_BLOCK: usize = 1024;
+const XMODEM_RETRY: u32 = 10;
+/// Seconds to wait for the sender after asking for CRC mode
+const XMODEM_START_TIMEOUT: u32 = 3;
+
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn find(buf: &[u8], target: &[u8]) -> Option<usize> {
+    buf.windows(target.len()).position(|w| w == target)
+}
+
+/// Run a command and capture its output and exit status.
+///
+/// The output is fenced by two random markers. The markers are split by `''`
+/// in the command line, so the echo of the command itself never matches.
+/// Unlike [`CliTestApi::script_run`], this returns the exit status instead of
+/// waiting for the timeout on failure.
+fn capture<T: CliTestApi + ?Sized>(
+    tty: &mut T,
+    cmd: &str,
+    timeout: u32,
+) -> Result<(Vec<u8>, i32), Box<dyn Error>> {
+    let mark = rand_string(8);
+    let begin_mark = format!("{}B", mark);
+    let end_mark = format!("{}E", mark);
+    // Keep it in one line if possible, or the echo of the command may mix
+    // into the output on shells which echo line by line
+    let sep = if cmd.contains('\n') { "\n" } else { "; " };
+    tty.writeln(&format!(
+        "echo {}''B{}{}{}echo {}''E$?",
+        mark, sep, cmd, sep, mark
+    ))?;
+
+    let begin = Instant::now();
+    let mut buf = Vec::new();
+    loop {
+        sleep(Duration::from_millis(DURATION));
+        buf.extend(tty.read()?);
+        if let Some(b) = find(&buf, begin_mark.as_bytes()) {
+            let out = &buf[b + begin_mark.len()..];
+            if let Some(e) = find(out, end_mark.as_bytes()) {
+                let tail = &out[e + end_mark.len()..];
+                let digits = tail.iter().take_while(|c| c.is_ascii_digit()).count();
+                if digits > 0 && tail.len() > digits {
+                    let status = String::from_utf8_lossy(&tail[..digits]).parse::<i32>()?;
+                    let out = out[..e].to_vec();
+                    return Ok((out, status));
+                }
+            }
+        }
+        if begin.elapsed().as_secs() > timeout as u64 {
+            return Err(format!("Timeout! Command: {}", cmd).into());
+        }
+    }
+}
+
+/// Run a command, fail if its exit status is not 0.
+fn check<T: CliTestApi + ?Sized>(
+    tty: &mut T,
+    cmd: &str,
+    timeout: u32,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let (out, status) = capture(tty, cmd, timeout)?;
+    if status != 0 {
+        return Err(format!("Command {} failed with status {}", cmd, status).into());
+    }
+    Ok(out)
+}
+
+fn has_command<T: CliTestApi + ?Sized>(tty: &mut T, cmd: &str) -> Result<bool, Box<dyn Error>> {
+    let (_, status) = capture(tty, &format!("command -v {} >/dev/null", cmd), 10)?;
+    Ok(status == 0)
+}
+
+fn remote_sha256<T: CliTestApi + ?Sized>(
+    tty: &mut T,
+    remote: &str,
+    timeout: u32,
+) -> Result<String, Box<dyn Error>> {
+    let out = check(tty, &format!("sha256sum {}", shell_quote(remote)), timeout)?;
+    let out = String::from_utf8_lossy(&out);
+    out.split_whitespace()
+        .find(|s| s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(|s| s.to_lowercase())
+        .ok_or_else(|| "Can't read sha256sum of the remote file".into())
+}
+
+fn remote_size<T: CliTestApi + ?Sized>(
+    tty: &mut T,
+    remote: &str,
+    timeout: u32,
+) -> Result<usize, Box<dyn Error>> {
+    let out = check(tty, &format!("wc -c < {}", shell_quote(remote)), timeout)?;
+    let out = String::from_utf8_lossy(&out);
+    out.split_whitespace()
+        .find_map(|s| s.parse::<usize>().ok())
+        .ok_or_else(|| "Can't read size of the remote file".into())
+}
+
+fn choose_mode<T: CliTestApi + ?Sized>(
+    tty: &mut T,
+    mode: TransferMode,
+    tool: &str,
+) -> Result<TransferMode, Box<dyn Error>> {
+    if mode != TransferMode::Auto {
+        return Ok(mode);
+    }
+    if has_command(tty, tool)? {
+        Ok(TransferMode::Xmodem)
+    } else {
+        Ok(TransferMode::Base64)
+    }
+}
+
+fn progress(what: &str, path: &str, done: usize, total: usize) {
+    let percent = (done * 100).checked_div(total).unwrap_or(100);
+    info!("{} {}: {}/{} bytes ({}%)", what, path, done, total, percent);
+}
+
+fn upload_base64<T: CliTestApi + ?Sized>(
+    tty: &mut T,
+    data: &[u8],
+    remote: &str,
+    timeout: u32,
+) -> Result<(), Box<dyn Error>> {
+    let quoted = shell_quote(remote);
+    check(tty, &format!(": > {}", quoted), timeout)?;
+    let mut done = 0;
+    for chunk in data.chunks(CHUNK_SIZE) {
+        let encoded = STANDARD.encode(chunk);
+        let mut cmd = format!("base64 -d >> {} << 'TESTER_EOF'\n", quoted);
+        for line in encoded.as_bytes().chunks(LINE_SIZE) {
+            cmd += &String::from_utf8_lossy(line);
+            cmd += "\n";
+        }
+        cmd += "TESTER_EOF";
+        check(tty, &cmd, timeout)?;
+        done += chunk.len();
+        progress("Upload", remote, done, data.len());
+    }
+    Ok(())
+}
+
+fn download_base64<T: CliTestApi + ?Sized>(
+    tty: &mut T,
+    remote: &str,
+    size: usize,
+    timeout: u32,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let quoted = shell_quote(remote);
+    let mut data = Vec::with_capacity(size);
+    let mut block = 0;
+    while data.len() < size {
+        let out = check(
+            tty,
+            &format!(
+                "dd if={} bs={} skip={} count=1 2>/dev/null | base64",
+                quoted, CHUNK_SIZE, block
+            ),
+            timeout,
+        )?;
+        let encoded: Vec<u8> = out
+            .into_iter()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == b'+' || *c == b'/' || *c == b'=')
+            .collect();
+        let chunk = STANDARD.decode(encoded)?;
+        if chunk.is_empty() {
+            return Err(format!("Unexpected end of {}", remote).into());
+        }
+        data.extend(chunk);
+        block += 1;
+        progress("Download", remote, data.len(), size);
+    }
+    Ok(data)
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for b in data {
+        crc ^= (*b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Wait for one of the `expected` bytes, other bytes are dropped.
+fn wait_byte<T: CliTestApi + ?Sized>(
+    tty: &mut T,
+    pending: &mut Vec<u8>,
+    expected: &[u8],
+    timeout: u32,
+) -> Result<u8, Box<dyn Error>> {
+    let begin = Instant::now();
+    loop {
+        if let Some(pos) = pending.iter().position(|c| expected.contains(c)) {
+            let c = pending[pos];
+            pending.drain(..=pos);
+            return Ok(c);
+        }
+        pending.clear();
+        if begin.elapsed().as_secs() > timeout as u64 {
+            return Err("Timeout! XMODEM peer doesn't response".into());
+        }
+        sleep(Duration::from_millis(DURATION));
+        pending.extend(tty.read()?);
+    }
+}
+
+/// Read exactly `len` bytes.
+fn read_exact<T: CliTestApi + ?Sized>(
+    tty: &mut T,
+    pending: &mut Vec<u8>,
+    len: usize,
+    timeout: u32,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let begin = Instant::now();
+    while pending.len() < len {
+        if begin.elapsed().as_secs() > timeout as u64 {
+            return Err("Timeout! XMODEM block incomplete".into());
+        }
+        sleep(Duration::from_millis(DURATION / 10));
+        pending.extend(tty.read()?);
+    }
+    Ok(pending.drain(..len).collect())
+}
+
+fn upload_xmodem<T: CliTestApi + ?Sized>(
+    tty: &mut T,
+    data: &[u8],
+    remote: &str,
+    timeout: u32,
+) -> Result<(), Box<dyn Error>> {
+    tty.writeln(&format!("rz -X -q {}", shell_quote(remote)))?;
+    // Drop the echo of the command, the receiver keeps asking for CRC mode
+    sleep(Duration::from_millis(DURATION * 5));
+    tty.read()?;
+
+    let mut pending = Vec::new();
+    wait_byte(tty, &mut pending, &[CRC], timeout)?;
+
+    for (i, chunk) in data.chunks(XMODEM_BLOCK).enumerate() {
+        let blk = (i + 1) as u8;
+        let mut packet = vec![STX, blk, !blk];
+        packet.extend_from_slice(chunk);
+        packet.resize(3 + XMODEM_BLOCK, 0x1A);
+        let crc = crc16(&packet[3..]);
+        packet.extend_from_slice(&crc.to_be_bytes());
+
+        let mut retry = 0;
+        loop {
+            tty.write(&packet)?;
+            match wait_byte(tty, &mut pending, &[ACK, NAK, CAN], timeout)? {
+                ACK => break,
+                CAN => return Err("XMODEM transfer cancelled by receiver".into()),
+                _ => {
+                    retry += 1;
+                    log!("XMODEM block {} NAK, retry {}", i + 1, retry);
+                    if retry > XMODEM_RETRY {
+                        tty.write(&[CAN, CAN])?;
+                        return Err("XMODEM too many retries".into());
+                    }
+                }
+            }
+        }
+        progress(
+            "Upload",
+            remote,
+            (i * XMODEM_BLOCK + chunk.len()).min(data.len()),
+            data.len(),
+        );
+    }
+
+    for _ in 0..XMODEM_RETRY {
+        tty.write(&[EOT])?;
+        if wait_byte(tty, &mut pending, &[ACK, NAK], timeout)? == ACK {
+            break;
+        }
+    }
+    // The last block is padded, cut it back
+    check(
+        tty,
+        &format!("truncate -s {} {}", data.len(), shell_quote(remote)),
+        timeout,
+    )?;
+    Ok(())
+}
+
+fn download_xmodem<T: CliTestApi + ?Sized>(
+    tty: &mut T,
+    remote: &str,
+    size: usize,
+    timeout: u32,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    tty.writeln(&format!("sz -X -q {}", shell_quote(remote)))?;
+    sleep(Duration::from_millis(DURATION * 5));
+    tty.read()?;
+
+    let mut pending = Vec::new();
+    let heads = [SOH, STX, EOT, CAN];
+    let mut head = None;
+    for i in 0..XMODEM_RETRY {
+        tty.write(&[CRC])?;
+        match wait_byte(tty, &mut pending, &heads, XMODEM_START_TIMEOUT) {
+            Ok(c) => {
+                head = Some(c);
+                break;
+            }
+            Err(_) => log!("XMODEM sender not ready, retry {}", i + 1),
+        }
+    }
+    let mut head = head.ok_or("XMODEM sender doesn't start")?;
+
+    let mut data = Vec::with_capacity(size);
+    let mut expect_blk: u8 = 1;
+    loop {
+        match head {
+            EOT => {
+                tty.write(&[ACK])?;
+                break;
+            }
+            CAN => return Err("XMODEM transfer cancelled by sender".into()),
+            _ => {}
+        }
+        let len = if head == STX { XMODEM_BLOCK } else { 128 };
+        let block = read_exact(tty, &mut pending, len + 4, timeout)?;
+        let (blk, nblk) = (block[0], block[1]);
+        let payload = &block[2..2 + len];
+        let crc = u16::from_be_bytes([block[2 + len], block[3 + len]]);
+        if blk != !nblk || crc != crc16(payload) {
+            log!("XMODEM bad block {}, NAK", blk);
+            tty.write(&[NAK])?;
+        } else {
+            if blk == expect_blk {
+                data.extend_from_slice(payload);
+                expect_blk = expect_blk.wrapping_add(1);
+                progress("Download", remote, data.len().min(size), size);
+            }
+            // A duplicated block is acked again, but dropped
+            tty.write(&[ACK])?;
+        }
+        head = wait_byte(tty, &mut pending, &heads, timeout)?;
+    }
+    data.truncate(size);
+    Ok(data)
+}
+
+/// File transfer over the console, see [`crate::exec::file_transfer`]
+///
+/// This is implemented for every [`CliTestApi`].
+pub trait FileTransferApi: CliTestApi {
+    /// Upload a local file to the target, `timeout` is for every step.
+    fn upload(
+        &mut self,
+        local: &str,
+        remote: &str,
+        mode: TransferMode,
+        timeout: u32,
+    ) -> Result<(), Box<dyn Error>> {
+        let data = fs::read(local)?;
+        let mode = choose_mode(self, mode, "rz")?;
+        info!("Upload {} to {} ({} bytes)", local, remote, data.len());
+        match mode {
+            TransferMode::Xmodem => upload_xmodem(self, &data, remote, timeout)?,
+            _ => upload_base64(self, &data, remote, timeout)?,
+        }
+        let expected = sha256_hex(&data);
+        let actual = remote_sha256(self, remote, timeout)?;
+        if expected != actual {
+            warn!("Upload {} checksum mismatch", remote);
+            return Err(format!(
+                "Checksum mismatch after upload, expected {}, got {}",
+                expected, actual
+            )
+            .into());
+        }
+        info!("Upload {} done, checksum verified", remote);
+        Ok(())
+    }
+
+    /// Download a file on the target to local, `timeout` is for every step.
+    fn download(
+        &mut self,
+        remote: &str,
+        local: &str,
+        mode: TransferMode,
+        timeout: u32,
+    ) -> Result<(), Box<dyn Error>> {
+        let mode = choose_mode(self, mode, "sz")?;
+        let size = remote_size(self, remote, timeout)?;
+        let expected = remote_sha256(self, remote, timeout)?;
+        info!("Download {} to {} ({} bytes)", remote, local, size);
+        let data = match mode {
+            TransferMode::Xmodem => download_xmodem(self, remote, size, timeout)?,
+            _ => download_base64(self, remote, size, timeout)?,
+        };
+        let actual = sha256_hex(&data);
+        if expected != actual {
+            warn!("Download {} checksum mismatch", remote);
+            return Err(format!(
+                "Checksum mismatch after download, expected {}, got {}",
+                expected, actual
+            )
+            .into());
+        }
+        fs::write(local, data)?;
+        info!("Download {} done, checksum verified", remote);
+        Ok(())
+    }
+}
+
+impl<T: CliTestApi + ?Sized> FileTransferApi for T {}
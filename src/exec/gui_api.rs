@@ -9,12 +9,19 @@ use std::error::Error;
 
 use crate::gui::screen::Screen;
 
-use super::needle::Needle;
+use super::{gui_handler::handler_api::MatchReport, needle::Needle};
 
 /// The API can used for testing, with bypass [`Screen`] operations.
 pub trait GuiTestApi: Screen {
-    /// Check if the current screen is the expected screen
-    fn assert_screen(&mut self, needle: &Needle, timeout: u32) -> Result<(), Box<dyn Error>>;
+    /// Check if the current screen is the expected screen, returns where the areas are found
+    ///
+    /// On timeout, the error is a [`super::gui_exec::ScreenMismatch`] with the closest match.
+    fn assert_screen(&mut self, needle: &Needle, timeout: u32) -> Result<MatchReport, Box<dyn Error>>;
+
+    /// Check if the current screen matches any needle with `tag`, the report has the name of the matched one
+    ///
+    /// The needles come from the [`super::needle_loader::NeedleIndex`] given to the executor.
+    fn assert_screen_tag(&mut self, tag: &str, timeout: u32) -> Result<MatchReport, Box<dyn Error>>;
 
     /// Check and click the target position
     /// 
@@ -23,8 +30,17 @@ pub trait GuiTestApi: Screen {
     fn assert_screen_click(&mut self, needle: &Needle, timeout: u32) -> Result<(), Box<dyn Error>>;
 
     /// Wait until current screen changed
+    ///
+    /// The screen is compared with the one when the call starts, not the previous
+    /// read, so a slow change like a fade is still seen. Returns as soon as the
+    /// handlers in `allow_list` see a difference, fails on timeout.
     fn wait_screen_change(&mut self, timeout: u32, allow_list: Option<&[String]>) -> Result<(), Box<dyn Error>>;
 
     /// Wait and assert the screen won't change in timeout
+    ///
+    /// The screen is compared with the one when the call starts during the whole
+    /// `timeout`, fails as soon as the handlers in `allow_list` see a difference.
+    /// Noise like dithering is not a difference, a blinking cursor is, see
+    /// [`super::gui_handler::screen_hash`].
     fn wait_still_screen(&mut self, timeout: u32, allow_list: Option<&[String]>) -> Result<(), Box<dyn Error>>;
 }
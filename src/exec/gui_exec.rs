@@ -1,26 +1,64 @@
 //! Executor for GUI. Look at [`GuiTestApi`] for more details.
 //!
 
-use std::{error::Error, time::Instant};
+use std::{
+    error::Error,
+    path::PathBuf,
+    thread::sleep,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-use image::RgbaImage;
+use image::{Rgba, RgbaImage};
 
 use crate::{
-    exec::gui_handler::{
-        basic_handle::basic_handle_once,
-        handler_api::HandlerCollector,
-    }, gui::screen::{DynScreen, Screen}, impl_any, info
+    consts::GUI_DURATION,
+    err,
+    exec::gui_handler::handler_api::{HandlerCollector, MatchReport},
+    gui::screen::{DynScreen, Screen},
+    impl_any, info,
 };
 
-use super::{gui_api::GuiTestApi, needle::Needle};
+use super::{
+    gui_api::GuiTestApi,
+    needle::{Needle, NeedleType},
+    needle_loader::NeedleIndex,
+};
 
 pub struct GuiTestor {
     inner: DynScreen,
+    needles: Option<NeedleIndex>,
+    debug_dir: PathBuf,
+    poll_interval: Duration,
 }
 
 impl GuiTestor {
     pub fn build(inner: DynScreen) -> GuiTestor {
-        GuiTestor { inner }
+        GuiTestor {
+            inner,
+            needles: None,
+            debug_dir: std::env::temp_dir(),
+            poll_interval: Duration::from_millis(GUI_DURATION),
+        }
+    }
+
+    /// How often the screen is read while waiting, default to [`GUI_DURATION`] ms
+    pub fn set_poll_interval(&mut self, interval: Duration) {
+        self.poll_interval = interval;
+    }
+
+    /// Where to save the screenshot of a failed [`GuiTestApi::assert_screen`],
+    /// default to the temporary directory
+    pub fn set_debug_dir(&mut self, dir: impl Into<PathBuf>) {
+        self.debug_dir = dir.into();
+    }
+
+    /// Set the needles to look up by tag, see [`GuiTestApi::assert_screen_tag`]
+    pub fn set_needle_index(&mut self, index: NeedleIndex) {
+        self.needles = Some(index);
+    }
+
+    pub fn needle_index(&self) -> Option<&NeedleIndex> {
+        self.needles.as_ref()
     }
 }
 
@@ -78,15 +116,145 @@ impl std::fmt::Display for TimeOutErr {
     }
 }
 
-/// Wait for a timeout and do something
+/// The screen didn't match in time, returned by [`GuiTestApi::assert_screen`]
+/// and [`GuiTestApi::assert_screen_tag`].
+///
+/// The last screenshot is saved with the expected areas drawn in: green for
+/// match, blue for ocr and gray for exclude, and in red where the best
+/// candidates of the failed areas are.
+#[derive(Debug)]
+pub struct ScreenMismatch {
+    /// The closest the screen came to match while waiting
+    pub best: Option<MatchReport>,
+    /// The saved screenshot, `None` if it couldn't be saved
+    pub screenshot: Option<PathBuf>,
+}
+
+impl Error for ScreenMismatch {}
+
+impl std::fmt::Display for ScreenMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Timeout, the screen doesn't match")?;
+        if let Some(area) = self.best.as_ref().and_then(|b| {
+            b.areas.iter().filter(|a| !a.matched).min_by(|a, b| {
+                (a.similarity - a.threshold).total_cmp(&(b.similarity - b.threshold))
+            })
+        }) {
+            write!(
+                f,
+                ", area {} came to {:.3} of {:.3} at ({}, {})",
+                area.index, area.similarity, area.threshold, area.x, area.y
+            )?;
+        }
+        if let Some(path) = &self.screenshot {
+            write!(f, ", screenshot saved to {}", path.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// How the screen matches the needle, with every handler able to handle it
+fn match_screen(needle: &Needle, screen: &RgbaImage) -> Result<MatchReport, Box<dyn Error>> {
+    let mut res: Option<MatchReport> = None;
+    for handler in inventory::iter::<HandlerCollector> {
+        if !handler.inner.can_handle(needle) {
+            continue;
+        }
+        // Each handler checks its own kind of areas, all of them must match
+        let report = handler.inner.handle(needle, screen);
+        match res.as_mut() {
+            Some(res) => res.merge(report),
+            None => res = Some(report),
+        }
+    }
+    res.ok_or_else(|| "No handler found".into())
+}
+
+fn draw_rect(img: &mut RgbaImage, (x, y, w, h): (u32, u32, u32, u32), color: Rgba<u8>) {
+    const BORDER: u32 = 2;
+    for py in y..(y + h).min(img.height()) {
+        for px in x..(x + w).min(img.width()) {
+            let inner =
+                px >= x + BORDER && py >= y + BORDER && px + BORDER < x + w && py + BORDER < y + h;
+            if !inner {
+                img.put_pixel(px, py, color);
+            }
+        }
+    }
+}
+
+/// Save `screen` with the areas of `needle` and the failed candidates of
+/// `report` drawn in.
+fn save_mismatch(
+    dir: &std::path::Path,
+    name: &str,
+    mut screen: RgbaImage,
+    needle: &Needle,
+    report: &MatchReport,
+) -> Result<PathBuf, Box<dyn Error>> {
+    if let Needle::Basic(areas) = needle {
+        for area in areas {
+            let (sx, sy) = match &area.target {
+                Some(t) => (
+                    screen.width() as f32 / t.width() as f32,
+                    screen.height() as f32 / t.height() as f32,
+                ),
+                None => (1.0, 1.0),
+            };
+            let rect = (
+                (area.x as f32 * sx) as u32,
+                (area.y as f32 * sy) as u32,
+                (area.width as f32 * sx) as u32,
+                (area.height as f32 * sy) as u32,
+            );
+            let color = match area.needle {
+                NeedleType::Match => Rgba([0, 255, 0, 255]),
+                NeedleType::Ocr => Rgba([0, 128, 255, 255]),
+                NeedleType::Exclude => Rgba([128, 128, 128, 255]),
+            };
+            draw_rect(&mut screen, rect, color);
+        }
+    }
+    for area in report.areas.iter().filter(|a| !a.matched) {
+        let rect = (area.x, area.y, area.width, area.height);
+        draw_rect(&mut screen, rect, Rgba([255, 0, 0, 255]));
+    }
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let path = dir.join(format!("{}-{}.png", name, millis));
+    screen.save(&path)?;
+    Ok(path)
+}
+
+/// Whether the screen is the same as the previous one, for every handler able to tell
+fn same_screen(
+    screen: &RgbaImage,
+    prev_screen: &RgbaImage,
+    allow_list: Option<&[String]>,
+) -> Result<bool, Box<dyn Error>> {
+    let mut res = None;
+    for handler in inventory::iter::<HandlerCollector> {
+        if !handler.inner.can_handle_change(allow_list) {
+            continue;
+        }
+        res = Some(handler.inner.handle_change(screen, prev_screen) && res.unwrap_or(true));
+    }
+    res.ok_or_else(|| "No handler found".into())
+}
+
+/// Wait for a timeout and do something, at most once per `interval`
 ///
 /// func should return `Some(T)` if the operation is done, `None` if the operation is not done yet, and `Err(E)` if an error occurred.
 fn wait_timeout_do<T>(
     timeout: u32,
+    interval: Duration,
     func: &mut dyn FnMut() -> Result<Option<T>, Box<dyn Error>>,
 ) -> Result<T, Box<dyn Error>> {
     let begin = Instant::now();
     loop {
+        let round = Instant::now();
         match func() {
             Ok(Some(v)) => return Ok(v),
             Ok(None) => {}
@@ -95,47 +263,140 @@ fn wait_timeout_do<T>(
         if begin.elapsed().as_secs() >= timeout as u64 {
             return Err(TimeOutErr.into());
         }
+        sleep(interval.saturating_sub(round.elapsed()));
+    }
+}
+
+impl GuiTestor {
+    /// Save the last screen of a failed assert, for debugging
+    fn mismatch(
+        &self,
+        name: &str,
+        needle: &Needle,
+        best: Option<MatchReport>,
+        last: Option<(RgbaImage, MatchReport)>,
+    ) -> Box<dyn Error> {
+        let screenshot = last.and_then(|(screen, report)| {
+            match save_mismatch(&self.debug_dir, name, screen, needle, &report) {
+                Ok(path) => Some(path),
+                Err(e) => {
+                    err!("Failed to save the screenshot. Reason: {}", e);
+                    None
+                }
+            }
+        });
+        let e = ScreenMismatch { best, screenshot };
+        err!("{}", e);
+        e.into()
     }
 }
 
 impl GuiTestApi for GuiTestor {
-    fn assert_screen(&mut self, needle: &Needle, timeout: u32) -> Result<(), Box<dyn Error>> {
+    fn assert_screen(
+        &mut self,
+        needle: &Needle,
+        timeout: u32,
+    ) -> Result<MatchReport, Box<dyn Error>> {
         info!("Waiting for screen...");
-        wait_timeout_do(timeout, &mut || {
+        let mut best: Option<MatchReport> = None;
+        let mut last = None;
+        let interval = self.poll_interval;
+        let res = wait_timeout_do(timeout, interval, &mut || {
             let screen = self.read()?;
-            let mut res = None;
-            for handler in inventory::iter::<HandlerCollector> {
-                if !handler.inner.can_handle(needle) {
-                    continue;
+            let report = match_screen(needle, &screen)?;
+            if report.matched {
+                return Ok(Some(report));
+            }
+            if best.as_ref().is_none_or(|b| report.score() > b.score()) {
+                best = Some(report.clone());
+            }
+            last = Some((screen, report));
+            Ok(None)
+        });
+        match res {
+            Err(e) if e.is::<TimeOutErr>() => {
+                Err(self.mismatch("assert_screen", needle, best, last))
+            }
+            res => res,
+        }
+    }
+    fn assert_screen_tag(
+        &mut self,
+        tag: &str,
+        timeout: u32,
+    ) -> Result<MatchReport, Box<dyn Error>> {
+        let needles = match &self.needles {
+            Some(index) => index.load(tag)?,
+            None => return Err("No needle index, set it first".into()),
+        };
+        info!("Waiting for screen {}...", tag);
+        let mut best: Option<MatchReport> = None;
+        let mut last: Option<(RgbaImage, MatchReport)> = None;
+        let interval = self.poll_interval;
+        let res = wait_timeout_do(timeout, interval, &mut || {
+            let screen = self.read()?;
+            let mut closest: Option<MatchReport> = None;
+            for info in &needles {
+                let mut report = match_screen(&info.needle, &screen)?;
+                report.needle = Some(info.name.clone());
+                if report.matched {
+                    return Ok(Some(report));
+                }
+                if closest.as_ref().is_none_or(|c| report.score() > c.score()) {
+                    closest = Some(report);
                 }
-                res = Some(handler.inner.handle(needle, &screen) || res.unwrap_or(false));
             }
-            match res {
-                Some(true) => Ok(Some(())),
-                Some(false) => Ok(None),
-                None => Err("No handler found".into()),
+            if let Some(closest) = closest {
+                if best.as_ref().is_none_or(|b| closest.score() > b.score()) {
+                    best = Some(closest.clone());
+                }
+                last = Some((screen, closest));
             }
-        })
+            Ok(None)
+        });
+        match res {
+            Ok(report) => {
+                info!(
+                    "Matched needle {}",
+                    report.needle.as_deref().unwrap_or_default()
+                );
+                Ok(report)
+            }
+            Err(e) if e.is::<TimeOutErr>() => {
+                // Draw the needle closest to the last screen
+                let name = last.as_ref().and_then(|(_, r)| r.needle.clone());
+                let needle = needles
+                    .iter()
+                    .find(|n| Some(&n.name) == name.as_ref())
+                    .or(needles.first())
+                    .map(|n| &n.needle);
+                match needle {
+                    Some(needle) => Err(self.mismatch(tag, needle, best, last)),
+                    None => Err(e),
+                }
+            }
+            Err(e) => Err(e),
+        }
     }
     fn assert_screen_click(&mut self, needle: &Needle, timeout: u32) -> Result<(), Box<dyn Error>> {
-        self.assert_screen(needle, timeout)?;
         if !needle.is_basic() {
             return Err("Only Basic Needle support this".into());
         }
+        let report = self.assert_screen(needle, timeout)?;
         let areas = match needle {
             Needle::Basic(areas) => areas,
             _ => unreachable!(),
         };
-        let screen = self.read()?;
-        for area in areas {
-            if area.click_point.is_none() {
-                continue;
-            }
-            if !basic_handle_once(area, &screen) {
-                continue;
-            }
-            let point = area.click_point.as_ref().unwrap();
-            self.click_left_at(point.xpos, point.ypos)?;
+        for found in &report.areas {
+            let area = &areas[found.index];
+            let point = match &area.click_point {
+                Some(point) => point,
+                None => continue,
+            };
+            // The area may be found off its position, or on a scaled screen
+            let x = found.x + point.xpos.saturating_sub(area.x) * found.width / area.width.max(1);
+            let y = found.y + point.ypos.saturating_sub(area.y) * found.height / area.height.max(1);
+            self.click_left_at(x, y)?;
         }
         Ok(())
     }
@@ -145,25 +406,13 @@ impl GuiTestApi for GuiTestor {
         allow_list: Option<&[String]>,
     ) -> Result<(), Box<dyn Error>> {
         info!("Waiting for screen change...");
-        let mut prev_screen = self.read()?;
-        wait_timeout_do(timeout, &mut || {
+        let first = self.read()?;
+        let interval = self.poll_interval;
+        wait_timeout_do(timeout, interval, &mut || {
             let screen = self.read()?;
-            let mut res = None;
-            for handler in inventory::iter::<HandlerCollector> {
-                if !handler.inner.can_handle_change(allow_list) {
-                    continue;
-                }
-                res = Some(
-                    handler.inner.handle_change(&screen, &prev_screen) || res.unwrap_or(false),
-                );
-            }
-            match res {
-                Some(true) => Ok(Some(())),
-                Some(false) => {
-                    prev_screen = screen;
-                    Ok(None)
-                }
-                None => Err("No handler found".into()),
+            match same_screen(&screen, &first, allow_list)? {
+                true => Ok(None),
+                false => Ok(Some(())),
             }
         })
     }
@@ -173,30 +422,18 @@ impl GuiTestApi for GuiTestor {
         allow_list: Option<&[String]>,
     ) -> Result<(), Box<dyn Error>> {
         info!("Waiting for still screen...");
-        let mut prev_screen = self.read()?;
-        let changed = wait_timeout_do(timeout, &mut || {
+        let first = self.read()?;
+        let interval = self.poll_interval;
+        let changed = wait_timeout_do(timeout, interval, &mut || {
             let screen = self.read()?;
-            let mut res = None;
-            for handler in inventory::iter::<HandlerCollector> {
-                if !handler.inner.can_handle_change(allow_list) {
-                    continue;
-                }
-                res = Some(
-                    handler.inner.handle_change(&screen, &prev_screen) || res.unwrap_or(false),
-                );
-            }
-            match res {
-                Some(true) => Ok(Some(())),
-                Some(false) => {
-                    prev_screen = screen;
-                    Ok(None)
-                }
-                None => Err("No handler found".into()),
+            match same_screen(&screen, &first, allow_list)? {
+                true => Ok(None),
+                false => Ok(Some(())),
             }
         });
         match changed {
             Ok(_) => Err("Screen changed".into()),
-            Err(e) if e.to_string() == "Timeout" => Ok(()),
+            Err(e) if e.is::<TimeOutErr>() => Ok(()),
             Err(e) => Err(e),
         }
     }
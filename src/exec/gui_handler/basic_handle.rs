@@ -1,6 +1,5 @@
 //! Basic handler for GUI. Which also means OpenQA compatible.
 
-
 use image::RgbaImage;
 
 use crate::exec::{
@@ -8,72 +7,68 @@ use crate::exec::{
     needle::{Area, Needle, NeedleType},
 };
 
-use super::handler_api::GuiHandler;
+use super::{
+    handler_api::{AreaReport, GuiHandler, MatchReport},
+    region_search::search_area_masked,
+    screen_hash::{ScreenHash, DEFAULT_THRESHOLD},
+};
 
 pub struct BasicHandler {}
 
-pub fn basic_handle_once(area: &Area, screen: &RgbaImage) -> bool {
-    let target = match &area.target {
-        Some(target) => target,
-        None => return false,
-    };
-    if target.width() != screen.width() || target.height() != screen.height() {
-        return false;
-    }
-    match area.needle {
-        NeedleType::Match => {
-            let mut match_count = 0;
-            let total_count = area.width * area.height;
-            for x in 0..area.width {
-                for y in 0..area.height {
-                    let x = area.x + x;
-                    let y = area.y + y;
-                    let pixel = screen.get_pixel(x, y);
-                    let target_pixel = target.get_pixel(x, y);
-                    if pixel == target_pixel {
-                        match_count += 1;
-                    }
-                }
-            }
-            let similarity = match_count as f32 / total_count as f32;
-            similarity >= area.match_threhold
-        }
-        NeedleType::Ocr => false,
-        NeedleType::Exclude => {
-            let mut match_count = 0;
-            let total_count = screen.width() * screen.height() - area.width * area.height;
-            for x in 0..screen.width() {
-                for y in 0..screen.height() {
-                    if x >= area.x
-                        && x < area.x + area.width
-                        && y >= area.y
-                        && y < area.y + area.height
-                    {
-                        continue;
-                    }
-                    let pixel = screen.get_pixel(x, y);
-                    let target_pixel = target.get_pixel(x, y);
-                    if pixel == target_pixel {
-                        match_count += 1;
-                    }
-                }
-            }
-            let similarity = match_count as f32 / total_count as f32;
-            similarity >= area.match_threhold
-        }
+/// How the area `index` matches `screen`, ignoring the pixels inside `excludes`.
+///
+/// Only match areas are checked, `None` for the others: exclude areas only
+/// mask the match areas, Ocr areas are left to [`super::ocr_handle::OcrHandler`].
+pub fn basic_handle_once(
+    index: usize,
+    area: &Area,
+    screen: &RgbaImage,
+    excludes: &[&Area],
+) -> Option<AreaReport> {
+    if area.needle != NeedleType::Match {
+        return None;
     }
+    let found = search_area_masked(area, screen, excludes);
+    let similarity = found.map_or(0.0, |m| m.similarity);
+    Some(AreaReport {
+        index,
+        kind: area.needle,
+        matched: found.is_some() && similarity >= area.match_threhold,
+        similarity,
+        threshold: area.match_threhold,
+        x: found.map_or(area.x, |m| m.x),
+        y: found.map_or(area.y, |m| m.y),
+        width: found.map_or(area.width, |m| m.width),
+        height: found.map_or(area.height, |m| m.height),
+        text: None,
+    })
+}
+
+/// The exclude areas of a needle
+pub fn exclude_areas(areas: &[Area]) -> Vec<&Area> {
+    areas
+        .iter()
+        .filter(|a| matches!(a.needle, NeedleType::Exclude))
+        .collect()
 }
 
 impl GuiHandler for BasicHandler {
     fn can_handle(&self, needle: &Needle) -> bool {
         needle.is_basic()
     }
-    fn handle(&self, needle: &Needle, screen: &RgbaImage) -> bool {
+    fn handle(&self, needle: &Needle, screen: &RgbaImage) -> MatchReport {
         let needle = match needle {
             Needle::Basic(areas) => areas,
-            _ => return false,
+            _ => return MatchReport::default(),
         };
-        needle.iter().all(|area| basic_handle_once(area, screen))
+        let excludes = exclude_areas(needle);
+        MatchReport::from_areas(
+            needle
+                .iter()
+                .enumerate()
+                .filter_map(|(i, area)| basic_handle_once(i, area, screen, &excludes))
+                .collect(),
+        )
     }
     fn can_handle_change(&self, allow_list: Option<&[String]>) -> bool {
         if let Some(allow_list) = allow_list {
@@ -82,18 +77,9 @@ impl GuiHandler for BasicHandler {
             true
         }
     }
+    /// Compare the perceptual hashes, see [`super::screen_hash`]
     fn handle_change(&self, screen: &RgbaImage, prev_screen: &RgbaImage) -> bool {
-        for x in 0..screen.width() {
-            for y in 0..screen.height() {
-                let pixel = screen.get_pixel(x, y);
-                let prev_pixel = prev_screen.get_pixel(x, y);
-                if pixel != prev_pixel {
-                    return false;
-                }
-            }
-        }
-        true
-        // screen != prev_screen
+        ScreenHash::of(screen).same(&ScreenHash::of(prev_screen), DEFAULT_THRESHOLD)
     }
 }
 
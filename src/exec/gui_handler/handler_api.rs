@@ -2,13 +2,72 @@
 
 use image::RgbaImage;
 
-use crate::exec::needle::Needle;
+use crate::exec::needle::{Needle, NeedleType};
+
+/// How one area of a needle matches the screen
+#[derive(Clone, Debug, PartialEq)]
+pub struct AreaReport {
+    /// Index of the area in the needle
+    pub index: usize,
+    pub kind: NeedleType,
+    pub matched: bool,
+    /// From 0 to 1, compared with `threshold`
+    pub similarity: f32,
+    pub threshold: f32,
+    /// Where the area is found, or its best candidate if it doesn't match,
+    /// in screen coordinates
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// The text read from an Ocr area
+    pub text: Option<String>,
+}
+
+/// How a needle matches the screen, area by area
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MatchReport {
+    /// Whether all the areas match
+    pub matched: bool,
+    /// The areas checked, by their index in the needle
+    pub areas: Vec<AreaReport>,
+    /// Name of the needle, for the needles of a [`crate::exec::needle_loader::NeedleIndex`]
+    pub needle: Option<String>,
+}
+
+impl MatchReport {
+    /// A report of `areas`, matched if all of them are
+    pub fn from_areas(areas: Vec<AreaReport>) -> MatchReport {
+        MatchReport {
+            matched: areas.iter().all(|a| a.matched),
+            areas,
+            needle: None,
+        }
+    }
+
+    /// Add the areas checked by another handler
+    pub fn merge(&mut self, other: MatchReport) {
+        self.matched &= other.matched;
+        self.areas.extend(other.areas);
+        self.areas.sort_by_key(|a| a.index);
+    }
+
+    /// How close the match is: the lowest similarity above threshold of the
+    /// areas, negative if one doesn't match.
+    pub fn score(&self) -> f32 {
+        self.areas
+            .iter()
+            .map(|a| a.similarity - a.threshold)
+            .min_by(|a, b| a.total_cmp(b))
+            .unwrap_or(if self.matched { 0.0 } else { -1.0 })
+    }
+}
 
 pub trait GuiHandler {
     /// Check if this handler can handle this type of needle
     fn can_handle(&self, needle: &Needle) -> bool;
-    /// Handle the needle, return if the screen matches the needle
-    fn handle(&self, needle: &Needle, screen: &RgbaImage) -> bool;
+    /// Handle the needle, report how the areas it checks match the screen
+    fn handle(&self, needle: &Needle, screen: &RgbaImage) -> MatchReport;
     // Check if this handler can handle screen change
     fn can_handle_change(&self, allow_list: Option<&[String]>) -> bool;
     /// Handle the screen change, return if the screen matches the previous screen
@@ -2,4 +2,7 @@
 //! 
 
 pub mod handler_api;
-pub mod basic_handle;
\ No newline at end of file
+pub mod basic_handle;
+pub mod ocr_handle;
+pub mod region_search;
+pub mod screen_hash;
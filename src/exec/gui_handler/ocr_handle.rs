@@ -0,0 +1,711 @@
+//! OCR handler for GUI, checks the text of the Ocr areas of a needle.
+//!
+//! The engine is bundled and small: the printable ASCII glyphs of the fonts of
+//! egui (Hack, monospace, and Ubuntu Light, proportional) are rendered once as
+//! templates. The area is binarized, split into lines and characters by the
+//! gaps between them, and each character is the closest template by shape,
+//! aspect and position on the line. Characters touching each other are cut
+//! where they have the least ink, if the pieces look more like characters.
+//!
+//! It reads clean, horizontal text, like consoles, installers or UI labels, in
+//! any color and at any size from about 14 pixels. Handwriting, rotated text
+//! and other scripts aren't read. Look-alikes like `l`, `1` and `|` are mixed
+//! up, so [`Area::text`] is compared with them folded, ignoring case and
+//! whitespace. Spaces are guessed from the gaps, a monospace font may get some
+//! extra. Use [`Area::regex`] to check the raw text.
+//!
+//! # Example
+//!
+//! ```
+//! # use tester::exec::gui_handler::ocr_handle::text_similarity;
+//! // What the engine may read from "Welcome, login:"
+//! let read = "WeIcome, log1n :";
+//! assert_eq!(text_similarity("login:", read), 1.0);
+//! assert!(text_similarity("Password:", read) < 0.5);
+//! ```
+
+use std::sync::OnceLock;
+
+use ab_glyph::{Font, FontArc, PxScale};
+use image::{imageops::FilterType, GrayImage, Luma, RgbaImage};
+use regex::Regex;
+
+use crate::{
+    err,
+    exec::{
+        gui_handler::handler_api::HandlerCollector,
+        needle::{Area, Needle, NeedleType},
+    },
+    log, warn,
+};
+
+use super::handler_api::{AreaReport, GuiHandler, MatchReport};
+
+/// Size of the normalized character bitmaps
+const CELL_W: u32 = 12;
+const CELL_H: u32 = 16;
+
+/// Size the templates are rendered at
+const TEMPLATE_PX: f32 = 48.0;
+
+/// Gap between characters of a word at most, by the height of the line
+const SPACE_GAP: f32 = 0.4;
+
+/// Ink at least this wide, by the height of the line, may be touching glyphs
+const SPLIT_WIDTH: f32 = 0.6;
+/// The narrowest glyph a cut may leave, by the height of the line, unless the
+/// glyphs touch by a single pixel
+const SPLIT_MIN: f32 = 0.3;
+/// How many times touching glyphs are cut at most
+const SPLIT_DEPTH: u32 = 3;
+/// Added to the score for each cut, so a glyph isn't cut into pieces that
+/// happen to look like others
+const SPLIT_PENALTY: f32 = 0.02;
+
+/// A character shape, in units of the cap height above the baseline
+struct Glyph {
+    ch: char,
+    cell: Vec<f32>,
+    aspect: f32,
+    top: f32,
+    bottom: f32,
+}
+
+/// A binary image, `true` for the ink
+struct Bitmap {
+    width: u32,
+    height: u32,
+    ink: Vec<bool>,
+}
+
+impl Bitmap {
+    fn get(&self, x: u32, y: u32) -> bool {
+        self.ink[(y * self.width + x) as usize]
+    }
+
+    /// Bounding box of the ink in the rect, `None` if there's none
+    fn ink_box(&self, x0: u32, y0: u32, x1: u32, y1: u32) -> Option<(u32, u32, u32, u32)> {
+        let mut found: Option<(u32, u32, u32, u32)> = None;
+        for y in y0..y1 {
+            for x in x0..x1 {
+                if self.get(x, y) {
+                    found = Some(match found {
+                        None => (x, y, x + 1, y + 1),
+                        Some((a, b, c, d)) => (a.min(x), b.min(y), c.max(x + 1), d.max(y + 1)),
+                    });
+                }
+            }
+        }
+        found
+    }
+
+    /// The ink in the box, scaled to a cell
+    fn cell(&self, (x0, y0, x1, y1): (u32, u32, u32, u32)) -> Vec<f32> {
+        let crop = GrayImage::from_fn(x1 - x0, y1 - y0, |x, y| {
+            Luma([if self.get(x0 + x, y0 + y) { 255 } else { 0 }])
+        });
+        image::imageops::resize(&crop, CELL_W, CELL_H, FilterType::Triangle)
+            .pixels()
+            .map(|p| p[0] as f32 / 255.0)
+            .collect()
+    }
+}
+
+/// Runs of `true` in `v`, as `[start, end)`
+fn runs(v: impl Iterator<Item = bool>) -> Vec<(u32, u32)> {
+    let mut res = Vec::new();
+    let mut start = None;
+    let mut len = 0;
+    for (i, on) in v.enumerate() {
+        match (on, start) {
+            (true, None) => start = Some(i as u32),
+            (false, Some(s)) => {
+                res.push((s, i as u32));
+                start = None;
+            }
+            _ => {}
+        }
+        len = i as u32 + 1;
+    }
+    if let Some(s) = start {
+        res.push((s, len));
+    }
+    res
+}
+
+fn render_templates() -> Vec<Glyph> {
+    let fonts = eframe::egui::FontDefinitions::default();
+    let mut glyphs = Vec::new();
+    for name in ["Hack", "Ubuntu-Light"] {
+        let font = match fonts
+            .font_data
+            .get(name)
+            .map(|data| FontArc::try_from_vec(data.font.to_vec()))
+        {
+            Some(Ok(font)) => font,
+            _ => {
+                warn!("The bundled font {} is missing, OCR won't know it", name);
+                continue;
+            }
+        };
+        let size = (TEMPLATE_PX * 2.0) as u32;
+        let baseline = TEMPLATE_PX * 1.3;
+        let render = |ch: char| -> Bitmap {
+            let mut ink = vec![false; (size * size) as usize];
+            let glyph = font.glyph_id(ch).with_scale_and_position(
+                PxScale::from(TEMPLATE_PX),
+                ab_glyph::point(8.0, baseline),
+            );
+            if let Some(outlined) = font.outline_glyph(glyph) {
+                let bounds = outlined.px_bounds();
+                outlined.draw(|x, y, v| {
+                    let x = x as i32 + bounds.min.x as i32;
+                    let y = y as i32 + bounds.min.y as i32;
+                    if v >= 0.5 && x >= 0 && y >= 0 && (x as u32) < size && (y as u32) < size {
+                        ink[(y as u32 * size + x as u32) as usize] = true;
+                    }
+                });
+            }
+            Bitmap {
+                width: size,
+                height: size,
+                ink,
+            }
+        };
+        let cap = match render('H').ink_box(0, 0, size, size) {
+            Some((_, top, _, _)) => baseline - top as f32,
+            None => continue,
+        };
+        for ch in '!'..='~' {
+            let bitmap = render(ch);
+            let Some(bbox) = bitmap.ink_box(0, 0, size, size) else {
+                continue;
+            };
+            glyphs.push(Glyph {
+                ch,
+                cell: bitmap.cell(bbox),
+                aspect: (bbox.2 - bbox.0) as f32 / (bbox.3 - bbox.1) as f32,
+                top: (baseline - bbox.1 as f32) / cap,
+                bottom: (baseline - bbox.3 as f32) / cap,
+            });
+        }
+    }
+    glyphs
+}
+
+fn templates() -> &'static [Glyph] {
+    static TEMPLATES: OnceLock<Vec<Glyph>> = OnceLock::new();
+    TEMPLATES.get_or_init(render_templates)
+}
+
+/// Separate the text from the background with Otsu's threshold, the text
+/// being the fewer pixels.
+fn binarize(image: &RgbaImage) -> Option<Bitmap> {
+    let luma: Vec<u8> = image
+        .pixels()
+        .map(|p| (0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32) as u8)
+        .collect();
+    let mut hist = [0u32; 256];
+    luma.iter().for_each(|&l| hist[l as usize] += 1);
+    let total = luma.len() as f32;
+    let sum: f32 = hist
+        .iter()
+        .enumerate()
+        .map(|(i, &c)| i as f32 * c as f32)
+        .sum();
+
+    let (mut best, mut threshold) = (0f32, None);
+    let (mut count, mut acc) = (0f32, 0f32);
+    for (i, &c) in hist.iter().enumerate() {
+        count += c as f32;
+        acc += i as f32 * c as f32;
+        if count == 0.0 || count == total {
+            continue;
+        }
+        let (m0, m1) = (acc / count, (sum - acc) / (total - count));
+        let between = count * (total - count) * (m0 - m1) * (m0 - m1);
+        if between > best {
+            best = between;
+            threshold = Some(i as u8);
+        }
+    }
+    // One color, there's no text
+    let threshold = threshold?;
+
+    let dark = luma.iter().filter(|&&l| l <= threshold).count();
+    let dark_text = dark as f32 <= total / 2.0;
+    Some(Bitmap {
+        width: image.width(),
+        height: image.height(),
+        ink: luma
+            .iter()
+            .map(|&l| (l <= threshold) == dark_text)
+            .collect(),
+    })
+}
+
+/// Rows of the lines of text, the dots of `i` and the like are kept with
+/// their line.
+fn split_lines(bitmap: &Bitmap) -> Vec<(u32, u32)> {
+    let mut lines = runs((0..bitmap.height).map(|y| (0..bitmap.width).any(|x| bitmap.get(x, y))));
+    let tallest = lines.iter().map(|(a, b)| b - a).max().unwrap_or(0);
+    let small = |(a, b): (u32, u32)| ((b - a) as f32) < 0.4 * tallest as f32;
+    let near = (0.3 * tallest as f32).max(2.0) as u32;
+    let mut i = 0;
+    while i + 1 < lines.len() {
+        let (cur, next) = (lines[i], lines[i + 1]);
+        if next.0 - cur.1 <= near && (small(cur) || small(next)) {
+            lines[i] = (cur.0, next.1);
+            lines.remove(i + 1);
+        } else {
+            i += 1;
+        }
+    }
+    lines
+}
+
+/// Where the glyphs sit on a line of text
+struct LineMetrics {
+    baseline: u32,
+    cap: f32,
+}
+
+/// The template closest to the ink in `bbox`, with how far it is
+fn classify(
+    bitmap: &Bitmap,
+    bbox: (u32, u32, u32, u32),
+    line: &LineMetrics,
+    glyphs: &[Glyph],
+) -> Option<(char, f32)> {
+    let cell = bitmap.cell(bbox);
+    let aspect = (bbox.2 - bbox.0) as f32 / (bbox.3 - bbox.1) as f32;
+    let top = (line.baseline as f32 - bbox.1 as f32) / line.cap;
+    let bottom = (line.baseline as f32 - bbox.3 as f32) / line.cap;
+    let score = |g: &Glyph| {
+        let shape = g
+            .cell
+            .iter()
+            .zip(&cell)
+            .map(|(a, b)| (a - b).abs())
+            .sum::<f32>()
+            / cell.len() as f32;
+        shape
+            + 0.3 * (aspect / g.aspect).ln().abs().min(2.0)
+            + 0.5 * ((top - g.top).abs() + (bottom - g.bottom).abs())
+    };
+    glyphs
+        .iter()
+        .map(|g| (g.ch, score(g)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+/// A character read, with how far it is from its template
+struct Piece {
+    bbox: (u32, u32, u32, u32),
+    ch: char,
+    score: f32,
+}
+
+/// How bad a reading is, the mean score of its glyphs with a penalty per cut
+fn cost(pieces: &[Piece]) -> f32 {
+    let n = pieces.len().max(1) as f32;
+    pieces.iter().map(|p| p.score).sum::<f32>() / n + SPLIT_PENALTY * (n - 1.0)
+}
+
+/// Read the ink between columns `x0` and `x1` as one glyph, or as several if
+/// it's wide enough to be touching glyphs and they fit the templates better.
+///
+/// They are cut at the columns with the least ink, at most `depth` times.
+fn segment(
+    bitmap: &Bitmap,
+    (x0, x1): (u32, u32),
+    (y0, y1): (u32, u32),
+    line: &LineMetrics,
+    glyphs: &[Glyph],
+    depth: u32,
+) -> Vec<Piece> {
+    let Some(bbox) = bitmap.ink_box(x0, y0, x1, y1) else {
+        return Vec::new();
+    };
+    let Some((ch, score)) = classify(bitmap, bbox, line, glyphs) else {
+        return Vec::new();
+    };
+    let mut best = vec![Piece { bbox, ch, score }];
+    let min_width = (SPLIT_MIN * line.cap).max(1.0) as usize;
+    if depth == 0 || ((bbox.2 - bbox.0) as f32) < SPLIT_WIDTH * line.cap {
+        return best;
+    }
+
+    let ink: Vec<usize> = (bbox.0..bbox.2)
+        .map(|x| (y0..y1).filter(|&y| bitmap.get(x, y)).count())
+        .collect();
+    let inner = min_width..ink.len().saturating_sub(min_width);
+    let least = ink.get(inner.clone()).and_then(|v| v.iter().min().copied());
+    // Glyphs touching by a single pixel, a narrow one may be cut off too
+    let cuts = (2..ink.len().saturating_sub(1)).filter(|&i| {
+        ink[i] == 1 || (inner.contains(&i) && least.is_some_and(|least| ink[i] <= least + 1))
+    });
+    for i in cuts {
+        let cut = bbox.0 + i as u32;
+        // The cut column is shared by the touching glyphs, give it to none
+        let mut pieces = segment(bitmap, (bbox.0, cut), (y0, y1), line, glyphs, depth - 1);
+        let right = segment(bitmap, (cut + 1, bbox.2), (y0, y1), line, glyphs, depth - 1);
+        if pieces.is_empty() || right.is_empty() {
+            continue;
+        }
+        pieces.extend(right);
+        if cost(&pieces) < cost(&best) {
+            best = pieces;
+        }
+    }
+    best
+}
+
+fn read_line(bitmap: &Bitmap, (y0, y1): (u32, u32), glyphs: &[Glyph]) -> String {
+    let columns = runs((0..bitmap.width).map(|x| (y0..y1).any(|y| bitmap.get(x, y))));
+    let boxes: Vec<(u32, u32, u32, u32)> = columns
+        .iter()
+        .filter_map(|&(x0, x1)| bitmap.ink_box(x0, y0, x1, y1))
+        .collect();
+    if boxes.is_empty() {
+        return String::new();
+    }
+
+    // Most characters sit on the baseline, the tallest of them gives the cap height
+    let mut bottoms: Vec<u32> = boxes.iter().map(|b| b.3).collect();
+    bottoms.sort();
+    let baseline = bottoms
+        .iter()
+        .max_by_key(|&&b| bottoms.iter().filter(|&&o| o.abs_diff(b) <= 1).count())
+        .copied()
+        .unwrap_or(y1);
+    let cap = boxes
+        .iter()
+        .filter(|b| b.3.abs_diff(baseline) <= 1)
+        .map(|b| b.3 - b.1)
+        .max()
+        .unwrap_or(y1 - y0)
+        .max(1) as f32;
+    let line = LineMetrics { baseline, cap };
+
+    let mut text = String::new();
+    let mut prev_end = None;
+    for bbox in boxes {
+        let pieces = segment(
+            bitmap,
+            (bbox.0, bbox.2),
+            (y0, y1),
+            &line,
+            glyphs,
+            SPLIT_DEPTH,
+        );
+        for piece in pieces {
+            let gap = prev_end.map(|end| piece.bbox.0.saturating_sub(end));
+            if gap.is_some_and(|gap| gap as f32 > SPACE_GAP * cap) {
+                text.push(' ');
+            }
+            prev_end = Some(piece.bbox.2);
+            text.push(piece.ch);
+        }
+    }
+    text
+}
+
+/// Read the text of `image`, the lines are joined with `\n`.
+pub fn recognize(image: &RgbaImage) -> String {
+    let bitmap = match binarize(image) {
+        Some(bitmap) => bitmap,
+        None => return String::new(),
+    };
+    split_lines(&bitmap)
+        .into_iter()
+        .map(|line| read_line(&bitmap, line, templates()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Fold the characters the engine mixes up, and drop the whitespace
+fn fold(text: &str) -> Vec<char> {
+    text.chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(|c| c.to_lowercase())
+        .map(|c| match c {
+            '1' | '|' | 'i' | '!' => 'l',
+            '0' => 'o',
+            '5' => 's',
+            _ => c,
+        })
+        .collect()
+}
+
+/// How well `expected` is found in `text`, from 0 to 1, by the edit distance
+/// to its closest substring.
+pub fn text_similarity(expected: &str, text: &str) -> f32 {
+    let (expected, text) = (fold(expected), fold(text));
+    if expected.is_empty() {
+        return 1.0;
+    }
+    // Edit distance where the match may start and end anywhere in the text
+    let mut prev: Vec<usize> = (0..=expected.len()).collect();
+    let mut best = prev[expected.len()];
+    for t in &text {
+        let mut cur = vec![0; expected.len() + 1];
+        for (j, e) in expected.iter().enumerate() {
+            let replace = prev[j] + (e != t) as usize;
+            cur[j + 1] = replace.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        best = best.min(cur[expected.len()]);
+        prev = cur;
+    }
+    1.0 - best as f32 / expected.len() as f32
+}
+
+/// The area on the screen, scaled if the needle is of another size
+fn screen_rect(area: &Area, screen: &RgbaImage) -> (u32, u32, u32, u32) {
+    let (sx, sy) = match &area.target {
+        Some(target) => (
+            screen.width() as f32 / target.width() as f32,
+            screen.height() as f32 / target.height() as f32,
+        ),
+        None => (1.0, 1.0),
+    };
+    let x = ((area.x as f32 * sx) as u32).min(screen.width());
+    let y = ((area.y as f32 * sy) as u32).min(screen.height());
+    let width = ((area.width as f32 * sx) as u32).min(screen.width() - x);
+    let height = ((area.height as f32 * sy) as u32).min(screen.height() - y);
+    (x, y, width, height)
+}
+
+/// How the text of the Ocr area `index` matches what it expects, `None` for
+/// the other areas.
+///
+/// The similarity is the one of [`Area::text`], or 1 if only [`Area::regex`]
+/// is given and matches. An area expecting neither only reads, it always
+/// matches.
+pub fn ocr_handle_once(index: usize, area: &Area, screen: &RgbaImage) -> Option<AreaReport> {
+    if area.needle != NeedleType::Ocr {
+        return None;
+    }
+    let (x, y, width, height) = screen_rect(area, screen);
+    let text = if width > 0 && height > 0 {
+        recognize(&image::imageops::crop_imm(screen, x, y, width, height).to_image())
+    } else {
+        String::new()
+    };
+    log!("OCR read {:?} at ({}, {})", text, x, y);
+
+    let mut similarity = match &area.text {
+        Some(expected) => text_similarity(expected, &text),
+        None => 1.0,
+    };
+    let mut matched = similarity >= area.match_threhold;
+    if let Some(regex) = &area.regex {
+        let found = match Regex::new(regex) {
+            Ok(re) => re.is_match(&text),
+            Err(e) => {
+                err!("Bad regex {} of an Ocr area. Reason: {}", regex, e);
+                false
+            }
+        };
+        if !found {
+            matched = false;
+            if area.text.is_none() {
+                similarity = 0.0;
+            }
+        }
+    }
+    Some(AreaReport {
+        index,
+        kind: area.needle,
+        matched,
+        similarity,
+        threshold: area.match_threhold,
+        x,
+        y,
+        width,
+        height,
+        text: Some(text),
+    })
+}
+
+pub struct OcrHandler {}
+
+impl GuiHandler for OcrHandler {
+    fn can_handle(&self, needle: &Needle) -> bool {
+        match needle {
+            Needle::Basic(areas) => areas.iter().any(|a| matches!(a.needle, NeedleType::Ocr)),
+            _ => false,
+        }
+    }
+    fn handle(&self, needle: &Needle, screen: &RgbaImage) -> MatchReport {
+        let areas = match needle {
+            Needle::Basic(areas) => areas,
+            _ => return MatchReport::default(),
+        };
+        MatchReport::from_areas(
+            areas
+                .iter()
+                .enumerate()
+                .filter_map(|(i, area)| ocr_handle_once(i, area, screen))
+                .collect(),
+        )
+    }
+    /// Reading text tells nothing about a screen change
+    fn can_handle_change(&self, _allow_list: Option<&[String]>) -> bool {
+        false
+    }
+    fn handle_change(&self, _screen: &RgbaImage, _prev_screen: &RgbaImage) -> bool {
+        false
+    }
+}
+
+inventory::submit! {
+    HandlerCollector {
+        inner: &OcrHandler {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ab_glyph::ScaleFont;
+    use image::Rgba;
+
+    use super::*;
+
+    const LINES: [&str; 4] = [
+        "login:",
+        "Password:",
+        "root@board:~#",
+        "Welcome to Ubuntu 22.04",
+    ];
+
+    fn font(name: &str) -> FontArc {
+        let fonts = eframe::egui::FontDefinitions::default();
+        FontArc::try_from_vec(fonts.font_data[name].font.to_vec()).unwrap()
+    }
+
+    /// Draw `text` light on dark at `(x, y)`, the top left of the line
+    fn draw(screen: &mut RgbaImage, font: &FontArc, px: f32, text: &str, (x, y): (f32, f32)) {
+        let scaled = font.as_scaled(PxScale::from(px));
+        let mut caret = ab_glyph::point(x, y + scaled.ascent());
+        let mut prev = None;
+        for ch in text.chars() {
+            let id = font.glyph_id(ch);
+            if let Some(prev) = prev {
+                caret.x += scaled.kern(prev, id);
+            }
+            let glyph = id.with_scale_and_position(PxScale::from(px), caret);
+            caret.x += scaled.h_advance(id);
+            prev = Some(id);
+            let Some(outlined) = font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outlined.px_bounds();
+            outlined.draw(|gx, gy, v| {
+                let (px, py) = (gx + bounds.min.x as u32, gy + bounds.min.y as u32);
+                if px < screen.width() && py < screen.height() {
+                    let c = (24.0 + v * (230.0 - 24.0)) as u8;
+                    screen.put_pixel(px, py, Rgba([c, c, c, 255]));
+                }
+            });
+        }
+    }
+
+    fn dark(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, Rgba([24, 24, 24, 255]))
+    }
+
+    fn area(x: u32, y: u32, width: u32, height: u32) -> Area {
+        Area {
+            x,
+            y,
+            width,
+            height,
+            needle: NeedleType::Ocr,
+            match_threhold: 0.8,
+            click_point: None,
+            margin: None,
+            tolerance: None,
+            text: None,
+            regex: None,
+            target: None,
+        }
+    }
+
+    #[test]
+    fn test_text_similarity() {
+        assert_eq!(text_similarity("login:", "Ubuntu login:"), 1.0);
+        assert_eq!(text_similarity("Login :", "l0gin:"), 1.0);
+        assert_eq!(text_similarity("", "anything"), 1.0);
+        assert!(text_similarity("Password:", "Passwrd:") > 0.85);
+        assert!(text_similarity("Password:", "login:") < 0.5);
+    }
+
+    #[test]
+    fn test_recognize() {
+        for name in ["Hack", "Ubuntu-Light"] {
+            let font = font(name);
+            for px in [14.0, 20.0, 32.0] {
+                // At a whole and a half pixel, the glyphs touch differently
+                for (line, x) in LINES.iter().flat_map(|l| [(l, 12.0), (l, 12.5)]) {
+                    let mut screen = dark((px * line.len() as f32) as u32 + 32, px as u32 * 2);
+                    draw(&mut screen, &font, px, line, (x, px / 2.0));
+                    let text = recognize(&screen);
+                    let similarity = text_similarity(line, &text);
+                    assert!(
+                        similarity >= 0.85,
+                        "{} at {}px, x {}: {:?} read as {:?}, {}",
+                        name,
+                        px,
+                        x,
+                        line,
+                        text,
+                        similarity
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_ocr_area() {
+        let font = font("Hack");
+        let mut screen = dark(640, 120);
+        draw(
+            &mut screen,
+            &font,
+            20.0,
+            "Welcome to Ubuntu 22.04",
+            (16.0, 10.0),
+        );
+        draw(&mut screen, &font, 20.0, "root@board:~#", (16.0, 70.0));
+
+        let mut prompt = area(0, 60, 320, 40);
+        prompt.regex = Some(r"root@\w+:~#".to_owned());
+        let report = ocr_handle_once(3, &prompt, &screen).unwrap();
+        assert!(report.matched, "read {:?}", report.text);
+        assert_eq!(report.index, 3);
+        assert_eq!(report.similarity, 1.0);
+
+        // Only the text inside the area is read
+        let mut welcome = area(0, 60, 320, 40);
+        welcome.regex = Some("Welcome".to_owned());
+        let report = ocr_handle_once(0, &welcome, &screen).unwrap();
+        assert!(!report.matched);
+        assert_eq!(report.similarity, 0.0);
+
+        // Both the text and the regex must match
+        let mut both = area(0, 0, 640, 50);
+        both.text = Some("Welcome to Ubuntu".to_owned());
+        both.regex = Some(r"\d+\.\d+".to_owned());
+        let report = ocr_handle_once(0, &both, &screen).unwrap();
+        assert!(report.matched, "read {:?}", report.text);
+        both.regex = Some("Fedora".to_owned());
+        assert!(!ocr_handle_once(0, &both, &screen).unwrap().matched);
+
+        // Other areas are left to the other handlers
+        let mut other = area(0, 0, 10, 10);
+        other.needle = NeedleType::Match;
+        assert!(ocr_handle_once(0, &other, &screen).is_none());
+    }
+}
@@ -0,0 +1,376 @@
+//! Find a needle area on the screen, tolerating offsets and small color changes.
+//!
+//! Like the `margin` of openQA, the area is searched within `margin` pixels
+//! around where the needle expects it. The best position is the one with the
+//! highest normalized cross-correlation of the luminance, which doesn't care
+//! about a global brightness change. It's then scored by the ratio of pixels
+//! whose channels are all within the color tolerance, which is what the
+//! match threshold is compared with.
+//!
+//! A screen of another size than the needle is scaled to the needle first,
+//! only the searched region of it, the result is mapped back to screen
+//! coordinates.
+//!
+//! Searching is kept cheap on big screens: the pixels are read as raw rows,
+//! an area exactly at its position is taken without searching, and a large
+//! area is first searched on a downscaled copy, then refined around the best
+//! few candidates.
+//!
+//! Like openQA, the exclude areas of a needle are ignored inside the match
+//! areas, e.g. a clock in a panel. They're in needle coordinates, so they move
+//! with the area when it's found off its position.
+
+use image::{imageops::FilterType, RgbaImage};
+
+use crate::exec::needle::Area;
+
+/// The default margin of openQA, in pixels
+pub const DEFAULT_MARGIN: u32 = 50;
+
+/// The default per channel color difference still counted as the same
+pub const DEFAULT_TOLERANCE: u8 = 32;
+
+/// An area is searched downscaled first if it's at least this large downscaled
+const COARSE_MIN: usize = 16;
+
+/// The downscaled positions refined: the best few, and the ones scoring
+/// almost as well as the best, e.g. along a long edge, up to a limit
+const COARSE_CANDIDATES: usize = 5;
+const COARSE_SLACK: f32 = 0.05;
+const COARSE_MAX: usize = 64;
+
+/// Where an area is found on the screen
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AreaMatch {
+    /// Top left corner of the found area, in screen coordinates
+    pub x: u32,
+    pub y: u32,
+    /// Size of the found area, in screen coordinates
+    pub width: u32,
+    pub height: u32,
+    /// Ratio of pixels within the color tolerance, from 0 to 1
+    pub similarity: f32,
+    /// Offset from the expected position, in needle coordinates
+    pub offset: (i32, i32),
+    /// Screen size divided by needle size
+    pub scale: (f32, f32),
+}
+
+impl AreaMatch {
+    /// Map a point of the needle, e.g. a click point, to the screen
+    pub fn map_point(&self, x: u32, y: u32) -> (u32, u32) {
+        let x = (x as i32 + self.offset.0).max(0) as f32 * self.scale.0;
+        let y = (y as i32 + self.offset.1).max(0) as f32 * self.scale.1;
+        (x as u32, y as u32)
+    }
+}
+
+fn luma(p: &[u8]) -> f32 {
+    0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32
+}
+
+fn in_area(area: &Area, x: u32, y: u32) -> bool {
+    x >= area.x && x < area.x + area.width && y >= area.y && y < area.y + area.height
+}
+
+/// The rows of the `w` x `h` window of `img` at `(x, y)`, as raw RGBA
+fn rows(img: &RgbaImage, x: u32, y: u32, w: u32, h: u32) -> impl Iterator<Item = &[u8]> {
+    let stride = img.width() as usize * 4;
+    let raw = img.as_raw();
+    (y..y + h).map(move |row| {
+        let start = row as usize * stride + x as usize * 4;
+        &raw[start..start + w as usize * 4]
+    })
+}
+
+/// Shrink `values`, `w` x `h`, by `f` with the mean of each `f` x `f` cell
+fn downscale(values: &[f32], w: usize, h: usize, f: usize) -> Vec<f32> {
+    let (cw, ch) = (w / f, h / f);
+    let mut res = vec![0f32; cw * ch];
+    for y in 0..ch * f {
+        let row = &values[y * w..y * w + cw * f];
+        let out = &mut res[(y / f) * cw..(y / f + 1) * cw];
+        for (cell, o) in row.chunks_exact(f).zip(out.iter_mut()) {
+            *o += cell.iter().sum::<f32>();
+        }
+    }
+    let n = (f * f) as f32;
+    res.iter_mut().for_each(|v| *v /= n);
+    res
+}
+
+/// The luminance of a needle area, to correlate with the screen
+struct Template {
+    w: usize,
+    h: usize,
+    /// Centered, zero where excluded
+    values: Vec<f32>,
+    mask: Vec<bool>,
+    /// Nothing is excluded
+    full: bool,
+    mean: f32,
+    norm: f32,
+    n: f32,
+    /// No texture to correlate
+    flat: bool,
+}
+
+impl Template {
+    fn new(mut values: Vec<f32>, mask: Vec<bool>, w: usize, h: usize) -> Template {
+        let n = mask.iter().filter(|&&m| m).count().max(1) as f32;
+        let mean = values
+            .iter()
+            .zip(&mask)
+            .filter(|(_, &m)| m)
+            .map(|(v, _)| v)
+            .sum::<f32>()
+            / n;
+        values
+            .iter_mut()
+            .zip(&mask)
+            .for_each(|(v, &m)| *v = if m { *v - mean } else { 0.0 });
+        let norm = values.iter().map(|v| v * v).sum::<f32>().sqrt();
+        Template {
+            w,
+            h,
+            full: mask.iter().all(|&m| m),
+            values,
+            mask,
+            mean,
+            norm,
+            n,
+            flat: norm < 1e-3 * n.sqrt(),
+        }
+    }
+
+    /// Shrink by `f`, a cell is compared if all its pixels are
+    fn downscale(luma: &[f32], mask: &[bool], w: usize, h: usize, f: usize) -> Template {
+        let (cw, ch) = (w / f, h / f);
+        let mut cmask = vec![true; cw * ch];
+        for y in 0..ch * f {
+            for x in 0..cw * f {
+                if !mask[y * w + x] {
+                    cmask[(y / f) * cw + x / f] = false;
+                }
+            }
+        }
+        Template::new(downscale(luma, w, h, f), cmask, cw, ch)
+    }
+
+    /// Normalized cross-correlation with the window of `region`, `rw` wide, at `(ox, oy)`
+    fn score(&self, region: &[f32], rw: usize, ox: usize, oy: usize) -> f32 {
+        let (w, mean) = (self.w, self.mean);
+        let (mut sum, mut sum2, mut cross, mut diff) = (0f32, 0f32, 0f32, 0f32);
+        let mut add = |s: f32, nv: f32| {
+            sum += s;
+            sum2 += s * s;
+            cross += s * nv;
+            diff += (s - (nv + mean)).abs();
+        };
+        for dy in 0..self.h {
+            let row = &region[(oy + dy) * rw + ox..(oy + dy) * rw + ox + w];
+            let nrow = &self.values[dy * w..dy * w + w];
+            if self.full {
+                row.iter().zip(nrow).for_each(|(&s, &nv)| add(s, nv));
+            } else {
+                let mrow = &self.mask[dy * w..dy * w + w];
+                row.iter()
+                    .zip(nrow)
+                    .zip(mrow)
+                    .filter(|(_, &m)| m)
+                    .for_each(|((&s, &nv), _)| add(s, nv));
+            }
+        }
+        if self.flat {
+            // No texture to correlate, compare the brightness instead
+            return 1.0 - diff / self.n / 255.0;
+        }
+        let var = (sum2 - sum * sum / self.n).max(0.0);
+        if var < 1e-6 {
+            return 0.0;
+        }
+        cross / (self.norm * var.sqrt())
+    }
+}
+
+/// Ratio of compared pixels of `needle` and `screen` within `tolerance`
+fn tolerance_ratio<'a>(
+    needle: impl Iterator<Item = &'a [u8]>,
+    screen: impl Iterator<Item = &'a [u8]>,
+    w: usize,
+    tolerance: u8,
+    mask: &[bool],
+) -> f32 {
+    let mut same = 0u32;
+    for ((a, b), m) in needle.zip(screen).zip(mask.chunks_exact(w)) {
+        for ((a, b), _) in a
+            .chunks_exact(4)
+            .zip(b.chunks_exact(4))
+            .zip(m)
+            .filter(|(_, &m)| m)
+        {
+            if (0..3).all(|c| a[c].abs_diff(b[c]) <= tolerance) {
+                same += 1;
+            }
+        }
+    }
+    let total = mask.iter().filter(|&&m| m).count();
+    if total == 0 {
+        // All excluded, nothing can differ
+        return 1.0;
+    }
+    same as f32 / total as f32
+}
+
+/// Search the area around its expected position, `None` if the area has no
+/// target or doesn't fit in it.
+pub fn search_area(area: &Area, screen: &RgbaImage) -> Option<AreaMatch> {
+    search_area_masked(area, screen, &[])
+}
+
+/// Like [`search_area`], ignoring the pixels inside `excludes`.
+pub fn search_area_masked(
+    area: &Area,
+    screen: &RgbaImage,
+    excludes: &[&Area],
+) -> Option<AreaMatch> {
+    let target = area.target.as_ref()?;
+    let (tw, th) = target.dimensions();
+    if area.width == 0 || area.height == 0 || area.x + area.width > tw || area.y + area.height > th
+    {
+        return None;
+    }
+    let scale = (
+        screen.width() as f32 / tw as f32,
+        screen.height() as f32 / th as f32,
+    );
+
+    let margin = area.margin.unwrap_or(DEFAULT_MARGIN);
+    let tolerance = area.tolerance.unwrap_or(DEFAULT_TOLERANCE);
+    let (w, h) = (area.width as usize, area.height as usize);
+    let x0 = area.x.saturating_sub(margin);
+    let y0 = area.y.saturating_sub(margin);
+    let x1 = (area.x + margin).min(tw - area.width);
+    let y1 = (area.y + margin).min(th - area.height);
+    let (rw, rh) = (x1 - x0 + area.width, y1 - y0 + area.height);
+
+    // The searched region, at the scale of the needle, and where it is in it
+    let scaled;
+    let (region_img, rx, ry) = if screen.dimensions() == target.dimensions() {
+        (screen, x0, y0)
+    } else {
+        let sx = ((x0 as f32 * scale.0) as u32).min(screen.width().saturating_sub(1));
+        let sy = ((y0 as f32 * scale.1) as u32).min(screen.height().saturating_sub(1));
+        let sw = ((rw as f32 * scale.0).round() as u32).clamp(1, screen.width() - sx);
+        let sh = ((rh as f32 * scale.1).round() as u32).clamp(1, screen.height() - sy);
+        let crop = image::imageops::crop_imm(screen, sx, sy, sw, sh).to_image();
+        scaled = image::imageops::resize(&crop, rw, rh, FilterType::Triangle);
+        (&scaled, 0, 0)
+    };
+
+    // Pixels of the area compared, the excluded ones are skipped
+    let mut mask = Vec::with_capacity(w * h);
+    for dy in 0..area.height {
+        for dx in 0..area.width {
+            let (x, y) = (area.x + dx, area.y + dy);
+            mask.push(!excludes.iter().any(|e| in_area(e, x, y)));
+        }
+    }
+
+    let ratio_at = |x: u32, y: u32| {
+        tolerance_ratio(
+            rows(target, area.x, area.y, area.width, area.height),
+            rows(
+                region_img,
+                rx + x - x0,
+                ry + y - y0,
+                area.width,
+                area.height,
+            ),
+            w,
+            tolerance,
+            &mask,
+        )
+    };
+    let found = |(x, y): (u32, u32), similarity: f32| AreaMatch {
+        x: (x as f32 * scale.0) as u32,
+        y: (y as f32 * scale.1) as u32,
+        width: (area.width as f32 * scale.0).round() as u32,
+        height: (area.height as f32 * scale.1).round() as u32,
+        similarity,
+        offset: (x as i32 - area.x as i32, y as i32 - area.y as i32),
+        scale,
+    };
+
+    // Nothing can do better than the expected position matching exactly
+    let expected = ratio_at(area.x, area.y);
+    if expected >= 1.0 {
+        return Some(found((area.x, area.y), expected));
+    }
+
+    let luma_of = |rows: &mut dyn Iterator<Item = &[u8]>| -> Vec<f32> {
+        rows.flat_map(|row| row.chunks_exact(4).map(luma)).collect()
+    };
+    let needle = luma_of(&mut rows(target, area.x, area.y, area.width, area.height));
+    let region = luma_of(&mut rows(region_img, rx, ry, rw, rh));
+    let template = Template::new(needle.clone(), mask.clone(), w, h);
+    let (rw, rh) = (rw as usize, rh as usize);
+    let (span_x, span_y) = ((x1 - x0) as usize, (y1 - y0) as usize);
+
+    // Start at the expected position, so it wins the ties
+    let (ex, ey) = ((area.x - x0) as usize, (area.y - y0) as usize);
+    let mut best = (template.score(&region, rw, ex, ey), ex, ey);
+    let mut try_at = |ox: usize, oy: usize| {
+        let score = template.score(&region, rw, ox, oy);
+        if score > best.0 + 1e-6 {
+            best = (score, ox, oy);
+        }
+    };
+
+    let factor = [4, 2]
+        .into_iter()
+        .find(|f| w / f >= COARSE_MIN && h / f >= COARSE_MIN && span_x.max(span_y) >= 2 * f);
+    match factor {
+        Some(f) => {
+            // Search the downscaled region, then around its best candidates
+            let coarse = Template::downscale(&needle, &mask, w, h, f);
+            let small = downscale(&region, rw, rh, f);
+            let (sw, sh) = (rw / f, rh / f);
+            let mut candidates = Vec::new();
+            for cy in 0..=sh - coarse.h {
+                for cx in 0..=sw - coarse.w {
+                    candidates.push((coarse.score(&small, sw, cx, cy), cx, cy));
+                }
+            }
+            candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+            let top = candidates.first().map_or(0.0, |c| c.0);
+            let count = candidates
+                .iter()
+                .take_while(|c| c.0 >= top - COARSE_SLACK)
+                .count()
+                .clamp(COARSE_CANDIDATES, COARSE_MAX);
+            for &(_, cx, cy) in candidates.iter().take(count) {
+                for oy in (cy * f).saturating_sub(f)..=(cy * f + f).min(span_y) {
+                    for ox in (cx * f).saturating_sub(f)..=(cx * f + f).min(span_x) {
+                        try_at(ox, oy);
+                    }
+                }
+            }
+        }
+        None => {
+            for oy in 0..=span_y {
+                for ox in 0..=span_x {
+                    try_at(ox, oy);
+                }
+            }
+        }
+    }
+
+    let (bx, by) = (x0 + best.1 as u32, y0 + best.2 as u32);
+    let similarity = ratio_at(bx, by);
+    // The expected position wins if it's as good
+    if (bx, by) != (area.x, area.y) && expected >= similarity {
+        return Some(found((area.x, area.y), expected));
+    }
+    Some(found((bx, by), similarity))
+}
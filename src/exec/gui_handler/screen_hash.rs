@@ -0,0 +1,111 @@
+//! A perceptual hash of the screen, for cheap change detection.
+//!
+//! The screen is cut into a grid of blocks and the hash is the mean luminance
+//! of each block. Two screens are the same if no block differs by more than a
+//! threshold: comparing a few thousand values instead of the whole screen,
+//! where dithering or lossy compression doesn't count as a change while a
+//! character typed on a console still does.
+//!
+//! Screens wider than [`SAMPLE_WIDTH`] are sampled every few pixels, so a 4K
+//! screen costs about the same as a 1080p one.
+//!
+//! # Example
+//!
+//! ```
+//! # use image::{Rgba, RgbaImage};
+//! # use tester::exec::gui_handler::screen_hash::{ScreenHash, DEFAULT_THRESHOLD};
+//! let mut screen = RgbaImage::from_pixel(3840, 2160, Rgba([0, 0, 0, 255]));
+//! let before = ScreenHash::of(&screen);
+//! // A character typed on the console
+//! for y in 1000..1016 {
+//!     for x in 2000..2008 {
+//!         screen.put_pixel(x, y, Rgba([200, 200, 200, 255]));
+//!     }
+//! }
+//! assert!(!before.same(&ScreenHash::of(&screen), DEFAULT_THRESHOLD));
+//! ```
+
+use image::RgbaImage;
+
+/// Blocks of the grid, in columns and rows
+pub const HASH_COLS: u32 = 64;
+pub const HASH_ROWS: u32 = 36;
+
+/// Screens up to this width are read fully
+pub const SAMPLE_WIDTH: u32 = 1920;
+
+/// The difference of the mean luminance of a block still counted as the same
+pub const DEFAULT_THRESHOLD: f32 = 0.5;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScreenHash {
+    /// Size of the screen
+    size: (u32, u32),
+    cols: u32,
+    rows: u32,
+    /// Mean luminance of the blocks, row by row
+    blocks: Vec<f32>,
+}
+
+impl ScreenHash {
+    /// Hash `screen` on the default grid
+    pub fn of(screen: &RgbaImage) -> ScreenHash {
+        let step = (screen.width() / SAMPLE_WIDTH).max(1);
+        ScreenHash::with_grid(screen, HASH_COLS, HASH_ROWS, step)
+    }
+
+    /// Hash `screen` on a `cols` x `rows` grid, reading every `step`th pixel
+    /// of every `step`th row.
+    pub fn with_grid(screen: &RgbaImage, cols: u32, rows: u32, step: u32) -> ScreenHash {
+        let (width, height) = screen.dimensions();
+        let (cols, rows) = (cols.clamp(1, width.max(1)), rows.clamp(1, height.max(1)));
+        let step = step.max(1) as usize;
+        let mut sums = vec![0u64; (cols * rows) as usize];
+        let mut counts = vec![0u32; (cols * rows) as usize];
+        // The block column of each pixel read in a row
+        let block_col: Vec<usize> = (0..width as usize)
+            .step_by(step)
+            .map(|x| x * cols as usize / width as usize)
+            .collect();
+
+        let stride = width as usize * 4;
+        let raw = screen.as_raw();
+        for y in (0..height as usize).step_by(step) {
+            let base = (y * rows as usize / height as usize) * cols as usize;
+            let row = &raw[y * stride..(y + 1) * stride];
+            for (p, &col) in row.chunks_exact(4).step_by(step).zip(&block_col) {
+                let luma = 77 * p[0] as u32 + 150 * p[1] as u32 + 29 * p[2] as u32;
+                sums[base + col] += luma as u64;
+                counts[base + col] += 1;
+            }
+        }
+        ScreenHash {
+            size: (width, height),
+            cols,
+            rows,
+            blocks: sums
+                .iter()
+                .zip(&counts)
+                .map(|(&s, &c)| s as f32 / c.max(1) as f32 / 256.0)
+                .collect(),
+        }
+    }
+
+    /// The largest difference of a block, infinite if the screen sizes or
+    /// the grids differ
+    pub fn distance(&self, other: &ScreenHash) -> f32 {
+        if (self.size, self.cols, self.rows) != (other.size, other.cols, other.rows) {
+            return f32::INFINITY;
+        }
+        self.blocks
+            .iter()
+            .zip(&other.blocks)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f32::max)
+    }
+
+    /// Whether the screens are the same, no block differing by more than `threshold`
+    pub fn same(&self, other: &ScreenHash, threshold: f32) -> bool {
+        self.distance(other) <= threshold
+    }
+}
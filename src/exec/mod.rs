@@ -6,9 +6,12 @@
 //! So these wrappers are here to provide high-level API for the CLI and GUI.
 
 pub mod cli_api;
+pub mod bootloader;
 pub mod cli_exec;
+pub mod file_transfer;
 pub mod gui_api;
 pub mod needle;
+pub mod needle_loader;
 pub mod gui_exec;
 
 pub mod gui_handler;
\ No newline at end of file
@@ -3,6 +3,8 @@
 //! We can't do the same thing as the CLI part: give a string and wait for
 //! it. We need to match the GUI with a needle.
 
+use std::sync::Arc;
+
 use image::RgbaImage;
 use serde::{Deserialize, Serialize};
 
@@ -27,18 +29,20 @@ impl Needle {
 /// OpenQA needle compatible, with same definition
 ///
 /// See <https://open.qa/docs/#_needle>
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum NeedleType {
     #[serde(rename = "match")]
     Match,
+    /// Text read from the area, see [`Area::text`] and [`Area::regex`]
     #[serde(rename = "ocr")]
-    Ocr, // Currently not supported
+    Ocr,
+    /// Ignored inside the match areas
     #[serde(rename = "exclude")]
     Exclude,
 }
 
 /// Click point
-/// OpenQA needle compatible, with same definition
+/// OpenQA needle compatible, but in screen coordinates instead of relative to the area
 ///
 /// See <https://open.qa/docs/#_needle>
 #[derive(Serialize, Deserialize)]
@@ -63,14 +67,27 @@ pub struct Area {
     pub height: u32,
     /// Type of the needle
     pub needle: NeedleType,
-    /// The similarity threshold
+    /// The similarity threshold, for Ocr areas the similarity of the text
     #[serde(rename = "match")]
     pub match_threhold: f32,
     /// The click point
     pub click_point: Option<ClickPoint>,
+    /// How far from its position the area is searched, in pixels, default to 50
+    #[serde(default)]
+    pub margin: Option<u32>,
+    /// Per channel color difference still counted as the same, default to 32
+    #[serde(default)]
+    pub tolerance: Option<u8>,
+    /// Text expected in an Ocr area, compared ignoring case and whitespace
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Regex the text of an Ocr area must match
+    #[serde(default)]
+    pub regex: Option<String>,
     /// The image to match
     ///
-    /// This field should be auto added by needle readers
+    /// The whole screenshot of the needle, not only the area, shared by the
+    /// areas of a needle. Filled by needle readers, see [`super::needle_loader`]
     #[serde(skip_serializing, skip_deserializing)]
-    pub target: Option<RgbaImage>,
+    pub target: Option<Arc<RgbaImage>>,
 }
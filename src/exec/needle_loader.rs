@@ -0,0 +1,346 @@
+//! Read needles from openQA needle files.
+//!
+//! An openQA needle is a `<name>.json` describing the areas, with the
+//! screenshot as `<name>.png` next to it. See <https://open.qa/docs/#_needle>.
+//! [`NeedleInfo::load`] reads one, [`NeedleIndex`] indexes a needle directory
+//! so needles can be looked up by tag, e.g. with
+//! [`super::gui_api::GuiTestApi::assert_screen_tag`].
+//!
+//! The match level of openQA is a percentage, it's turned into the ratio of
+//! [`Area::match_threhold`]. The click point of openQA is relative to its
+//! area, it's turned into screen coordinates. An ocr area may also have the
+//! `text` or `regex` it must read, see [`Area::text`] and [`Area::regex`].
+//!
+//! # Example
+//!
+//! ```
+//! # use tester::exec::needle_loader::NeedleIndex;
+//! let dir = std::env::temp_dir().join("tester-doc-needles");
+//! std::fs::create_dir_all(&dir)?;
+//! image::RgbaImage::new(64, 48).save(dir.join("login-20240101.png"))?;
+//! std::fs::write(
+//!     dir.join("login-20240101.json"),
+//!     r#"{
+//!         "area": [{"xpos": 8, "ypos": 8, "width": 16, "height": 8,
+//!                   "type": "match", "click_point": "center"}],
+//!         "tags": ["login-prompt", "ENV-DESKTOP"],
+//!         "properties": ["workaround"]
+//!     }"#,
+//! )?;
+//!
+//! let index = NeedleIndex::build(&dir)?;
+//! let needles = index.load("login-prompt")?;
+//! assert_eq!(needles[0].name, "login-20240101");
+//! assert!(needles[0].has_property("workaround"));
+//! # std::fs::remove_dir_all(&dir)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use image::RgbaImage;
+use serde::Deserialize;
+
+use crate::{info, log, warn};
+
+use super::needle::{Area, ClickPoint, Needle, NeedleType};
+
+/// The default match level of openQA, in percent
+const DEFAULT_MATCH: f32 = 96.0;
+
+#[derive(Deserialize)]
+struct QaNeedle {
+    area: Vec<QaArea>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    properties: Vec<QaProperty>,
+}
+
+#[derive(Deserialize)]
+struct QaArea {
+    xpos: u32,
+    ypos: u32,
+    width: u32,
+    height: u32,
+    #[serde(rename = "type", default = "default_type")]
+    kind: NeedleType,
+    #[serde(rename = "match")]
+    match_level: Option<f32>,
+    margin: Option<u32>,
+    click_point: Option<QaClickPoint>,
+    /// Not in openQA, the expected text of an ocr area
+    text: Option<String>,
+    /// Not in openQA, a regex the text of an ocr area must match
+    regex: Option<String>,
+}
+
+fn default_type() -> NeedleType {
+    NeedleType::Match
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum QaClickPoint {
+    /// Only `"center"` is defined
+    Named(String),
+    Point {
+        xpos: f32,
+        ypos: f32,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum QaProperty {
+    Name(String),
+    Pair { name: String, value: Option<String> },
+}
+
+/// A property of a needle, e.g. `workaround`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NeedleProperty {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+/// A needle read from openQA files, with its metadata
+pub struct NeedleInfo {
+    /// The file name without extension
+    pub name: String,
+    /// Path to the JSON file
+    pub path: PathBuf,
+    pub tags: Vec<String>,
+    pub properties: Vec<NeedleProperty>,
+    /// The needle, with [`Area::target`] filled with the screenshot
+    pub needle: Needle,
+}
+
+fn read_json(path: &Path) -> Result<QaNeedle, Box<dyn Error>> {
+    let text = fs::read_to_string(path)?;
+    serde_json::from_str(&text).map_err(|e| format!("Bad needle {}: {}", path.display(), e).into())
+}
+
+fn read_png(path: &Path) -> Result<Arc<RgbaImage>, Box<dyn Error>> {
+    let png = path.with_extension("png");
+    let image = image::open(&png)
+        .map_err(|e| format!("Failed to read {}: {}", png.display(), e))?
+        .to_rgba8();
+    Ok(Arc::new(image))
+}
+
+fn needle_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+impl NeedleInfo {
+    /// Load the needle `path`, a `.json` with the `.png` next to it.
+    pub fn load(path: impl AsRef<Path>) -> Result<NeedleInfo, Box<dyn Error>> {
+        let path = path.as_ref();
+        NeedleInfo::load_with(path, read_png(path)?)
+    }
+
+    /// Load the needle `path` with its screenshot already read
+    fn load_with(path: &Path, target: Arc<RgbaImage>) -> Result<NeedleInfo, Box<dyn Error>> {
+        let json = read_json(path)?;
+
+        // The areas come from a file, don't overflow on a bogus one
+        let fits =
+            |pos: u32, len: u32, max: u32| pos.checked_add(len).is_some_and(|end| end <= max);
+        let mut areas = Vec::with_capacity(json.area.len());
+        for a in json.area {
+            if !fits(a.xpos, a.width, target.width()) || !fits(a.ypos, a.height, target.height()) {
+                return Err(
+                    format!("An area of needle {} is out of its image", path.display()).into(),
+                );
+            }
+            let click_point = match a.click_point {
+                None => None,
+                Some(QaClickPoint::Named(name)) if name == "center" => Some(ClickPoint {
+                    xpos: a.xpos + a.width / 2,
+                    ypos: a.ypos + a.height / 2,
+                }),
+                Some(QaClickPoint::Named(name)) => {
+                    return Err(
+                        format!("Unknown click point {} in {}", name, path.display()).into(),
+                    )
+                }
+                Some(QaClickPoint::Point { xpos, ypos }) => Some(ClickPoint {
+                    xpos: a.xpos.saturating_add(xpos.max(0.0) as u32),
+                    ypos: a.ypos.saturating_add(ypos.max(0.0) as u32),
+                }),
+            };
+            areas.push(Area {
+                x: a.xpos,
+                y: a.ypos,
+                width: a.width,
+                height: a.height,
+                needle: a.kind,
+                match_threhold: a.match_level.unwrap_or(DEFAULT_MATCH) / 100.0,
+                click_point,
+                margin: a.margin,
+                tolerance: None,
+                text: a.text,
+                regex: a.regex,
+                target: Some(Arc::clone(&target)),
+            });
+        }
+
+        let properties = json
+            .properties
+            .into_iter()
+            .map(|p| match p {
+                QaProperty::Name(name) => NeedleProperty { name, value: None },
+                QaProperty::Pair { name, value } => NeedleProperty { name, value },
+            })
+            .collect();
+
+        log!("Loaded needle {}", path.display());
+        Ok(NeedleInfo {
+            name: needle_name(path),
+            path: path.to_owned(),
+            tags: json.tags,
+            properties,
+            needle: Needle::Basic(areas),
+        })
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    pub fn has_property(&self, name: &str) -> bool {
+        self.properties.iter().any(|p| p.name == name)
+    }
+}
+
+struct IndexEntry {
+    name: String,
+    path: PathBuf,
+}
+
+/// Needles of a directory by tag, the images are read when the needles are
+/// loaded the first time and kept for the next loads.
+pub struct NeedleIndex {
+    entries: Vec<IndexEntry>,
+    tags: HashMap<String, Vec<usize>>,
+    images: Mutex<HashMap<usize, Arc<RgbaImage>>>,
+}
+
+impl NeedleIndex {
+    /// Index the needles in `dir` and its subdirectories.
+    ///
+    /// Broken needle files are skipped with a warning.
+    pub fn build(dir: impl AsRef<Path>) -> Result<NeedleIndex, Box<dyn Error>> {
+        let mut index = NeedleIndex {
+            entries: Vec::new(),
+            tags: HashMap::new(),
+            images: Mutex::new(HashMap::new()),
+        };
+        let mut dirs = vec![dir.as_ref().to_owned()];
+        while let Some(dir) = dirs.pop() {
+            let mut paths = fs::read_dir(&dir)?
+                .map(|e| e.map(|e| e.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            paths.sort();
+            for path in paths {
+                if path.is_dir() {
+                    dirs.push(path);
+                } else if path.extension().is_some_and(|e| e == "json") {
+                    index.add(path);
+                }
+            }
+        }
+        info!(
+            "Indexed {} needles with {} tags in {}",
+            index.entries.len(),
+            index.tags.len(),
+            dir.as_ref().display()
+        );
+        Ok(index)
+    }
+
+    fn add(&mut self, path: PathBuf) {
+        let json = match read_json(&path) {
+            Ok(json) => json,
+            Err(e) => {
+                warn!("Skip needle: {}", e);
+                return;
+            }
+        };
+        if !path.with_extension("png").exists() {
+            warn!("Skip needle {}, its png is missing", path.display());
+            return;
+        }
+        let id = self.entries.len();
+        for tag in json.tags {
+            self.tags.entry(tag).or_default().push(id);
+        }
+        self.entries.push(IndexEntry {
+            name: needle_name(&path),
+            path,
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// All the tags, sorted
+    pub fn tags(&self) -> Vec<&str> {
+        let mut tags: Vec<&str> = self.tags.keys().map(|t| t.as_str()).collect();
+        tags.sort();
+        tags
+    }
+
+    /// Names of the needles with `tag`
+    pub fn names(&self, tag: &str) -> Vec<&str> {
+        self.tags
+            .get(tag)
+            .map(|ids| ids.iter().map(|&i| self.entries[i].name.as_str()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Load the needles with `tag`, fails if there's none.
+    pub fn load(&self, tag: &str) -> Result<Vec<NeedleInfo>, Box<dyn Error>> {
+        let ids = match self.tags.get(tag) {
+            Some(ids) => ids,
+            None => return Err(format!("No needle has tag {}", tag).into()),
+        };
+        ids.iter().map(|&i| self.load_entry(i)).collect()
+    }
+
+    /// Load the needle named `name`
+    pub fn load_name(&self, name: &str) -> Result<NeedleInfo, Box<dyn Error>> {
+        match self.entries.iter().position(|e| e.name == name) {
+            Some(i) => self.load_entry(i),
+            None => Err(format!("No needle named {}", name).into()),
+        }
+    }
+
+    fn load_entry(&self, id: usize) -> Result<NeedleInfo, Box<dyn Error>> {
+        let path = &self.entries[id].path;
+        let cached = self.images.lock().unwrap().get(&id).cloned();
+        let target = match cached {
+            Some(target) => target,
+            None => {
+                let target = read_png(path)?;
+                self.images.lock().unwrap().insert(id, Arc::clone(&target));
+                target
+            }
+        };
+        NeedleInfo::load_with(path, target)
+    }
+}
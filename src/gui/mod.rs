@@ -9,4 +9,5 @@
 //! - Can write text (e.g. typing in a text box)
 
 pub mod screen;
-pub mod monitor;
\ No newline at end of file
+pub mod monitor;
+pub mod term_screen;
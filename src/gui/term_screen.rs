@@ -0,0 +1,466 @@
+//! Render a terminal as a [`Screen`], so needles work on serial consoles.
+//!
+//! [`TermScreen`] runs the output of a CLI [`Tty`] through the terminal
+//! emulator and draws the cell grid with a bundled monospace font (Hack, the
+//! one shipped with egui). Text and key presses are written back as the bytes
+//! a terminal would send. Mouse clicks and scrolls only work when the program
+//! asked for mouse reporting.
+
+use std::{collections::HashMap, error::Error};
+
+use ab_glyph::{Font, FontArc, PxScale, ScaleFont};
+use image::{Rgba, RgbaImage};
+
+use crate::{
+    cli::{
+        emulator::{Cell, Color},
+        tty::{DynTty, Tty},
+        vterm::VTerm,
+    },
+    impl_any,
+};
+
+use super::screen::Screen;
+
+const DEFAULT_FG: [u8; 3] = [229, 229, 229];
+const DEFAULT_BG: [u8; 3] = [0, 0, 0];
+
+/// The xterm colors 0-15
+const ANSI_COLORS: [[u8; 3]; 16] = [
+    [0, 0, 0],
+    [205, 0, 0],
+    [0, 205, 0],
+    [205, 205, 0],
+    [0, 0, 238],
+    [205, 0, 205],
+    [0, 205, 205],
+    [229, 229, 229],
+    [127, 127, 127],
+    [255, 0, 0],
+    [0, 255, 0],
+    [255, 255, 0],
+    [92, 92, 255],
+    [255, 0, 255],
+    [0, 255, 255],
+    [255, 255, 255],
+];
+
+fn palette(index: u8) -> [u8; 3] {
+    match index {
+        0..=15 => ANSI_COLORS[index as usize],
+        16..=231 => {
+            let i = index - 16;
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            [level(i / 36), level(i / 6 % 6), level(i % 6)]
+        }
+        _ => {
+            let v = 8 + (index - 232) * 10;
+            [v, v, v]
+        }
+    }
+}
+
+fn resolve(color: Color, default: [u8; 3], bold: bool) -> [u8; 3] {
+    match color {
+        Color::Default => default,
+        // Bold shows the bright variant of the basic colors
+        Color::Indexed(i) if bold && i < 8 => palette(i + 8),
+        Color::Indexed(i) => palette(i),
+        Color::Rgb(r, g, b) => [r, g, b],
+    }
+}
+
+/// Which arms of a box drawing char exist: up, down, left, right
+fn box_arms(c: char) -> Option<[bool; 4]> {
+    let arms = match c {
+        '─' | '━' | '═' => [false, false, true, true],
+        '│' | '┃' | '║' => [true, true, false, false],
+        '┌' | '┏' | '╔' | '╭' => [false, true, false, true],
+        '┐' | '┓' | '╗' | '╮' => [false, true, true, false],
+        '└' | '┗' | '╚' | '╰' => [true, false, false, true],
+        '┘' | '┛' | '╝' | '╯' => [true, false, true, false],
+        '├' | '┣' | '╠' => [true, true, false, true],
+        '┤' | '┫' | '╣' => [true, true, true, false],
+        '┬' | '┳' | '╦' => [false, true, true, true],
+        '┴' | '┻' | '╩' => [true, false, true, true],
+        '┼' | '╋' | '╬' => [true, true, true, true],
+        _ => return None,
+    };
+    Some(arms)
+}
+
+/// The coverage of a cell, `cell_w * cell_h` values in 0.0..=1.0
+type Coverage = Vec<f32>;
+
+pub struct TermScreen {
+    inner: VTerm,
+    font: FontArc,
+    scale: PxScale,
+    cell_w: u32,
+    cell_h: u32,
+    ascent: f32,
+    glyphs: HashMap<char, Coverage>,
+    show_cursor: bool,
+    pos: (u32, u32),
+    ctrl: bool,
+    shift: bool,
+    alt: bool,
+}
+
+impl TermScreen {
+    /// Build a new [`TermScreen`] instance.
+    ///
+    /// # Arguments
+    ///
+    /// - `inner`: The CLI Tty, e.g. a serial console.
+    /// - `cols`, `rows`: The terminal size, should match the remote side.
+    /// - `font_size`: The font size in pixels, 16 gives 9x16 cells.
+    pub fn build(
+        inner: DynTty,
+        cols: usize,
+        rows: usize,
+        font_size: f32,
+    ) -> Result<TermScreen, Box<dyn Error>> {
+        let fonts = eframe::egui::FontDefinitions::default();
+        let data = fonts
+            .font_data
+            .get("Hack")
+            .ok_or("The bundled monospace font is missing")?;
+        let font = FontArc::try_from_vec(data.font.to_vec())?;
+
+        let scale = PxScale::from(font_size);
+        let scaled = font.as_scaled(scale);
+        let cell_w = scaled.h_advance(font.glyph_id('M')).ceil().max(1.0) as u32;
+        let cell_h = (scaled.ascent() - scaled.descent() + scaled.line_gap())
+            .ceil()
+            .max(1.0) as u32;
+        let ascent = scaled.ascent();
+
+        Ok(TermScreen {
+            inner: VTerm::build(inner, cols, rows),
+            font,
+            scale,
+            cell_w,
+            cell_h,
+            ascent,
+            glyphs: HashMap::new(),
+            show_cursor: true,
+            pos: (0, 0),
+            ctrl: false,
+            shift: false,
+            alt: false,
+        })
+    }
+
+    /// Draw the cursor as an inverted cell or not.
+    pub fn set_show_cursor(&mut self, show: bool) {
+        self.show_cursor = show;
+    }
+
+    pub fn vterm(&self) -> &VTerm {
+        &self.inner
+    }
+
+    pub fn vterm_mut(&mut self) -> &mut VTerm {
+        &mut self.inner
+    }
+
+    /// The size of one cell in pixels
+    pub fn cell_size(&self) -> (u32, u32) {
+        (self.cell_w, self.cell_h)
+    }
+
+    fn coverage(&mut self, c: char) -> &Coverage {
+        let (w, h) = (self.cell_w, self.cell_h);
+        let font = &self.font;
+        let scale = self.scale;
+        let ascent = self.ascent;
+        self.glyphs.entry(c).or_insert_with(|| {
+            let mut cov = vec![0f32; (w * h) as usize];
+            let mut fill = |x0: u32, y0: u32, x1: u32, y1: u32, v: f32| {
+                for y in y0..y1.min(h) {
+                    for x in x0..x1.min(w) {
+                        cov[(y * w + x) as usize] = v;
+                    }
+                }
+            };
+            // Draw the box and block chars by hand, so they connect between cells
+            if let Some([up, down, left, right]) = box_arms(c) {
+                let (cx, cy) = (w / 2, h / 2);
+                let t = (w / 8).max(1);
+                if up {
+                    fill(cx, 0, cx + t, cy + t, 1.0);
+                }
+                if down {
+                    fill(cx, cy, cx + t, h, 1.0);
+                }
+                if left {
+                    fill(0, cy, cx + t, cy + t, 1.0);
+                }
+                if right {
+                    fill(cx, cy, w, cy + t, 1.0);
+                }
+                return cov;
+            }
+            match c {
+                '█' => fill(0, 0, w, h, 1.0),
+                '▀' => fill(0, 0, w, h / 2, 1.0),
+                '▄' => fill(0, h / 2, w, h, 1.0),
+                '▌' => fill(0, 0, w / 2, h, 1.0),
+                '▐' => fill(w / 2, 0, w, h, 1.0),
+                '░' => fill(0, 0, w, h, 0.25),
+                '▒' => fill(0, 0, w, h, 0.5),
+                '▓' => fill(0, 0, w, h, 0.75),
+                _ => {
+                    let glyph = font
+                        .glyph_id(c)
+                        .with_scale_and_position(scale, ab_glyph::point(0.0, ascent));
+                    if let Some(outlined) = font.outline_glyph(glyph) {
+                        let bounds = outlined.px_bounds();
+                        outlined.draw(|x, y, v| {
+                            let x = x as i32 + bounds.min.x as i32;
+                            let y = y as i32 + bounds.min.y as i32;
+                            if x >= 0 && y >= 0 && (x as u32) < w && (y as u32) < h {
+                                let p = &mut cov[(y as u32 * w + x as u32) as usize];
+                                *p = p.max(v);
+                            }
+                        });
+                    }
+                }
+            }
+            cov
+        })
+    }
+
+    fn draw_cell(&mut self, img: &mut RgbaImage, row: usize, col: usize, cell: Cell, cursor: bool) {
+        let attr = cell.attr;
+        let mut fg = resolve(attr.fg, DEFAULT_FG, attr.bold);
+        let mut bg = resolve(attr.bg, DEFAULT_BG, false);
+        if attr.inverse != cursor {
+            std::mem::swap(&mut fg, &mut bg);
+        }
+        if attr.dim {
+            fg = fg.map(|v| v / 2);
+        }
+        if attr.hidden {
+            fg = bg;
+        }
+
+        let (w, h) = (self.cell_w, self.cell_h);
+        let underline = (self.ascent.ceil() as u32 + 1).min(h - 1);
+        let (x0, y0) = (col as u32 * w, row as u32 * h);
+        let cov = self.coverage(cell.c).clone();
+        for y in 0..h {
+            for x in 0..w {
+                let mut v = cov[(y * w + x) as usize];
+                // Fake bold by smearing one pixel to the right
+                if attr.bold && x > 0 {
+                    v = v.max(cov[(y * w + x - 1) as usize]);
+                }
+                if (attr.underline && y == underline) || (attr.strike && y == h / 2) {
+                    v = 1.0;
+                }
+                let px =
+                    [0, 1, 2].map(|i| (bg[i] as f32 * (1.0 - v) + fg[i] as f32 * v).round() as u8);
+                img.put_pixel(x0 + x, y0 + y, Rgba([px[0], px[1], px[2], 255]));
+            }
+        }
+    }
+
+    /// Send a mouse button press and release at the current position.
+    fn mouse_click(&mut self, button: u8) -> Result<(), Box<dyn Error>> {
+        let mode = self.inner.emulator().mouse_mode();
+        if !mode.tracking {
+            return Err("The program in the terminal doesn't track the mouse".into());
+        }
+        let col = self.pos.0 / self.cell_w + 1;
+        let row = self.pos.1 / self.cell_h + 1;
+        let data = if mode.sgr {
+            format!(
+                "\x1b[<{b};{c};{r}M\x1b[<{b};{c};{r}m",
+                b = button,
+                c = col,
+                r = row
+            )
+            .into_bytes()
+        } else {
+            // The legacy encoding, release is button 3
+            let enc = |v: u32| (32 + v.min(223)) as u8;
+            let mut data = b"\x1b[M".to_vec();
+            data.extend([32 + button, enc(col), enc(row)]);
+            if button < 64 {
+                data.extend(b"\x1b[M");
+                data.extend([32 + 3, enc(col), enc(row)]);
+            }
+            data
+        };
+        self.inner.write(&data)
+    }
+
+    /// Map an X11 keycode to the bytes sent by a terminal
+    fn key_bytes(&self, key: u16) -> Option<Vec<u8>> {
+        let app = self.inner.emulator().app_cursor();
+        let arrow = |c: u8| {
+            if app {
+                vec![0x1b, b'O', c]
+            } else {
+                vec![0x1b, b'[', c]
+            }
+        };
+        let letters = [
+            (24, 'q'),
+            (25, 'w'),
+            (26, 'e'),
+            (27, 'r'),
+            (28, 't'),
+            (29, 'y'),
+            (30, 'u'),
+            (31, 'i'),
+            (32, 'o'),
+            (33, 'p'),
+            (38, 'a'),
+            (39, 's'),
+            (40, 'd'),
+            (41, 'f'),
+            (42, 'g'),
+            (43, 'h'),
+            (44, 'j'),
+            (45, 'k'),
+            (46, 'l'),
+            (52, 'z'),
+            (53, 'x'),
+            (54, 'c'),
+            (55, 'v'),
+            (56, 'b'),
+            (57, 'n'),
+            (58, 'm'),
+        ];
+        let bytes = match key {
+            9 => vec![0x1b],
+            22 => vec![0x7f],
+            23 => vec![b'\t'],
+            36 | 104 => vec![b'\r'],
+            65 => vec![b' '],
+            10..=18 => vec![b'1' + (key - 10) as u8],
+            19 => vec![b'0'],
+            111 => arrow(b'A'),
+            116 => arrow(b'B'),
+            114 => arrow(b'C'),
+            113 => arrow(b'D'),
+            110 => b"\x1b[H".to_vec(),
+            115 => b"\x1b[F".to_vec(),
+            112 => b"\x1b[5~".to_vec(),
+            117 => b"\x1b[6~".to_vec(),
+            118 => b"\x1b[2~".to_vec(),
+            119 => b"\x1b[3~".to_vec(),
+            67..=70 => vec![0x1b, b'O', b'P' + (key - 67) as u8],
+            71..=73 => format!("\x1b[{}~", 15 + (key - 71) + (key > 71) as u16).into_bytes(),
+            74..=76 => format!("\x1b[{}~", 19 + (key - 74)).into_bytes(),
+            95 => b"\x1b[23~".to_vec(),
+            96 => b"\x1b[24~".to_vec(),
+            _ => {
+                let (_, c) = letters.iter().find(|(k, _)| *k == key)?;
+                let c = *c as u8;
+                if self.ctrl {
+                    vec![c & 0x1f]
+                } else if self.shift {
+                    vec![c.to_ascii_uppercase()]
+                } else {
+                    vec![c]
+                }
+            }
+        };
+        if self.alt {
+            Some([vec![0x1b], bytes].concat())
+        } else {
+            Some(bytes)
+        }
+    }
+}
+
+impl_any!(TermScreen);
+
+impl Screen for TermScreen {
+    fn size(&self) -> (u32, u32) {
+        let (cols, rows) = self.inner.emulator().size();
+        (cols as u32 * self.cell_w, rows as u32 * self.cell_h)
+    }
+
+    fn read(&mut self) -> Result<RgbaImage, Box<dyn Error>> {
+        self.inner.update()?;
+        let (width, height) = self.size();
+        let mut img = RgbaImage::new(width, height);
+        let emulator = self.inner.emulator();
+        let lines = emulator.lines().to_vec();
+        let cursor = (self.show_cursor && emulator.cursor_visible()).then(|| emulator.cursor());
+        for (r, line) in lines.iter().enumerate() {
+            for (c, cell) in line.iter().enumerate() {
+                self.draw_cell(&mut img, r, c, *cell, cursor == Some((r, c)));
+            }
+        }
+        Ok(img)
+    }
+
+    fn move_to(&mut self, x: u32, y: u32) -> Result<(), Box<dyn Error>> {
+        self.pos = (x, y);
+        Ok(())
+    }
+
+    fn click_left(&mut self) -> Result<(), Box<dyn Error>> {
+        self.mouse_click(0)
+    }
+
+    fn click_right(&mut self) -> Result<(), Box<dyn Error>> {
+        self.mouse_click(2)
+    }
+
+    fn click_middle(&mut self) -> Result<(), Box<dyn Error>> {
+        self.mouse_click(1)
+    }
+
+    fn scroll_up(&mut self, len: u32) -> Result<(), Box<dyn Error>> {
+        for _ in 0..len {
+            self.mouse_click(64)?;
+        }
+        Ok(())
+    }
+
+    fn scroll_down(&mut self, len: u32) -> Result<(), Box<dyn Error>> {
+        for _ in 0..len {
+            self.mouse_click(65)?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, data: String) -> Result<(), Box<dyn Error>> {
+        self.inner.write(data.as_bytes())
+    }
+
+    /// Press a key, identified by the X11 keycode like [`super::monitor::Monitor`] on Linux.
+    ///
+    /// Modifiers (Ctrl, Shift, Alt) apply to the keys pressed while they're held.
+    fn hold(&mut self, key: u16) -> Result<(), Box<dyn Error>> {
+        match key {
+            37 | 105 => self.ctrl = true,
+            50 | 62 => self.shift = true,
+            64 | 108 => self.alt = true,
+            _ => {
+                let bytes = self
+                    .key_bytes(key)
+                    .ok_or_else(|| format!("Unknown keycode {}", key))?;
+                self.inner.write(&bytes)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn release(&mut self, key: u16) -> Result<(), Box<dyn Error>> {
+        match key {
+            37 | 105 => self.ctrl = false,
+            50 | 62 => self.shift = false,
+            64 | 108 => self.alt = false,
+            _ => {}
+        }
+        Ok(())
+    }
+}
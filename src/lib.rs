@@ -9,6 +9,7 @@ pub mod gui;
 pub mod exec;
 pub mod devhost {
     pub mod devhost;
+    pub mod fastboot;
     pub mod sdwirec;
 }
 pub mod device {
@@ -0,0 +1,136 @@
+use std::time::Duration;
+
+use pyo3::{exceptions::PyRuntimeError, pyclass, pymethods, PyRefMut, PyResult};
+
+use crate::{
+    cli::adb::{AdbConnect, ADB_SERVER},
+    util::anybase::heap_raw,
+};
+
+use super::shell_like::{py_tty_inner, PyTty, TtyType};
+
+fn get_adb<'a>(self_: &'a mut PyRefMut<'_, Adb>) -> PyResult<&'a mut crate::cli::adb::Adb> {
+    let self_ = self_.as_mut();
+    let inner = self_.inner.get_mut()?;
+    inner
+        .as_any_mut()
+        .downcast_mut::<crate::cli::adb::Adb>()
+        .ok_or_else(|| PyRuntimeError::new_err("This type isn't an Adb"))
+}
+
+#[pyclass(extends=PyTty, subclass)]
+pub struct Adb {}
+
+#[pymethods]
+impl Adb {
+    #[new]
+    #[pyo3(signature = (serial=None, server=None, tcp=None))]
+    fn py_new(
+        serial: Option<String>,
+        server: Option<String>,
+        tcp: Option<String>,
+    ) -> PyResult<(Self, PyTty)> {
+        let conn = match tcp {
+            Some(addr) if addr.contains(':') => AdbConnect::Tcp { addr },
+            Some(addr) => AdbConnect::Tcp {
+                addr: format!("{}:5555", addr),
+            },
+            None => AdbConnect::Server {
+                addr: server.unwrap_or(ADB_SERVER.to_owned()),
+                serial,
+            },
+        };
+        let adb = crate::cli::adb::Adb::build(conn)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        let adb = Box::new(adb) as TtyType;
+        Ok((Adb {}, PyTty::build(py_tty_inner(heap_raw(adb)))))
+    }
+
+    #[staticmethod]
+    #[pyo3(signature = (server=None))]
+    fn devices(server: Option<&str>) -> PyResult<Vec<(String, String)>> {
+        crate::cli::adb::devices(server.unwrap_or(ADB_SERVER))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    fn exec(mut self_: PyRefMut<'_, Self>, cmd: &str) -> PyResult<Vec<u8>> {
+        get_adb(&mut self_)?
+            .exec(cmd)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    fn push(mut self_: PyRefMut<'_, Self>, local: &str, remote: &str) -> PyResult<()> {
+        get_adb(&mut self_)?
+            .push(local, remote)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    fn pull(mut self_: PyRefMut<'_, Self>, remote: &str, local: &str) -> PyResult<()> {
+        get_adb(&mut self_)?
+            .pull(remote, local)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+}
+
+#[pyclass]
+pub struct Fastboot {
+    pub inner: crate::devhost::fastboot::Fastboot,
+}
+
+#[pymethods]
+impl Fastboot {
+    #[new]
+    fn py_new(addr: &str) -> PyResult<Self> {
+        let inner = crate::devhost::fastboot::Fastboot::connect_tcp(addr)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(Fastboot { inner })
+    }
+
+    fn set_timeout(&mut self, timeout: u32) -> PyResult<()> {
+        self.inner
+            .set_timeout(Duration::from_secs(timeout as u64))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    fn command(&mut self, cmd: &str) -> PyResult<String> {
+        self.inner
+            .command(cmd)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    fn getvar(&mut self, name: &str) -> PyResult<String> {
+        self.inner
+            .getvar(name)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    fn flash(&mut self, partition: &str, path: &str) -> PyResult<()> {
+        self.inner
+            .flash_file(partition, path)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    fn erase(&mut self, partition: &str) -> PyResult<()> {
+        self.inner
+            .erase(partition)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    fn reboot(&mut self) -> PyResult<()> {
+        self.inner
+            .reboot()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    fn reboot_bootloader(&mut self) -> PyResult<()> {
+        self.inner
+            .reboot_bootloader()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    fn continue_boot(&mut self) -> PyResult<()> {
+        self.inner
+            .continue_boot()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+}
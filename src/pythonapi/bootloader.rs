@@ -0,0 +1,134 @@
+use pyo3::{exceptions::PyRuntimeError, pyclass, pymethods, PyRefMut, PyResult};
+
+use crate::{
+    exec::{
+        bootloader::{BootloaderKind, BootloaderTester},
+        cli_api::CliTestApi,
+    },
+    util::anybase::heap_raw,
+};
+
+use super::shell_like::{handle_wrap, py_tty_inner, PyTty, TtyType};
+
+fn parse_bootloader_kind(kind: &str) -> PyResult<BootloaderKind> {
+    match kind {
+        "u-boot" | "uboot" => Ok(BootloaderKind::UBoot),
+        "grub" => Ok(BootloaderKind::Grub),
+        _ => Err(PyRuntimeError::new_err(
+            "kind must be one of u-boot or grub",
+        )),
+    }
+}
+
+fn get_tester<'a>(self_: &'a mut PyRefMut<'_, Bootloader>) -> PyResult<&'a mut BootloaderTester> {
+    let self_ = self_.as_mut();
+    let inner = self_.inner.get_mut()?;
+    inner
+        .as_any_mut()
+        .downcast_mut::<BootloaderTester>()
+        .ok_or_else(|| PyRuntimeError::new_err("Can't find the right object to run the script"))
+}
+
+#[pyclass(extends=PyTty, subclass)]
+pub struct Bootloader {}
+
+#[pymethods]
+impl Bootloader {
+    #[new]
+    #[pyo3(signature = (be_wrapped, kind="u-boot", prompt=None, interrupt_key=None, check_status=true))]
+    fn py_new(
+        be_wrapped: &mut PyTty,
+        kind: &str,
+        prompt: Option<&str>,
+        interrupt_key: Option<&str>,
+        check_status: bool,
+    ) -> PyResult<(Self, PyTty)> {
+        let kind = parse_bootloader_kind(kind)?;
+        let mut inner = None;
+        handle_wrap(&mut inner, Some(be_wrapped))?;
+        let tty = inner.unwrap().safe_take()?;
+        let tty = Box::into_inner(tty);
+
+        let mut res = BootloaderTester::build(tty, kind);
+        if let Some(prompt) = prompt {
+            res.set_prompt(prompt);
+        }
+        if let Some(key) = interrupt_key {
+            res.set_interrupt_key(key);
+        }
+        res.set_check_status(check_status);
+
+        let res = Box::new(res) as TtyType;
+        Ok((Bootloader {}, PyTty::build(py_tty_inner(heap_raw(res)))))
+    }
+
+    #[pyo3(signature = (timeout=None))]
+    fn interrupt_autoboot(mut self_: PyRefMut<'_, Self>, timeout: Option<u32>) -> PyResult<()> {
+        let timeout = timeout.unwrap_or(60);
+        get_tester(&mut self_)?
+            .interrupt_autoboot(timeout)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    #[pyo3(signature = (script, timeout=None))]
+    fn script_run(
+        mut self_: PyRefMut<'_, Self>,
+        script: &str,
+        timeout: Option<u32>,
+    ) -> PyResult<String> {
+        let timeout = timeout.unwrap_or(30);
+        get_tester(&mut self_)?
+            .script_run(script, timeout)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    fn writeln(mut self_: PyRefMut<'_, Self>, script: &str) -> PyResult<()> {
+        get_tester(&mut self_)?
+            .writeln(script)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    #[pyo3(signature = (expected, timeout=None))]
+    fn wait_serial(
+        mut self_: PyRefMut<'_, Self>,
+        expected: &str,
+        timeout: Option<u32>,
+    ) -> PyResult<String> {
+        let timeout = timeout.unwrap_or(30);
+        get_tester(&mut self_)?
+            .wait_serial(expected, timeout)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    fn add_fail_pattern(mut self_: PyRefMut<'_, Self>, pattern: &str) -> PyResult<()> {
+        get_tester(&mut self_)?.add_fail_pattern(pattern);
+        Ok(())
+    }
+
+    fn printenv(mut self_: PyRefMut<'_, Self>, name: &str) -> PyResult<Option<String>> {
+        get_tester(&mut self_)?
+            .printenv(name)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    fn setenv(mut self_: PyRefMut<'_, Self>, name: &str, value: &str) -> PyResult<()> {
+        get_tester(&mut self_)?
+            .setenv(name, value)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    #[pyo3(signature = (timeout=None))]
+    fn saveenv(mut self_: PyRefMut<'_, Self>, timeout: Option<u32>) -> PyResult<()> {
+        let timeout = timeout.unwrap_or(30);
+        get_tester(&mut self_)?
+            .saveenv(timeout)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    #[pyo3(signature = (cmd=None))]
+    fn boot(mut self_: PyRefMut<'_, Self>, cmd: Option<&str>) -> PyResult<()> {
+        get_tester(&mut self_)?
+            .boot(cmd)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+}
@@ -0,0 +1,81 @@
+use pyo3::{exceptions::PyRuntimeError, pyclass, pymethods, PyRefMut, PyResult};
+
+use crate::{cli::tty::Tty, util::anybase::heap_raw};
+
+use super::shell_like::{handle_wrap, py_tty_inner, PyTty, PyTtyInner, TtyType};
+
+pub fn handle_bootlog(inner: &mut Option<PyTtyInner>, fail_fast: bool) -> PyResult<()> {
+    if inner.is_none() {
+        return Err(PyRuntimeError::new_err(
+            "You must define at least one valid object",
+        ));
+    }
+    let mut be_wrapped = inner.take().unwrap();
+    let be_wrapped = be_wrapped.safe_take()?;
+    let be_wrapped = Box::into_inner(be_wrapped);
+    let bootlog = Box::new(crate::cli::bootlog::BootLog::build(be_wrapped, fail_fast));
+    let bootlog: Box<dyn Tty + Send> = bootlog as TtyType;
+    *inner = Some(py_tty_inner(heap_raw(bootlog)));
+    Ok(())
+}
+
+fn get_bootlog<'a>(
+    self_: &'a mut PyRefMut<'_, BootLog>,
+) -> PyResult<&'a mut crate::cli::bootlog::BootLog> {
+    let self_ = self_.as_mut();
+    let inner = self_.inner.get_mut()?;
+    inner
+        .as_any_mut()
+        .downcast_mut::<crate::cli::bootlog::BootLog>()
+        .ok_or_else(|| PyRuntimeError::new_err("This type isn't a BootLog"))
+}
+
+#[pyclass(extends=PyTty, subclass)]
+pub struct BootLog {}
+
+#[pymethods]
+impl BootLog {
+    #[new]
+    #[pyo3(signature = (be_wrapped, fail_fast=true))]
+    fn py_new(be_wrapped: &mut PyTty, fail_fast: bool) -> PyResult<(Self, PyTty)> {
+        let mut inner = None;
+        handle_wrap(&mut inner, Some(be_wrapped))?;
+        handle_bootlog(&mut inner, fail_fast)?;
+        Ok((BootLog {}, PyTty::build(inner.unwrap())))
+    }
+
+    fn add_stage(mut self_: PyRefMut<'_, Self>, pattern: &str, name: &str) -> PyResult<()> {
+        get_bootlog(&mut self_)?.add_stage(pattern, name);
+        Ok(())
+    }
+
+    fn add_fatal(mut self_: PyRefMut<'_, Self>, pattern: &str) -> PyResult<()> {
+        get_bootlog(&mut self_)?.add_fatal(pattern);
+        Ok(())
+    }
+
+    fn reset(mut self_: PyRefMut<'_, Self>) -> PyResult<()> {
+        get_bootlog(&mut self_)?.reset();
+        Ok(())
+    }
+
+    fn stages(mut self_: PyRefMut<'_, Self>) -> PyResult<Vec<(String, f64)>> {
+        Ok(get_bootlog(&mut self_)?
+            .stages()
+            .iter()
+            .map(|s| (s.name.clone(), s.at.as_secs_f64()))
+            .collect())
+    }
+
+    fn reached(mut self_: PyRefMut<'_, Self>, name: &str) -> PyResult<bool> {
+        Ok(get_bootlog(&mut self_)?.reached(name))
+    }
+
+    fn fatal(mut self_: PyRefMut<'_, Self>) -> PyResult<Option<String>> {
+        Ok(get_bootlog(&mut self_)?.fatal().map(|s| s.to_owned()))
+    }
+
+    fn report(mut self_: PyRefMut<'_, Self>) -> PyResult<String> {
+        Ok(get_bootlog(&mut self_)?.report())
+    }
+}
@@ -0,0 +1,79 @@
+use pyo3::{exceptions::PyRuntimeError, pyclass, pymethods, PyRefMut, PyResult};
+
+use crate::util::anybase::heap_raw;
+
+use super::shell_like::{handle_unwrap, handle_wrap, py_tty_inner, PyTty, PyTtyInner, TtyType};
+
+pub fn handle_console_server(inner: &mut Option<PyTtyInner>, name: &str) -> PyResult<()> {
+    if inner.is_none() {
+        return Err(PyRuntimeError::new_err(
+            "You must define at least one valid object",
+        ));
+    }
+    // Create the socket before taking the Tty, so a name in use leaves it in place
+    let listener = crate::cli::console_server::ConsoleServer::listen(name)
+        .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+    let mut be_wrapped = inner.take().unwrap();
+    let be_wrapped = be_wrapped.safe_take()?;
+    let be_wrapped = Box::into_inner(be_wrapped);
+    let server = crate::cli::console_server::ConsoleServer::serve(be_wrapped, listener);
+    let server = Box::new(server) as TtyType;
+    *inner = Some(py_tty_inner(heap_raw(server)));
+    Ok(())
+}
+
+fn get_server<'a>(
+    self_: &'a mut PyRefMut<'_, ConsoleServer>,
+) -> PyResult<&'a mut crate::cli::console_server::ConsoleServer> {
+    let self_ = self_.as_mut();
+    let inner = self_.inner.get_mut()?;
+    inner
+        .as_any_mut()
+        .downcast_mut::<crate::cli::console_server::ConsoleServer>()
+        .ok_or_else(|| PyRuntimeError::new_err("This type isn't a ConsoleServer"))
+}
+
+#[pyclass(extends=PyTty, subclass)]
+pub struct ConsoleServer {}
+
+#[pymethods]
+impl ConsoleServer {
+    #[new]
+    fn py_new(be_wrapped: &mut PyTty, name: &str) -> PyResult<(Self, PyTty)> {
+        let mut inner = None;
+        handle_wrap(&mut inner, Some(&mut *be_wrapped))?;
+        if let Err(e) = handle_console_server(&mut inner, name) {
+            handle_unwrap(&mut inner, be_wrapped)?;
+            return Err(e);
+        }
+        Ok((ConsoleServer {}, PyTty::build(inner.unwrap())))
+    }
+
+    fn set_local_writer(mut self_: PyRefMut<'_, Self>, write: bool) -> PyResult<()> {
+        get_server(&mut self_)?
+            .set_local_writer(write)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    fn clients(mut self_: PyRefMut<'_, Self>) -> PyResult<usize> {
+        Ok(get_server(&mut self_)?.clients())
+    }
+}
+
+#[pyclass(extends=PyTty, subclass)]
+pub struct ConsoleClient {}
+
+#[pymethods]
+impl ConsoleClient {
+    #[new]
+    #[pyo3(signature = (name, read_write=false))]
+    fn py_new(name: &str, read_write: bool) -> PyResult<(Self, PyTty)> {
+        let client = crate::cli::console_server::ConsoleClient::connect(name, read_write)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        let client = Box::new(client) as TtyType;
+        Ok((
+            ConsoleClient {},
+            PyTty::build(py_tty_inner(heap_raw(client))),
+        ))
+    }
+}
@@ -4,7 +4,12 @@ use crate::{cli::tty::Tty, util::anybase::heap_raw};
 
 use super::shell_like::{handle_wrap, py_tty_inner, PyTty, PyTtyInner, TtyType};
 
-pub fn handle_deansi(inner: &mut Option<PyTtyInner>) -> PyResult<()> {
+pub fn handle_deansi(
+    inner: &mut Option<PyTtyInner>,
+    keep_sgr: bool,
+    cursor_edit: bool,
+    cr_overwrite: bool,
+) -> PyResult<()> {
     if inner.is_none() {
         return Err(PyRuntimeError::new_err(
             "You must define at least one valid object",
@@ -13,7 +18,10 @@ pub fn handle_deansi(inner: &mut Option<PyTtyInner>) -> PyResult<()> {
     let mut be_wrapped = inner.take().unwrap();
     let be_wrapped = be_wrapped.safe_take()?;
     let be_wrapped = Box::into_inner(be_wrapped);
-    let dean = Box::new(crate::cli::deansi::DeANSI::build(be_wrapped));
+    let mut dean = Box::new(crate::cli::deansi::DeANSI::build(be_wrapped));
+    dean.set_keep_sgr(keep_sgr);
+    dean.set_cursor_edit(cursor_edit);
+    dean.set_cr_overwrite(cr_overwrite);
     let dean: Box<dyn Tty + Send> = dean as TtyType;
     *inner = Some(py_tty_inner(heap_raw(dean)));
     Ok(())
@@ -25,10 +33,16 @@ pub struct DeANSI {}
 #[pymethods]
 impl DeANSI {
     #[new]
-    fn py_new(be_wrapped: &mut PyTty) -> PyResult<(Self, PyTty)> {
+    #[pyo3(signature = (be_wrapped, keep_sgr=false, cursor_edit=false, cr_overwrite=false))]
+    fn py_new(
+        be_wrapped: &mut PyTty,
+        keep_sgr: bool,
+        cursor_edit: bool,
+        cr_overwrite: bool,
+    ) -> PyResult<(Self, PyTty)> {
         let mut inner = None;
         handle_wrap(&mut inner, Some(be_wrapped))?;
-        handle_deansi(&mut inner)?;
+        handle_deansi(&mut inner, keep_sgr, cursor_edit, cr_overwrite)?;
         Ok((DeANSI {}, PyTty::build(inner.unwrap())))
     }
 }
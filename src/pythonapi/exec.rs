@@ -4,7 +4,8 @@ use crate::{
     exec::{
         cli_api::{CliTestApi, SudoCliTestApi},
         cli_exec::CliTester,
-        cli_exec::SudoCliTester,
+        cli_exec::{ResponseRule, SudoCliTester},
+        file_transfer::{FileTransferApi, TransferMode},
     },
     util::anybase::heap_raw,
 };
@@ -32,18 +33,87 @@ pub fn handle_clitester(inner: &mut Option<PyTtyInner>, need_sudo: Option<bool>)
     Ok(())
 }
 
+pub fn handle_responses(
+    inner: &mut Option<PyTtyInner>,
+    sudo_password: Option<&str>,
+    auto_respond: bool,
+) -> PyResult<()> {
+    let inner = match inner {
+        Some(inner) => inner.get_mut()?,
+        None => {
+            return Err(PyRuntimeError::new_err(
+                "You must define at least one valid object",
+            ))
+        }
+    };
+    let inner = inner.as_any_mut();
+
+    let mut rules = Vec::new();
+    if auto_respond {
+        rules.extend(ResponseRule::presets());
+    }
+    if let Some(password) = sudo_password {
+        rules.push(ResponseRule::sudo(password));
+    }
+
+    if let Some(inner) = inner.downcast_mut::<CliTester>() {
+        rules.into_iter().for_each(|r| inner.add_response(r));
+    } else if let Some(inner) = inner.downcast_mut::<SudoCliTester>() {
+        rules.into_iter().for_each(|r| inner.add_response(r));
+    }
+    Ok(())
+}
+
+pub fn handle_fail_on(inner: &mut Option<PyTtyInner>, patterns: Vec<String>) -> PyResult<()> {
+    let inner = match inner {
+        Some(inner) => inner.get_mut()?,
+        None => {
+            return Err(PyRuntimeError::new_err(
+                "You must define at least one valid object",
+            ))
+        }
+    };
+    let inner = inner.as_any_mut();
+
+    if let Some(inner) = inner.downcast_mut::<CliTester>() {
+        patterns.iter().for_each(|p| inner.add_fail_pattern(p));
+    } else if let Some(inner) = inner.downcast_mut::<SudoCliTester>() {
+        patterns.iter().for_each(|p| inner.add_fail_pattern(p));
+    }
+    Ok(())
+}
+
+fn parse_transfer_mode(mode: &str) -> PyResult<TransferMode> {
+    match mode {
+        "auto" => Ok(TransferMode::Auto),
+        "base64" => Ok(TransferMode::Base64),
+        "xmodem" => Ok(TransferMode::Xmodem),
+        _ => Err(PyRuntimeError::new_err(
+            "mode must be one of auto, base64 or xmodem",
+        )),
+    }
+}
+
 #[pyclass(extends=PyTty, subclass)]
 pub struct Exec {}
 
 #[pymethods]
 impl Exec {
     #[new]
-    #[pyo3(signature = (be_wrapped, sudo=None))]
-    fn py_new(be_wrapped: &mut PyTty, sudo: Option<bool>) -> PyResult<(Self, PyTty)> {
+    #[pyo3(signature = (be_wrapped, sudo=None, sudo_password=None, auto_respond=false, fail_on=None))]
+    fn py_new(
+        be_wrapped: &mut PyTty,
+        sudo: Option<bool>,
+        sudo_password: Option<&str>,
+        auto_respond: bool,
+        fail_on: Option<Vec<String>>,
+    ) -> PyResult<(Self, PyTty)> {
         let mut inner = None;
 
         handle_wrap(&mut inner, Some(be_wrapped))?;
         handle_clitester(&mut inner, sudo)?;
+        handle_responses(&mut inner, sudo_password, auto_respond)?;
+        handle_fail_on(&mut inner, fail_on.unwrap_or_default())?;
 
         Ok((Exec {}, PyTty::build(inner.unwrap())))
     }
@@ -156,6 +226,156 @@ impl Exec {
         }
     }
 
+    #[pyo3(signature = (pattern, reply, max_times=None, secret=false))]
+    fn add_response(
+        mut self_: PyRefMut<'_, Self>,
+        pattern: &str,
+        reply: &str,
+        max_times: Option<u32>,
+        secret: bool,
+    ) -> PyResult<()> {
+        let self_ = self_.as_mut();
+        let inner = self_.inner.get_mut()?;
+        let inner = inner.as_any_mut();
+
+        let mut rule = ResponseRule::new(pattern, reply, max_times);
+        rule.secret = secret;
+
+        if inner.downcast_ref::<CliTester>().is_some() {
+            let inner = inner.downcast_mut::<CliTester>().unwrap();
+            inner.add_response(rule);
+        } else if inner.downcast_ref::<SudoCliTester>().is_some() {
+            let inner = inner.downcast_mut::<SudoCliTester>().unwrap();
+            inner.add_response(rule);
+        } else {
+            return Err(PyRuntimeError::new_err(
+                "Can't find the right object to run the script",
+            ));
+        }
+        Ok(())
+    }
+
+    fn clear_responses(mut self_: PyRefMut<'_, Self>) -> PyResult<()> {
+        let self_ = self_.as_mut();
+        let inner = self_.inner.get_mut()?;
+        let inner = inner.as_any_mut();
+
+        if inner.downcast_ref::<CliTester>().is_some() {
+            let inner = inner.downcast_mut::<CliTester>().unwrap();
+            inner.clear_responses();
+        } else if inner.downcast_ref::<SudoCliTester>().is_some() {
+            let inner = inner.downcast_mut::<SudoCliTester>().unwrap();
+            inner.clear_responses();
+        } else {
+            return Err(PyRuntimeError::new_err(
+                "Can't find the right object to run the script",
+            ));
+        }
+        Ok(())
+    }
+
+    fn add_fail_pattern(mut self_: PyRefMut<'_, Self>, pattern: &str) -> PyResult<()> {
+        let self_ = self_.as_mut();
+        let inner = self_.inner.get_mut()?;
+        let inner = inner.as_any_mut();
+
+        if inner.downcast_ref::<CliTester>().is_some() {
+            let inner = inner.downcast_mut::<CliTester>().unwrap();
+            inner.add_fail_pattern(pattern);
+        } else if inner.downcast_ref::<SudoCliTester>().is_some() {
+            let inner = inner.downcast_mut::<SudoCliTester>().unwrap();
+            inner.add_fail_pattern(pattern);
+        } else {
+            return Err(PyRuntimeError::new_err(
+                "Can't find the right object to run the script",
+            ));
+        }
+        Ok(())
+    }
+
+    fn clear_fail_patterns(mut self_: PyRefMut<'_, Self>) -> PyResult<()> {
+        let self_ = self_.as_mut();
+        let inner = self_.inner.get_mut()?;
+        let inner = inner.as_any_mut();
+
+        if inner.downcast_ref::<CliTester>().is_some() {
+            let inner = inner.downcast_mut::<CliTester>().unwrap();
+            inner.clear_fail_patterns();
+        } else if inner.downcast_ref::<SudoCliTester>().is_some() {
+            let inner = inner.downcast_mut::<SudoCliTester>().unwrap();
+            inner.clear_fail_patterns();
+        } else {
+            return Err(PyRuntimeError::new_err(
+                "Can't find the right object to run the script",
+            ));
+        }
+        Ok(())
+    }
+
+    #[pyo3(signature = (timeout=None))]
+    fn sync_prompt(mut self_: PyRefMut<'_, Self>, timeout: Option<u32>) -> PyResult<String> {
+        let self_ = self_.as_mut();
+        let inner = self_.inner.get_mut()?;
+        let inner = inner.as_any_mut();
+
+        let timeout = timeout.unwrap_or(30);
+
+        if inner.downcast_ref::<CliTester>().is_some() {
+            let inner = inner.downcast_mut::<CliTester>().unwrap();
+            inner
+                .sync_prompt(timeout)
+                .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+        } else if inner.downcast_ref::<SudoCliTester>().is_some() {
+            let inner = inner.downcast_mut::<SudoCliTester>().unwrap();
+            inner
+                .sync_prompt(timeout)
+                .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+        } else {
+            Err(PyRuntimeError::new_err(
+                "Can't find the right object to run the script",
+            ))
+        }
+    }
+
+    #[pyo3(signature = (prompt, check_status=false))]
+    fn set_prompt(mut self_: PyRefMut<'_, Self>, prompt: &str, check_status: bool) -> PyResult<()> {
+        let self_ = self_.as_mut();
+        let inner = self_.inner.get_mut()?;
+        let inner = inner.as_any_mut();
+
+        if inner.downcast_ref::<CliTester>().is_some() {
+            let inner = inner.downcast_mut::<CliTester>().unwrap();
+            inner.set_prompt(prompt, check_status);
+        } else if inner.downcast_ref::<SudoCliTester>().is_some() {
+            let inner = inner.downcast_mut::<SudoCliTester>().unwrap();
+            inner.set_prompt(prompt, check_status);
+        } else {
+            return Err(PyRuntimeError::new_err(
+                "Can't find the right object to run the script",
+            ));
+        }
+        Ok(())
+    }
+
+    fn clear_prompt(mut self_: PyRefMut<'_, Self>) -> PyResult<()> {
+        let self_ = self_.as_mut();
+        let inner = self_.inner.get_mut()?;
+        let inner = inner.as_any_mut();
+
+        if inner.downcast_ref::<CliTester>().is_some() {
+            let inner = inner.downcast_mut::<CliTester>().unwrap();
+            inner.clear_prompt();
+        } else if inner.downcast_ref::<SudoCliTester>().is_some() {
+            let inner = inner.downcast_mut::<SudoCliTester>().unwrap();
+            inner.clear_prompt();
+        } else {
+            return Err(PyRuntimeError::new_err(
+                "Can't find the right object to run the script",
+            ));
+        }
+        Ok(())
+    }
+
     #[pyo3(signature = (script, timeout=None))]
     fn script_sudo(
         mut self_: PyRefMut<'_, Self>,
@@ -180,4 +400,68 @@ impl Exec {
             ))
         }
     }
+
+    #[pyo3(signature = (local, remote, mode="auto", timeout=None))]
+    fn upload(
+        mut self_: PyRefMut<'_, Self>,
+        local: &str,
+        remote: &str,
+        mode: &str,
+        timeout: Option<u32>,
+    ) -> PyResult<()> {
+        let self_ = self_.as_mut();
+        let inner = self_.inner.get_mut()?;
+        let inner = inner.as_any_mut();
+
+        let mode = parse_transfer_mode(mode)?;
+        let timeout = timeout.unwrap_or(30);
+
+        if inner.downcast_ref::<CliTester>().is_some() {
+            let inner = inner.downcast_mut::<CliTester>().unwrap();
+            inner
+                .upload(local, remote, mode, timeout)
+                .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+        } else if inner.downcast_ref::<SudoCliTester>().is_some() {
+            let inner = inner.downcast_mut::<SudoCliTester>().unwrap();
+            inner
+                .upload(local, remote, mode, timeout)
+                .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+        } else {
+            Err(PyRuntimeError::new_err(
+                "Can't find the right object to run the script",
+            ))
+        }
+    }
+
+    #[pyo3(signature = (remote, local, mode="auto", timeout=None))]
+    fn download(
+        mut self_: PyRefMut<'_, Self>,
+        remote: &str,
+        local: &str,
+        mode: &str,
+        timeout: Option<u32>,
+    ) -> PyResult<()> {
+        let self_ = self_.as_mut();
+        let inner = self_.inner.get_mut()?;
+        let inner = inner.as_any_mut();
+
+        let mode = parse_transfer_mode(mode)?;
+        let timeout = timeout.unwrap_or(30);
+
+        if inner.downcast_ref::<CliTester>().is_some() {
+            let inner = inner.downcast_mut::<CliTester>().unwrap();
+            inner
+                .download(remote, local, mode, timeout)
+                .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+        } else if inner.downcast_ref::<SudoCliTester>().is_some() {
+            let inner = inner.downcast_mut::<SudoCliTester>().unwrap();
+            inner
+                .download(remote, local, mode, timeout)
+                .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+        } else {
+            Err(PyRuntimeError::new_err(
+                "Can't find the right object to run the script",
+            ))
+        }
+    }
 }
@@ -1,9 +1,18 @@
 pub mod shell_like;
 
+pub mod adb;
+pub mod bootloader;
+pub mod bootlog;
+pub mod console_server;
 pub mod exec;
+pub mod mux;
+pub mod process;
 pub mod serial;
 pub mod shell;
+pub mod ssh;
 pub mod tee;
+pub mod transform;
+pub mod vterm;
 
 pub mod hook;
 
@@ -17,15 +26,24 @@ pub mod asciicast;
 pub mod deansi;
 
 use deansi::DeANSI;
+use adb::{Adb, Fastboot};
 use asciicast::Asciicast;
+use bootloader::Bootloader;
+use bootlog::BootLog;
+use console_server::{ConsoleClient, ConsoleServer};
 use exec::Exec;
+use mux::{Mux, MuxReader};
+use process::Process;
 use hook::build_ttyhook;
 use pylogger::{err, info, log, warn};
 use pyo3::prelude::*;
 use sdwirec::SdWirec;
 use serial::Serial;
 use shell::Shell;
+use ssh::Ssh;
 use tee::Tee;
+use transform::Transform;
+use vterm::VTerm;
 use shell_like::PyTty;
 use util::{get_log_level, run_ui, set_log_level};
 
@@ -38,10 +56,22 @@ fn tester(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<Shell>()?;
     m.add_class::<Tee>()?;
     m.add_class::<Exec>()?;
+    m.add_class::<Bootloader>()?;
     m.add_class::<Serial>()?;
+    m.add_class::<Ssh>()?;
     m.add_class::<SdWirec>()?;
     m.add_class::<Asciicast>()?;
     m.add_class::<DeANSI>()?;
+    m.add_class::<BootLog>()?;
+    m.add_class::<VTerm>()?;
+    m.add_class::<Transform>()?;
+    m.add_class::<ConsoleServer>()?;
+    m.add_class::<ConsoleClient>()?;
+    m.add_class::<Mux>()?;
+    m.add_class::<MuxReader>()?;
+    m.add_class::<Process>()?;
+    m.add_class::<Adb>()?;
+    m.add_class::<Fastboot>()?;
 
     m.add_function(wrap_pyfunction!(build_ttyhook, m)?)?;
     m.add_function(wrap_pyfunction!(set_log_level, m)?)?;
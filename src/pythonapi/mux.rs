@@ -0,0 +1,116 @@
+use pyo3::{exceptions::PyRuntimeError, pyclass, pymethods, PyRefMut, PyResult};
+
+use crate::{cli::mux::OverflowPolicy, util::anybase::heap_raw};
+
+use super::shell_like::{handle_wrap, py_tty_inner, PyTty, PyTtyInner, TtyType};
+
+fn parse_policy(policy: &str) -> PyResult<OverflowPolicy> {
+    match policy {
+        "block" => Ok(OverflowPolicy::Block),
+        "drop_oldest" => Ok(OverflowPolicy::DropOldest),
+        "disconnect" => Ok(OverflowPolicy::Disconnect),
+        _ => Err(PyRuntimeError::new_err(
+            "policy must be one of block, drop_oldest or disconnect",
+        )),
+    }
+}
+
+pub fn handle_mux_reader(
+    inner: &mut Option<PyTtyInner>,
+    mux: &crate::cli::mux::Mux,
+    capacity: usize,
+    policy: OverflowPolicy,
+) -> PyResult<()> {
+    if inner.is_some() {
+        return Err(PyRuntimeError::new_err(
+            "Seems you defined more than one unwrappable object",
+        ));
+    }
+    let reader = Box::new(mux.reader(capacity, policy)) as TtyType;
+    *inner = Some(py_tty_inner(heap_raw(reader)));
+    Ok(())
+}
+
+fn get_reader<'a>(
+    self_: &'a mut PyRefMut<'_, MuxReader>,
+) -> PyResult<&'a mut crate::cli::mux::MuxReader> {
+    let self_ = self_.as_mut();
+    let inner = self_.inner.get_mut()?;
+    inner
+        .as_any_mut()
+        .downcast_mut::<crate::cli::mux::MuxReader>()
+        .ok_or_else(|| PyRuntimeError::new_err("This type isn't a MuxReader"))
+}
+
+/// Not a PyTty, the readers are
+#[pyclass]
+pub struct Mux {
+    inner: Option<crate::cli::mux::Mux>,
+}
+
+impl Mux {
+    fn get(&self) -> PyResult<&crate::cli::mux::Mux> {
+        self.inner
+            .as_ref()
+            .ok_or_else(|| PyRuntimeError::new_err("The Mux has exited"))
+    }
+}
+
+#[pymethods]
+impl Mux {
+    #[new]
+    fn py_new(be_wrapped: &mut PyTty) -> PyResult<Self> {
+        let mut inner = None;
+        handle_wrap(&mut inner, Some(be_wrapped))?;
+        let tty = Box::into_inner(inner.unwrap().safe_take()?);
+        Ok(Mux {
+            inner: Some(crate::cli::mux::Mux::build(tty)),
+        })
+    }
+
+    /// Stop reading, close the readers and give the wrapped PyTty back
+    fn exit(&mut self) -> PyResult<PyTty> {
+        let mux = self
+            .inner
+            .take()
+            .ok_or_else(|| PyRuntimeError::new_err("The Mux has exited"))?;
+        Ok(PyTty::build(py_tty_inner(heap_raw(mux.exit()))))
+    }
+}
+
+#[pyclass(extends=PyTty, subclass)]
+pub struct MuxReader {}
+
+#[pymethods]
+impl MuxReader {
+    #[new]
+    #[pyo3(signature = (mux, capacity=1 << 20, policy="block"))]
+    fn py_new(mux: &Mux, capacity: usize, policy: &str) -> PyResult<(Self, PyTty)> {
+        let mut inner = None;
+        handle_mux_reader(&mut inner, mux.get()?, capacity, parse_policy(policy)?)?;
+        Ok((MuxReader {}, PyTty::build(inner.unwrap())))
+    }
+
+    fn claim_writer(mut self_: PyRefMut<'_, Self>) -> PyResult<()> {
+        get_reader(&mut self_)?
+            .claim_writer()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    fn release_writer(mut self_: PyRefMut<'_, Self>) -> PyResult<()> {
+        get_reader(&mut self_)?.release_writer();
+        Ok(())
+    }
+
+    fn is_writer(mut self_: PyRefMut<'_, Self>) -> PyResult<bool> {
+        Ok(get_reader(&mut self_)?.is_writer())
+    }
+
+    fn dropped(mut self_: PyRefMut<'_, Self>) -> PyResult<u64> {
+        Ok(get_reader(&mut self_)?.dropped())
+    }
+
+    fn lag(mut self_: PyRefMut<'_, Self>) -> PyResult<usize> {
+        Ok(get_reader(&mut self_)?.lag())
+    }
+}
@@ -0,0 +1,80 @@
+use std::{collections::HashMap, time::Duration};
+
+use pyo3::{exceptions::PyRuntimeError, pyclass, pymethods, PyRefMut, PyResult};
+
+use crate::{cli::process::ProcessBuilder, util::anybase::heap_raw};
+
+use super::shell_like::{py_tty_inner, PyTty, TtyType};
+
+fn get_process<'a>(
+    self_: &'a mut PyRefMut<'_, Process>,
+) -> PyResult<&'a mut crate::cli::process::Process> {
+    let self_ = self_.as_mut();
+    let inner = self_.inner.get_mut()?;
+    inner
+        .as_any_mut()
+        .downcast_mut::<crate::cli::process::Process>()
+        .ok_or_else(|| PyRuntimeError::new_err("This type isn't a Process"))
+}
+
+#[pyclass(extends=PyTty, subclass)]
+pub struct Process {}
+
+#[pymethods]
+impl Process {
+    #[new]
+    #[pyo3(signature = (program, args=None, env=None, cwd=None))]
+    fn py_new(
+        program: &str,
+        args: Option<Vec<String>>,
+        env: Option<HashMap<String, String>>,
+        cwd: Option<String>,
+    ) -> PyResult<(Self, PyTty)> {
+        let mut builder = ProcessBuilder::new(program).args(args.unwrap_or_default());
+        for (key, value) in env.unwrap_or_default() {
+            builder = builder.env(&key, &value);
+        }
+        if let Some(cwd) = cwd {
+            builder = builder.cwd(&cwd);
+        }
+        let process = builder
+            .build()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        let process = Box::new(process) as TtyType;
+        Ok((Process {}, PyTty::build(py_tty_inner(heap_raw(process)))))
+    }
+
+    fn read_stderr(mut self_: PyRefMut<'_, Self>) -> PyResult<Vec<u8>> {
+        Ok(get_process(&mut self_)?.read_stderr())
+    }
+
+    fn close_stdin(mut self_: PyRefMut<'_, Self>) -> PyResult<()> {
+        get_process(&mut self_)?.close_stdin();
+        Ok(())
+    }
+
+    fn is_alive(mut self_: PyRefMut<'_, Self>) -> PyResult<bool> {
+        Ok(get_process(&mut self_)?.is_alive())
+    }
+
+    /// The exit code, None while running or if killed by a signal
+    fn exit_status(mut self_: PyRefMut<'_, Self>) -> PyResult<Option<i32>> {
+        Ok(get_process(&mut self_)?
+            .exit_status()
+            .and_then(|status| status.code()))
+    }
+
+    #[pyo3(signature = (timeout=None))]
+    fn wait(mut self_: PyRefMut<'_, Self>, timeout: Option<u32>) -> PyResult<Option<i32>> {
+        let timeout = timeout.map(|t| Duration::from_secs(t as u64));
+        let status = get_process(&mut self_)?
+            .wait(timeout)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(status.code())
+    }
+
+    fn kill(mut self_: PyRefMut<'_, Self>) -> PyResult<()> {
+        get_process(&mut self_)?.kill();
+        Ok(())
+    }
+}
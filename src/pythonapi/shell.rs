@@ -1,40 +1,106 @@
-use pyo3::{exceptions::PyRuntimeError, pyclass, pymethods, PyResult};
+use std::collections::HashMap;
+
+use pyo3::{exceptions::PyRuntimeError, pyclass, pymethods, PyRefMut, PyResult};
 use serde::Deserialize;
 
-use crate::util::anybase::heap_raw;
+use crate::{cli::shell::ShellBuilder, util::anybase::heap_raw};
 
 use super::shell_like::{py_tty_inner, PyTty, PyTtyInner, TtyType};
 
 #[derive(Deserialize)]
 pub struct ShellConf {
     pub shell: Option<String>,
+    pub args: Option<Vec<String>>,
+    pub env: Option<HashMap<String, String>>,
+    pub cwd: Option<String>,
+    pub cols: Option<u16>,
+    pub rows: Option<u16>,
+}
+
+fn build_shell(shell_conf: ShellConf) -> PyResult<crate::cli::shell::Shell> {
+    let mut builder = ShellBuilder::new(shell_conf.shell.as_deref().unwrap_or("/bin/sh"));
+    if let Some(args) = shell_conf.args {
+        builder = builder.args(args);
+    }
+    for (key, value) in shell_conf.env.unwrap_or_default() {
+        builder = builder.env(&key, &value);
+    }
+    if let Some(cwd) = shell_conf.cwd {
+        builder = builder.cwd(&cwd);
+    }
+    builder = builder.size(shell_conf.cols.unwrap_or(80), shell_conf.rows.unwrap_or(24));
+    builder
+        .build()
+        .map_err(|e| PyRuntimeError::new_err(e.to_string()))
 }
 
 pub fn handle_shell(inner: &mut Option<PyTtyInner>, shell_conf: ShellConf) -> PyResult<()> {
-    let shell = shell_conf.shell.as_deref();
-    let shell = crate::cli::shell::Shell::build(shell)
-        .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
     if inner.is_some() {
         return Err(PyRuntimeError::new_err(
             "Seems you defined more than one unwrappable object",
         ));
     }
+    let shell = build_shell(shell_conf)?;
     let shell = Box::new(shell) as TtyType;
     *inner = Some(py_tty_inner(heap_raw(shell)));
     Ok(())
 }
 
+fn get_shell<'a>(self_: &'a mut PyRefMut<'_, Shell>) -> PyResult<&'a mut crate::cli::shell::Shell> {
+    let self_ = self_.as_mut();
+    let inner = self_.inner.get_mut()?;
+    inner
+        .as_any_mut()
+        .downcast_mut::<crate::cli::shell::Shell>()
+        .ok_or_else(|| PyRuntimeError::new_err("This type isn't a Shell"))
+}
+
 #[pyclass(extends=PyTty, subclass)]
 pub struct Shell {}
 
 #[pymethods]
 impl Shell {
     #[new]
-    #[pyo3(signature = (shell=None))]
-    fn py_new(shell: Option<&str>) -> PyResult<(Self, PyTty)> {
-        let shell = crate::cli::shell::Shell::build(shell)
-            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+    #[pyo3(signature = (shell=None, args=None, env=None, cwd=None, cols=80, rows=24))]
+    fn py_new(
+        shell: Option<String>,
+        args: Option<Vec<String>>,
+        env: Option<HashMap<String, String>>,
+        cwd: Option<String>,
+        cols: u16,
+        rows: u16,
+    ) -> PyResult<(Self, PyTty)> {
+        let shell = build_shell(ShellConf {
+            shell,
+            args,
+            env,
+            cwd,
+            cols: Some(cols),
+            rows: Some(rows),
+        })?;
         let shell = Box::new(shell) as TtyType;
         Ok((Shell {}, PyTty::build(py_tty_inner(heap_raw(shell)))))
     }
+
+    fn resize(mut self_: PyRefMut<'_, Self>, cols: u16, rows: u16) -> PyResult<()> {
+        get_shell(&mut self_)?
+            .resize(cols, rows)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    fn is_alive(mut self_: PyRefMut<'_, Self>) -> PyResult<bool> {
+        Ok(get_shell(&mut self_)?.is_alive())
+    }
+
+    /// The exit code, None while the shell is running
+    fn exit_status(mut self_: PyRefMut<'_, Self>) -> PyResult<Option<u32>> {
+        Ok(get_shell(&mut self_)?
+            .exit_status()
+            .map(|status| status.exit_code()))
+    }
+
+    fn shutdown(mut self_: PyRefMut<'_, Self>) -> PyResult<()> {
+        get_shell(&mut self_)?.shutdown();
+        Ok(())
+    }
 }
@@ -3,22 +3,31 @@ use std::ptr::null_mut;
 use pyo3::{exceptions::PyRuntimeError, prelude::*};
 use serde::Deserialize;
 
+#[cfg(unix)]
+use crate::cli::interact::{interact, DEFAULT_ESCAPE};
 use crate::{
     cli::{
         asciicast::Asciicast,
+        bootlog::BootLog,
+        console_server::ConsoleServer,
         deansi::DeANSI,
         recorder::{Recorder, SimpleRecorder},
         tee::Tee,
+        transform::Transform,
         tty::{DynTty, WrapperTty},
+        vterm::VTerm,
+    },
+    exec::{
+        bootloader::BootloaderTester,
+        cli_exec::{CliTester, SudoCliTester},
     },
-    exec::cli_exec::{CliTester, SudoCliTester},
     log,
     pythonapi::{asciicast::handle_asciicast, tee::handle_tee},
     util::anybase::heap_raw,
 };
 
 use super::{
-    exec::handle_clitester,
+    exec::{handle_clitester, handle_fail_on, handle_responses},
     hook::TtyHook,
     shell::{handle_shell, ShellConf},
     tee::PyTeeConf,
@@ -76,7 +85,16 @@ impl PyTtyWrapper for PyTtyWrapperBasic {
             ));
         }
         self.tty = tty;
-        Ok(())  
+        Ok(())
+    }
+}
+
+impl Drop for PyTtyWrapperBasic {
+    fn drop(&mut self) {
+        // Owned unless taken, free it so Drop of the Tty runs, e.g. to kill a process
+        if !self.tty.is_null() {
+            drop(unsafe { Box::from_raw(self.tty) });
+        }
     }
 }
 
@@ -119,6 +137,9 @@ struct PyTtyConf {
 #[derive(Deserialize)]
 struct PyTtyExecConf {
     sudo: Option<bool>,
+    sudo_password: Option<String>,
+    auto_respond: Option<bool>,
+    fail_on: Option<Vec<String>>,
 }
 
 pub fn handle_wrap(inner: &mut Option<PyTtyInner>, be_wrapped: Option<&mut PyTty>) -> PyResult<()> {
@@ -133,6 +154,14 @@ pub fn handle_wrap(inner: &mut Option<PyTtyInner>, be_wrapped: Option<&mut PyTty
     Ok(())
 }
 
+/// Give the Tty taken by [`handle_wrap`] back to `be_wrapped`, when wrapping it failed.
+pub fn handle_unwrap(inner: &mut Option<PyTtyInner>, be_wrapped: &mut PyTty) -> PyResult<()> {
+    if let Some(mut tty) = inner.take() {
+        be_wrapped.inner.put(tty.take()?)?;
+    }
+    Ok(())
+}
+
 pub fn handle_simple_recorder(inner: &mut Option<PyTtyInner>) -> PyResult<()> {
     if inner.is_none() {
         return Err(PyRuntimeError::new_err(
@@ -149,6 +178,22 @@ pub fn handle_simple_recorder(inner: &mut Option<PyTtyInner>) -> PyResult<()> {
     Ok(())
 }
 
+/// Whether [`PyTty::exit`] can unwrap `tty`
+fn can_exit(tty: &TtyType) -> bool {
+    let any = tty.as_any();
+    any.is::<SimpleRecorder>()
+        || any.is::<Asciicast>()
+        || any.is::<DeANSI>()
+        || any.is::<Tee>()
+        || any.is::<ConsoleServer>()
+        || any.is::<Transform>()
+        || any.is::<CliTester>()
+        || any.is::<SudoCliTester>()
+        || any.is::<BootloaderTester>()
+        || any.is::<BootLog>()
+        || any.is::<VTerm>()
+}
+
 /**
  * Shell
  * Serial
@@ -169,21 +214,28 @@ impl PyTty {
      * separate class, while new Tty-like object will
      * not be added in this class.
      */
-    fn py_new(conf: &str, be_wrapped: Option<&mut PyTty>) -> PyResult<Self> {
+    fn py_new(conf: &str, mut be_wrapped: Option<&mut PyTty>) -> PyResult<Self> {
         log!("Got conf: {}", conf);
 
         let conf: PyTtyConf = toml::from_str(conf).unwrap();
 
         let mut inner = None;
 
-        if conf.wrap.is_some_and(|x| x) {
-            handle_wrap(&mut inner, be_wrapped)?;
+        let wrap = conf.wrap.is_some_and(|x| x);
+        if wrap {
+            handle_wrap(&mut inner, be_wrapped.as_deref_mut())?;
         }
         if let Some(shell_conf) = conf.shell {
             handle_shell(&mut inner, shell_conf)?;
         }
         if let Some(tee_conf) = conf.tee {
-            handle_tee(&mut inner, tee_conf)?;
+            if let Err(e) = handle_tee(&mut inner, tee_conf) {
+                // Hand the wrapped console back, a bad path mustn't cost it
+                if let Some(be_wrapped) = be_wrapped.filter(|_| wrap) {
+                    handle_unwrap(&mut inner, be_wrapped)?;
+                }
+                return Err(e);
+            }
         }
         if conf.simple_recorder.is_some_and(|x| x) {
             handle_simple_recorder(&mut inner)?;
@@ -194,6 +246,12 @@ impl PyTty {
         if conf.exec.is_some() {
             let exec_conf = conf.exec.unwrap();
             handle_clitester(&mut inner, exec_conf.sudo)?;
+            handle_responses(
+                &mut inner,
+                exec_conf.sudo_password.as_deref(),
+                exec_conf.auto_respond.unwrap_or(false),
+            )?;
+            handle_fail_on(&mut inner, exec_conf.fail_on.unwrap_or_default())?;
         }
 
         if inner.is_none() {
@@ -227,9 +285,42 @@ impl PyTty {
             .map_err(|e| PyRuntimeError::new_err(e.to_string()))
     }
 
+    /// Hand the console to the user until the escape key (Ctrl-] by default)
+    #[cfg(unix)]
+    #[pyo3(signature = (escape=None))]
+    fn interact(&mut self, escape: Option<&str>) -> PyResult<()> {
+        let escape = match escape.map(|e| e.as_bytes()) {
+            None => DEFAULT_ESCAPE,
+            Some([b]) => *b,
+            Some(_) => {
+                return Err(PyRuntimeError::new_err(
+                    "escape must be a single ASCII char, e.g. \"\\x1d\"",
+                ))
+            }
+        };
+        let inner = self.inner.get_mut()?;
+        interact(inner.as_mut(), escape).map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// The raw terminal mode needs termios, unix only
+    #[cfg(not(unix))]
+    #[pyo3(signature = (escape=None))]
+    fn interact(&mut self, escape: Option<&str>) -> PyResult<()> {
+        let _ = escape;
+        Err(PyRuntimeError::new_err(
+            "Interactive mode is not supported on this platform",
+        ))
+    }
+
     // WrapperTty begin
 
     fn exit(&mut self) -> PyResult<Self> {
+        // Check before taking, so an unsupported type is left in place
+        if !can_exit(self.inner.get()?) {
+            return Err(PyRuntimeError::new_err(
+                "This type doesn't have function exit",
+            ));
+        }
         let inner = self.inner.safe_take()?;
         let inner = Box::into_inner(inner);
         let inner = inner.into_any();
@@ -258,6 +349,18 @@ impl PyTty {
             Ok(PyTty {
                 inner: py_tty_inner(heap_raw(inner)),
             })
+        } else if inner.downcast_ref::<ConsoleServer>().is_some() {
+            let inner = inner.downcast::<ConsoleServer>().unwrap();
+            let inner = inner.exit();
+            Ok(PyTty {
+                inner: py_tty_inner(heap_raw(inner)),
+            })
+        } else if inner.downcast_ref::<Transform>().is_some() {
+            let inner = inner.downcast::<Transform>().unwrap();
+            let inner = inner.exit();
+            Ok(PyTty {
+                inner: py_tty_inner(heap_raw(inner)),
+            })
         } else if inner.downcast_ref::<CliTester>().is_some() {
             let inner = inner.downcast::<CliTester>().unwrap();
             let inner = inner.exit();
@@ -270,6 +373,24 @@ impl PyTty {
             Ok(PyTty {
                 inner: py_tty_inner(heap_raw(inner)),
             })
+        } else if inner.downcast_ref::<BootloaderTester>().is_some() {
+            let inner = inner.downcast::<BootloaderTester>().unwrap();
+            let inner = inner.exit();
+            Ok(PyTty {
+                inner: py_tty_inner(heap_raw(inner)),
+            })
+        } else if inner.downcast_ref::<BootLog>().is_some() {
+            let inner = inner.downcast::<BootLog>().unwrap();
+            let inner = inner.exit();
+            Ok(PyTty {
+                inner: py_tty_inner(heap_raw(inner)),
+            })
+        } else if inner.downcast_ref::<VTerm>().is_some() {
+            let inner = inner.downcast::<VTerm>().unwrap();
+            let inner = inner.exit();
+            Ok(PyTty {
+                inner: py_tty_inner(heap_raw(inner)),
+            })
         } else {
             Err(PyRuntimeError::new_err(
                 "This type doesn't have function exit",
@@ -0,0 +1,237 @@
+use pyo3::{exceptions::PyRuntimeError, pyclass, pymethods, PyRefMut, PyResult};
+use serde::Deserialize;
+
+use crate::{
+    cli::ssh::{HostKeyCheck, SshConf, SshPass},
+    util::anybase::heap_raw,
+};
+
+use super::shell_like::{py_tty_inner, PyTty, TtyType};
+
+pub fn parse_host_key_check(check: &str) -> PyResult<HostKeyCheck> {
+    match check {
+        "strict" => Ok(HostKeyCheck::Strict),
+        "accept-new" => Ok(HostKeyCheck::AcceptNew),
+        "off" => Ok(HostKeyCheck::Off),
+        _ => Err(PyRuntimeError::new_err(
+            "host_key_check must be one of strict, accept-new or off",
+        )),
+    }
+}
+
+pub fn parse_ssh_pass(
+    password: Option<String>,
+    key: Option<String>,
+    agent: bool,
+    keyboard_interactive: bool,
+) -> PyResult<SshPass> {
+    if let Some(key) = key {
+        Ok(SshPass::Key(key))
+    } else if let Some(password) = password {
+        if keyboard_interactive {
+            Ok(SshPass::KeyboardInteractive(password))
+        } else {
+            Ok(SshPass::Password(password))
+        }
+    } else if agent {
+        Ok(SshPass::Agent)
+    } else {
+        Err(PyRuntimeError::new_err(
+            "You must give one of password, key or agent",
+        ))
+    }
+}
+
+/// One bastion for `jump`, given as a toml string
+#[derive(Deserialize)]
+pub struct SshJumpConf {
+    pub host: String,
+    pub port: Option<u16>,
+    pub user: String,
+    pub password: Option<String>,
+    pub key: Option<String>,
+    pub agent: Option<bool>,
+    pub keyboard_interactive: Option<bool>,
+    pub host_key_check: Option<String>,
+    pub known_hosts: Option<String>,
+}
+
+pub fn parse_jump_conf(conf: &str) -> PyResult<SshConf> {
+    let conf: SshJumpConf =
+        toml::from_str(conf).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+    let pass = parse_ssh_pass(
+        conf.password,
+        conf.key,
+        conf.agent.unwrap_or(false),
+        conf.keyboard_interactive.unwrap_or(false),
+    )?;
+    let mut res = SshConf::new(&conf.host, conf.port.unwrap_or(22), &conf.user, pass);
+    if let Some(check) = conf.host_key_check {
+        res.host_key_check = parse_host_key_check(&check)?;
+    }
+    res.known_hosts = conf.known_hosts;
+    Ok(res)
+}
+
+#[pyclass(extends=PyTty, subclass)]
+pub struct Ssh {}
+
+#[pymethods]
+impl Ssh {
+    #[new]
+    #[pyo3(signature = (
+        host,
+        user,
+        port=22,
+        password=None,
+        key=None,
+        agent=false,
+        keyboard_interactive=false,
+        host_key_check="accept-new",
+        known_hosts=None,
+        term="xterm",
+        cols=80,
+        rows=24,
+        keepalive=0,
+        reconnect=0,
+        timeout=10000,
+        jump=None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn py_new(
+        host: &str,
+        user: &str,
+        port: u16,
+        password: Option<String>,
+        key: Option<String>,
+        agent: bool,
+        keyboard_interactive: bool,
+        host_key_check: &str,
+        known_hosts: Option<String>,
+        term: &str,
+        cols: u32,
+        rows: u32,
+        keepalive: u32,
+        reconnect: u32,
+        timeout: u32,
+        jump: Option<Vec<String>>,
+    ) -> PyResult<(Self, PyTty)> {
+        let pass = parse_ssh_pass(password, key, agent, keyboard_interactive)?;
+        let mut conf = SshConf::new(host, port, user, pass);
+        conf.host_key_check = parse_host_key_check(host_key_check)?;
+        conf.known_hosts = known_hosts;
+        conf.term = term.to_owned();
+        conf.cols = cols;
+        conf.rows = rows;
+        conf.keepalive = keepalive;
+        conf.reconnect = reconnect;
+        conf.timeout = timeout;
+        for hop in jump.unwrap_or_default() {
+            conf.jump.push(parse_jump_conf(&hop)?);
+        }
+
+        let ssh = crate::cli::ssh::Ssh::build_conf(conf)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        let ssh = Box::new(ssh) as TtyType;
+        Ok((Ssh {}, PyTty::build(py_tty_inner(heap_raw(ssh)))))
+    }
+
+    fn resize(mut self_: PyRefMut<'_, Self>, cols: u32, rows: u32) -> PyResult<()> {
+        let self_ = self_.as_mut();
+        let inner = self_.inner.get_mut()?;
+        let inner = inner.as_any_mut();
+
+        if let Some(inner) = inner.downcast_mut::<crate::cli::ssh::Ssh>() {
+            inner
+                .resize(cols, rows)
+                .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+        } else {
+            Err(PyRuntimeError::new_err(
+                "This type doesn't have function resize",
+            ))
+        }
+    }
+
+    #[pyo3(signature = (remote_host, remote_port, local_port=0))]
+    fn forward_local(
+        mut self_: PyRefMut<'_, Self>,
+        remote_host: &str,
+        remote_port: u16,
+        local_port: u16,
+    ) -> PyResult<u16> {
+        let self_ = self_.as_mut();
+        let inner = self_.inner.get_mut()?;
+        let inner = inner.as_any_mut();
+
+        if let Some(inner) = inner.downcast_mut::<crate::cli::ssh::Ssh>() {
+            let addr = inner
+                .forward_local(local_port, remote_host, remote_port)
+                .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+            Ok(addr.port())
+        } else {
+            Err(PyRuntimeError::new_err(
+                "This type doesn't have function forward_local",
+            ))
+        }
+    }
+
+    #[pyo3(signature = (cmd, timeout=None))]
+    fn exec(
+        mut self_: PyRefMut<'_, Self>,
+        cmd: &str,
+        timeout: Option<u32>,
+    ) -> PyResult<(String, String, i32)> {
+        let self_ = self_.as_mut();
+        let inner = self_.inner.get_mut()?;
+        let inner = inner.as_any_mut();
+
+        let timeout = timeout.unwrap_or(30);
+
+        if let Some(inner) = inner.downcast_mut::<crate::cli::ssh::Ssh>() {
+            let res = inner
+                .exec(cmd, timeout)
+                .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+            Ok((
+                String::from_utf8_lossy(&res.stdout).to_string(),
+                String::from_utf8_lossy(&res.stderr).to_string(),
+                res.status,
+            ))
+        } else {
+            Err(PyRuntimeError::new_err(
+                "This type doesn't have function exec",
+            ))
+        }
+    }
+
+    fn upload(mut self_: PyRefMut<'_, Self>, local: &str, remote: &str) -> PyResult<u64> {
+        let self_ = self_.as_mut();
+        let inner = self_.inner.get_mut()?;
+        let inner = inner.as_any_mut();
+
+        if let Some(inner) = inner.downcast_mut::<crate::cli::ssh::Ssh>() {
+            inner
+                .upload(local, remote)
+                .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+        } else {
+            Err(PyRuntimeError::new_err(
+                "This type doesn't have function upload",
+            ))
+        }
+    }
+
+    fn download(mut self_: PyRefMut<'_, Self>, remote: &str, local: &str) -> PyResult<u64> {
+        let self_ = self_.as_mut();
+        let inner = self_.inner.get_mut()?;
+        let inner = inner.as_any_mut();
+
+        if let Some(inner) = inner.downcast_mut::<crate::cli::ssh::Ssh>() {
+            inner
+                .download(remote, local)
+                .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+        } else {
+            Err(PyRuntimeError::new_err(
+                "This type doesn't have function download",
+            ))
+        }
+    }
+}
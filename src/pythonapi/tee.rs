@@ -1,13 +1,20 @@
 use pyo3::{exceptions::PyRuntimeError, pyclass, pymethods, PyResult};
 use serde::Deserialize;
 
-use crate::util::anybase::heap_raw;
+use crate::{
+    cli::tee::{TeeFile, TeeOptions},
+    util::anybase::heap_raw,
+};
 
-use super::shell_like::{handle_wrap, py_tty_inner, PyTty, PyTtyInner, TtyType};
+use super::shell_like::{handle_unwrap, handle_wrap, py_tty_inner, PyTty, PyTtyInner, TtyType};
 
 #[derive(Deserialize)]
 pub struct PyTeeConf {
     pub path: String,
+    pub transcript: Option<bool>,
+    pub append: Option<bool>,
+    pub max_size: Option<u64>,
+    pub keep: Option<usize>,
 }
 
 pub fn handle_tee(inner: &mut Option<PyTtyInner>, tee_conf: PyTeeConf) -> PyResult<()> {
@@ -17,11 +24,20 @@ pub fn handle_tee(inner: &mut Option<PyTtyInner>, tee_conf: PyTeeConf) -> PyResu
             "You must define at least one valid object",
         ));
     }
+    let default = TeeOptions::default();
+    let options = TeeOptions {
+        transcript: tee_conf.transcript.unwrap_or(default.transcript),
+        append: tee_conf.append.unwrap_or(default.append),
+        max_size: tee_conf.max_size,
+        keep: tee_conf.keep.unwrap_or(default.keep),
+    };
+    // Open the file before taking the Tty, so a bad path leaves it in place
+    let file = TeeFile::open(&path, options).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
     let mut be_wrapped = inner.take().unwrap();
     let be_wrapped = be_wrapped.safe_take()?;
     let be_wrapped = Box::into_inner(be_wrapped);
-    let tee = Box::new(crate::cli::tee::Tee::build(be_wrapped, &path));
-    let tee = tee as TtyType;
+    let tee = crate::cli::tee::Tee::with_file(be_wrapped, file);
+    let tee = Box::new(tee) as TtyType;
     *inner = Some(py_tty_inner(heap_raw(tee)));
     Ok(())
 }
@@ -32,16 +48,29 @@ pub struct Tee {}
 #[pymethods]
 impl Tee {
     #[new]
-    fn py_new(be_wrapped: &mut PyTty, path: &str) -> PyResult<(Self, PyTty)> {
+    #[pyo3(signature = (be_wrapped, path, transcript=false, append=false, max_size=None, keep=None))]
+    fn py_new(
+        be_wrapped: &mut PyTty,
+        path: &str,
+        transcript: bool,
+        append: bool,
+        max_size: Option<u64>,
+        keep: Option<usize>,
+    ) -> PyResult<(Self, PyTty)> {
         let mut inner = None;
 
-        handle_wrap(&mut inner, Some(be_wrapped))?;
-        handle_tee(
-            &mut inner,
-            PyTeeConf {
-                path: path.to_owned(),
-            },
-        )?;
+        handle_wrap(&mut inner, Some(&mut *be_wrapped))?;
+        let conf = PyTeeConf {
+            path: path.to_owned(),
+            transcript: Some(transcript),
+            append: Some(append),
+            max_size,
+            keep,
+        };
+        if let Err(e) = handle_tee(&mut inner, conf) {
+            handle_unwrap(&mut inner, be_wrapped)?;
+            return Err(e);
+        }
 
         Ok((Tee {}, PyTty::build(inner.unwrap())))
     }
@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use pyo3::{exceptions::PyRuntimeError, pyclass, pymethods, PyResult};
+
+use crate::{
+    cli::transform::{Charset, LineEnding, TransformOptions},
+    util::anybase::heap_raw,
+};
+
+use super::shell_like::{handle_wrap, py_tty_inner, PyTty, PyTtyInner, TtyType};
+
+fn parse_line_ending(eol: Option<&str>) -> PyResult<LineEnding> {
+    match eol {
+        None => Ok(LineEnding::Keep),
+        Some("lf") => Ok(LineEnding::Lf),
+        Some("crlf") => Ok(LineEnding::CrLf),
+        Some("cr") => Ok(LineEnding::Cr),
+        _ => Err(PyRuntimeError::new_err(
+            "line ending must be one of lf, crlf or cr",
+        )),
+    }
+}
+
+fn parse_charset(charset: &str) -> PyResult<Charset> {
+    match charset {
+        "utf-8" | "utf8" => Ok(Charset::Utf8),
+        "latin-1" | "latin1" | "iso-8859-1" => Ok(Charset::Latin1),
+        "ascii" => Ok(Charset::Ascii),
+        _ => Err(PyRuntimeError::new_err(
+            "charset must be one of utf-8, latin-1 or ascii",
+        )),
+    }
+}
+
+pub fn handle_transform(inner: &mut Option<PyTtyInner>, options: TransformOptions) -> PyResult<()> {
+    if inner.is_none() {
+        return Err(PyRuntimeError::new_err(
+            "You must define at least one valid object",
+        ));
+    }
+    let mut be_wrapped = inner.take().unwrap();
+    let be_wrapped = be_wrapped.safe_take()?;
+    let be_wrapped = Box::into_inner(be_wrapped);
+    let transform = Box::new(crate::cli::transform::Transform::build(be_wrapped, options));
+    let transform = transform as TtyType;
+    *inner = Some(py_tty_inner(heap_raw(transform)));
+    Ok(())
+}
+
+#[pyclass(extends=PyTty, subclass)]
+pub struct Transform {}
+
+#[pymethods]
+impl Transform {
+    #[new]
+    #[pyo3(signature = (
+        be_wrapped,
+        write_eol=None,
+        read_eol=None,
+        char_delay=0,
+        chunk_size=0,
+        chunk_delay=0,
+        max_rate=None,
+        charset="utf-8"
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn py_new(
+        be_wrapped: &mut PyTty,
+        write_eol: Option<&str>,
+        read_eol: Option<&str>,
+        char_delay: u64,
+        chunk_size: usize,
+        chunk_delay: u64,
+        max_rate: Option<u32>,
+        charset: &str,
+    ) -> PyResult<(Self, PyTty)> {
+        let options = TransformOptions {
+            write_eol: parse_line_ending(write_eol)?,
+            read_eol: parse_line_ending(read_eol)?,
+            char_delay: Duration::from_millis(char_delay),
+            chunk_size,
+            chunk_delay: Duration::from_millis(chunk_delay),
+            max_rate,
+            charset: parse_charset(charset)?,
+        };
+        let mut inner = None;
+        handle_wrap(&mut inner, Some(be_wrapped))?;
+        handle_transform(&mut inner, options)?;
+        Ok((Transform {}, PyTty::build(inner.unwrap())))
+    }
+}
@@ -0,0 +1,103 @@
+use pyo3::{exceptions::PyRuntimeError, pyclass, pymethods, PyRefMut, PyResult};
+
+use crate::{cli::tty::Tty, util::anybase::heap_raw};
+
+use super::shell_like::{handle_wrap, py_tty_inner, PyTty, PyTtyInner, TtyType};
+
+pub fn handle_vterm(inner: &mut Option<PyTtyInner>, cols: usize, rows: usize) -> PyResult<()> {
+    if inner.is_none() {
+        return Err(PyRuntimeError::new_err(
+            "You must define at least one valid object",
+        ));
+    }
+    let mut be_wrapped = inner.take().unwrap();
+    let be_wrapped = be_wrapped.safe_take()?;
+    let be_wrapped = Box::into_inner(be_wrapped);
+    let vterm = Box::new(crate::cli::vterm::VTerm::build(be_wrapped, cols, rows));
+    let vterm: Box<dyn Tty + Send> = vterm as TtyType;
+    *inner = Some(py_tty_inner(heap_raw(vterm)));
+    Ok(())
+}
+
+fn get_vterm<'a>(self_: &'a mut PyRefMut<'_, VTerm>) -> PyResult<&'a mut crate::cli::vterm::VTerm> {
+    let self_ = self_.as_mut();
+    let inner = self_.inner.get_mut()?;
+    inner
+        .as_any_mut()
+        .downcast_mut::<crate::cli::vterm::VTerm>()
+        .ok_or_else(|| PyRuntimeError::new_err("This type isn't a VTerm"))
+}
+
+#[pyclass(extends=PyTty, subclass)]
+pub struct VTerm {}
+
+#[pymethods]
+impl VTerm {
+    #[new]
+    #[pyo3(signature = (be_wrapped, cols=80, rows=24))]
+    fn py_new(be_wrapped: &mut PyTty, cols: usize, rows: usize) -> PyResult<(Self, PyTty)> {
+        let mut inner = None;
+        handle_wrap(&mut inner, Some(be_wrapped))?;
+        handle_vterm(&mut inner, cols, rows)?;
+        Ok((VTerm {}, PyTty::build(inner.unwrap())))
+    }
+
+    fn update(mut self_: PyRefMut<'_, Self>) -> PyResult<()> {
+        get_vterm(&mut self_)?
+            .update()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    fn resize(mut self_: PyRefMut<'_, Self>, cols: usize, rows: usize) -> PyResult<()> {
+        get_vterm(&mut self_)?.resize(cols, rows);
+        Ok(())
+    }
+
+    fn screen_text(mut self_: PyRefMut<'_, Self>) -> PyResult<String> {
+        Ok(get_vterm(&mut self_)?.screen_text())
+    }
+
+    fn row_text(mut self_: PyRefMut<'_, Self>, row: usize) -> PyResult<String> {
+        Ok(get_vterm(&mut self_)?.emulator().row_text(row))
+    }
+
+    fn text_at(
+        mut self_: PyRefMut<'_, Self>,
+        row: usize,
+        col: usize,
+        len: usize,
+    ) -> PyResult<String> {
+        Ok(get_vterm(&mut self_)?.emulator().text_at(row, col, len))
+    }
+
+    fn cursor(mut self_: PyRefMut<'_, Self>) -> PyResult<(usize, usize)> {
+        Ok(get_vterm(&mut self_)?.emulator().cursor())
+    }
+
+    fn find(mut self_: PyRefMut<'_, Self>, text: &str) -> PyResult<Option<(usize, usize)>> {
+        Ok(get_vterm(&mut self_)?.emulator().find(text))
+    }
+
+    fn assert_text_at(
+        mut self_: PyRefMut<'_, Self>,
+        row: usize,
+        col: usize,
+        text: &str,
+    ) -> PyResult<()> {
+        get_vterm(&mut self_)?
+            .assert_text_at(row, col, text)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    #[pyo3(signature = (text, timeout=None))]
+    fn wait_screen(
+        mut self_: PyRefMut<'_, Self>,
+        text: &str,
+        timeout: Option<u32>,
+    ) -> PyResult<(usize, usize)> {
+        let timeout = timeout.unwrap_or(30);
+        get_vterm(&mut self_)?
+            .wait_screen(text, timeout)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+}
@@ -0,0 +1,208 @@
+#!/usr/bin/env python3
+"""A tiny fake adbd speaking the adb wire protocol over TCP, for test_adb.py.
+
+It listens on a free port of 127.0.0.1 and prints the port. Files pushed
+with the sync protocol are kept in memory, paths under /proc can't be
+written.
+"""
+import socket
+import struct
+import sys
+import threading
+
+A_CNXN = b"CNXN"
+A_OPEN = b"OPEN"
+A_OKAY = b"OKAY"
+A_WRTE = b"WRTE"
+A_CLSE = b"CLSE"
+ADB_VERSION = 0x01000001
+MAX_PAYLOAD = 64 * 1024
+REMOTE_ID = 0x42
+
+files = {"/etc/hostname": (0o100644, b"fake\n")}
+lock = threading.Lock()
+
+
+class Closed(Exception):
+    pass
+
+
+class Conn:
+    def __init__(self, sock):
+        self.sock = sock
+        self.local = 0
+        self.max_payload = MAX_PAYLOAD
+        self.inbox = b""
+
+    def recv_exact(self, n):
+        buf = b""
+        while len(buf) < n:
+            data = self.sock.recv(n - len(buf))
+            if not data:
+                raise Closed()
+            buf += data
+        return buf
+
+    def read_message(self):
+        head = self.recv_exact(24)
+        cmd, arg0, arg1, length, _, magic = struct.unpack("<4sIIIII", head)
+        assert magic == struct.unpack("<I", cmd)[0] ^ 0xFFFFFFFF, "bad magic"
+        return cmd, arg0, arg1, self.recv_exact(length)
+
+    def write_message(self, cmd, arg0, arg1, data=b""):
+        head = struct.pack("<4sIIII", cmd, arg0, arg1, len(data), sum(data) & 0xFFFFFFFF)
+        head += struct.pack("<I", struct.unpack("<I", cmd)[0] ^ 0xFFFFFFFF)
+        self.sock.sendall(head + data)
+
+    def take(self, msg):
+        """Handle a message of the open stream, False once it's closed."""
+        cmd, _, _, data = msg
+        if cmd == A_WRTE:
+            self.inbox += data
+            self.write_message(A_OKAY, REMOTE_ID, self.local)
+        elif cmd == A_CLSE:
+            return False
+        return True
+
+    def recv(self, n):
+        while len(self.inbox) < n:
+            if not self.take(self.read_message()):
+                raise Closed()
+        data, self.inbox = self.inbox[:n], self.inbox[n:]
+        return data
+
+    def send(self, data):
+        # Like adbd, wait for each write to be taken before the next one
+        for i in range(0, len(data), self.max_payload):
+            self.write_message(A_WRTE, REMOTE_ID, self.local, data[i : i + self.max_payload])
+            while True:
+                msg = self.read_message()
+                if msg[0] == A_OKAY:
+                    break
+                if not self.take(msg):
+                    raise Closed()
+
+    def close(self):
+        self.write_message(A_CLSE, REMOTE_ID, self.local)
+
+
+def run(cmd):
+    args = cmd.split()
+    if not args:
+        return b""
+    if args[0] == "echo":
+        return (" ".join(args[1:]) + "\n").encode()
+    if args[0] == "cat" and len(args) == 2:
+        with lock:
+            if args[1] in files:
+                return files[args[1]][1]
+        return ("cat: %s: No such file or directory\n" % args[1]).encode()
+    if args[0] == "stat" and args[1:3] == ["-c", "%a"] and len(args) == 4:
+        with lock:
+            if args[3] in files:
+                return ("%o\n" % (files[args[3]][0] & 0o777)).encode()
+        return ("stat: can't stat '%s': No such file or directory\n" % args[3]).encode()
+    return ("/system/bin/sh: %s: not found\n" % args[0]).encode()
+
+
+def shell(conn):
+    conn.send(b"fake:/ # ")
+    line = b""
+    while True:
+        c = conn.recv(1)
+        if c == b"\r":
+            continue
+        conn.send(c)
+        if c != b"\n":
+            line += c
+            continue
+        if line.strip() == b"exit":
+            return
+        conn.send(run(line.decode(errors="replace")) + b"fake:/ # ")
+        line = b""
+
+
+def sync_fail(conn, msg):
+    msg = msg.encode()
+    conn.send(b"FAIL" + struct.pack("<I", len(msg)) + msg)
+
+
+def sync(conn):
+    while True:
+        req = conn.recv(8)
+        id_, length = req[:4], struct.unpack("<I", req[4:])[0]
+        if id_ == b"QUIT":
+            return
+        arg = conn.recv(length).decode()
+        if id_ == b"SEND":
+            path, mode = arg.rsplit(",", 1)
+            data = b""
+            while True:
+                req = conn.recv(8)
+                id_, length = req[:4], struct.unpack("<I", req[4:])[0]
+                if id_ == b"DONE":
+                    break
+                assert id_ == b"DATA", "bad sync request %r" % id_
+                assert length <= 64 * 1024, "sync chunk too large"
+                data += conn.recv(length)
+            if path.startswith("/proc/"):
+                sync_fail(conn, "couldn't create file: Read-only file system")
+                continue
+            with lock:
+                files[path] = (int(mode), data)
+            conn.send(b"OKAY" + struct.pack("<I", 0))
+        elif id_ == b"RECV":
+            with lock:
+                entry = files.get(arg)
+            if entry is None:
+                sync_fail(conn, "remote object '%s' does not exist" % arg)
+                continue
+            data = entry[1]
+            for i in range(0, len(data), 64 * 1024):
+                chunk = data[i : i + 64 * 1024]
+                conn.send(b"DATA" + struct.pack("<I", len(chunk)) + chunk)
+            conn.send(b"DONE" + struct.pack("<I", 0))
+        else:
+            sync_fail(conn, "unknown sync request %r" % id_)
+
+
+def serve(sock):
+    conn = Conn(sock)
+    try:
+        cmd, arg0, arg1, _ = conn.read_message()
+        assert cmd == A_CNXN, "expected CNXN"
+        conn.max_payload = min(arg1, MAX_PAYLOAD)
+        conn.write_message(A_CNXN, ADB_VERSION, MAX_PAYLOAD, b"device::ro.product.name=fake;\0")
+        cmd, arg0, _, data = conn.read_message()
+        assert cmd == A_OPEN, "expected OPEN"
+        conn.local = arg0
+        service = data.rstrip(b"\0").decode()
+        if service == "shell:":
+            conn.write_message(A_OKAY, REMOTE_ID, conn.local)
+            shell(conn)
+        elif service.startswith("shell:"):
+            conn.write_message(A_OKAY, REMOTE_ID, conn.local)
+            conn.send(run(service[6:]))
+        elif service == "sync:":
+            conn.write_message(A_OKAY, REMOTE_ID, conn.local)
+            sync(conn)
+        conn.close()
+    except Closed:
+        pass
+    finally:
+        sock.close()
+
+
+def main():
+    server = socket.socket()
+    server.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
+    server.bind(("127.0.0.1", 0))
+    server.listen()
+    print(server.getsockname()[1], flush=True)
+    while True:
+        sock, _ = server.accept()
+        threading.Thread(target=serve, args=(sock,), daemon=True).start()
+
+
+if __name__ == "__main__":
+    sys.exit(main())
@@ -0,0 +1,106 @@
+#!/usr/bin/env python3
+"""A tiny fake bootloader speaking fastboot over TCP, for test_fastboot.py.
+
+It listens on a free port of 127.0.0.1 and prints the port. Flashed images
+are kept in memory, `oem md5 <partition>` returns the md5 of one.
+"""
+import hashlib
+import socket
+import struct
+import sys
+
+MAX_DOWNLOAD = 4 * 1024 * 1024
+partitions = {"boot": b"", "system": b""}
+
+
+class Closed(Exception):
+    pass
+
+
+def recv_exact(sock, n):
+    buf = b""
+    while len(buf) < n:
+        data = sock.recv(n - len(buf))
+        if not data:
+            raise Closed()
+        buf += data
+    return buf
+
+
+def read_packet(sock):
+    (length,) = struct.unpack(">Q", recv_exact(sock, 8))
+    return recv_exact(sock, length)
+
+
+def reply(sock, msg):
+    msg = msg.encode()
+    sock.sendall(struct.pack(">Q", len(msg)) + msg)
+
+
+def serve(sock):
+    assert recv_exact(sock, 4) == b"FB01", "bad handshake"
+    sock.sendall(b"FB01")
+    downloaded = None
+    while True:
+        cmd = read_packet(sock).decode()
+        if cmd == "getvar:max-download-size":
+            reply(sock, "OKAY0x%08x" % MAX_DOWNLOAD)
+        elif cmd == "getvar:product":
+            reply(sock, "INFOchecking the product")
+            reply(sock, "OKAYfake")
+        elif cmd.startswith("getvar:"):
+            reply(sock, "FAILGetVar Variable Not found")
+        elif cmd.startswith("download:"):
+            length = int(cmd[9:], 16)
+            if length > MAX_DOWNLOAD:
+                reply(sock, "FAILdata too large")
+                continue
+            reply(sock, "DATA%08x" % length)
+            data = b""
+            while len(data) < length:
+                data += read_packet(sock)
+            assert len(data) == length, "downloaded too much"
+            downloaded = data
+            reply(sock, "OKAY")
+        elif cmd.startswith("flash:"):
+            if cmd[6:] not in partitions:
+                reply(sock, "FAILpartition does not exist")
+            elif downloaded is None:
+                reply(sock, "FAILno image downloaded")
+            else:
+                reply(sock, "INFOwriting '%s'" % cmd[6:])
+                partitions[cmd[6:]] = downloaded
+                reply(sock, "OKAY")
+        elif cmd.startswith("erase:"):
+            if cmd[6:] not in partitions:
+                reply(sock, "FAILpartition does not exist")
+            else:
+                partitions[cmd[6:]] = b""
+                reply(sock, "OKAY")
+        elif cmd.startswith("oem md5 ") and cmd[8:] in partitions:
+            reply(sock, "OKAY" + hashlib.md5(partitions[cmd[8:]]).hexdigest())
+        elif cmd in ("reboot", "reboot-bootloader", "continue"):
+            reply(sock, "OKAY")
+            return
+        else:
+            reply(sock, "FAILunknown command")
+
+
+def main():
+    server = socket.socket()
+    server.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
+    server.bind(("127.0.0.1", 0))
+    server.listen()
+    print(server.getsockname()[1], flush=True)
+    while True:
+        sock, _ = server.accept()
+        try:
+            serve(sock)
+        except Closed:
+            pass
+        finally:
+            sock.close()
+
+
+if __name__ == "__main__":
+    sys.exit(main())
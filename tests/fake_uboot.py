@@ -0,0 +1,91 @@
+#!/usr/bin/env python3
+"""A tiny fake U-Boot console for test_bootloader.py, run it in a pty."""
+import os
+import select
+import sys
+import termios
+import tty
+
+PROMPT = "=> "
+env = {"bootdelay": "3", "bootcmd": "run distro_bootcmd"}
+status = 0
+
+
+def out(s):
+    os.write(1, s.encode())
+
+
+def readline():
+    line = ""
+    while True:
+        c = os.read(0, 1).decode(errors="replace")
+        if c in ("\r", "\n"):
+            out("\r\n")
+            return line
+        if c == "\x15":
+            out("\b \b" * len(line))
+            line = ""
+        elif c == "\x03":
+            out("<INTERRUPT>\r\n" + PROMPT)
+            line = ""
+        else:
+            out(c)
+            line += c
+
+
+def run(line):
+    global status
+    args = line.split(None, 2)
+    if not args:
+        return
+    cmd = args[0]
+    if cmd == "echo":
+        out(line[5:].replace("$?", str(status)) + "\r\n")
+        status = 0
+    elif cmd == "printenv":
+        if len(args) == 1:
+            for k, v in env.items():
+                out("%s=%s\r\n" % (k, v))
+            status = 0
+        elif args[1] in env:
+            out("%s=%s\r\n" % (args[1], env[args[1]]))
+            status = 0
+        else:
+            out('## Error: "%s" not defined\r\n' % args[1])
+            status = 1
+    elif cmd == "setenv":
+        if len(args) == 2:
+            env.pop(args[1], None)
+        else:
+            env[args[1]] = args[2].strip("'")
+        status = 0
+    elif cmd == "saveenv":
+        out("Saving Environment to MMC... OK\r\n")
+        status = 0
+    elif cmd == "false":
+        status = 1
+    else:
+        out("Unknown command '%s' - try 'help'\r\n" % cmd)
+        status = 1
+
+
+def main():
+    tty.setraw(0, termios.TCSANOW)
+    out("\r\nU-Boot SPL 2024.01 (fake)\r\n\r\nU-Boot 2024.01 (fake)\r\n\r\n")
+    for i in range(int(env["bootdelay"]), 0, -1):
+        out("\rHit any key to stop autoboot: %2d " % i)
+        r, _, _ = select.select([0], [], [], 1)
+        if r:
+            os.read(0, 1024)
+            out("\r\n")
+            break
+    else:
+        out("\r\nStarting kernel ...\r\n")
+        sys.exit(0)
+    while True:
+        out(PROMPT)
+        run(readline())
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,70 @@
+import os
+import subprocess
+import sys
+import tempfile
+import time
+
+import tester
+
+
+def wait_for(adb, text, timeout=10):
+    buf = b""
+    end = time.time() + timeout
+    while time.time() < end:
+        buf += bytes(adb.read())
+        if text in buf:
+            return buf
+        time.sleep(0.1)
+    assert False, "%r not in %r" % (text, buf)
+
+
+if __name__ == "__main__":
+    fake = os.path.join(os.path.dirname(os.path.abspath(__file__)), "fake_adbd.py")
+    adbd = subprocess.Popen([sys.executable, fake], stdout=subprocess.PIPE)
+    try:
+        addr = "127.0.0.1:%d" % int(adbd.stdout.readline())
+        adb = tester.Adb(tcp=addr)
+        wait_for(adb, b"fake:/ # ")
+        adb.write(b"echo hello adb\n")
+        wait_for(adb, b"hello adb\nfake:/ # ")
+
+        assert bytes(adb.exec("echo hi")) == b"hi\n"
+        assert bytes(adb.exec("cat /etc/hostname")) == b"fake\n"
+
+        with tempfile.TemporaryDirectory() as tmp:
+            local = os.path.join(tmp, "blob")
+            # Several sync chunks of 64 KiB, and a partial one
+            data = os.urandom(200 * 1024 + 123)
+            with open(local, "wb") as f:
+                f.write(data)
+            os.chmod(local, 0o751)
+            adb.push(local, "/data/blob")
+            assert bytes(adb.exec("stat -c %a /data/blob")) == b"751\n"
+            assert bytes(adb.exec("cat /data/blob")) == data
+
+            back = os.path.join(tmp, "back")
+            adb.pull("/data/blob", back)
+            with open(back, "rb") as f:
+                assert f.read() == data
+
+            missing = os.path.join(tmp, "missing")
+            try:
+                adb.pull("/data/nothing", missing)
+                assert False, "pulling a missing file should fail"
+            except RuntimeError as e:
+                assert "does not exist" in str(e)
+            assert not os.path.exists(missing)
+            try:
+                adb.push(local, "/proc/blob")
+                assert False, "pushing to a read-only path should fail"
+            except RuntimeError as e:
+                assert "Read-only file system" in str(e)
+
+        # The shell still works after the other streams
+        adb.write(b"echo still here\n")
+        wait_for(adb, b"still here\n")
+        del adb
+    finally:
+        adbd.kill()
+        adbd.wait()
+    print("All done")
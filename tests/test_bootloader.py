@@ -0,0 +1,27 @@
+import os
+
+import tester
+
+if __name__ == "__main__":
+    fake = os.path.join(os.path.dirname(os.path.abspath(__file__)), "fake_uboot.py")
+    s = tester.Shell(fake)
+    b = tester.Bootloader(s, "u-boot")
+    b.interrupt_autoboot(10)
+    assert b.printenv("bootdelay") == "3"
+    assert b.printenv("nothing") is None
+    b.setenv("bootargs", "console=ttyS0,115200 root=${rootdev}")
+    assert b.printenv("bootargs") == "console=ttyS0,115200 root=${rootdev}"
+    b.setenv("bootargs", "")
+    assert b.printenv("bootargs") is None
+    b.saveenv()
+    try:
+        b.script_run("false")
+        assert False, "false should fail"
+    except RuntimeError:
+        pass
+    try:
+        b.script_run("tftp 0x80200000 Image")
+        assert False, "unknown command should fail"
+    except RuntimeError:
+        pass
+    print("All done")
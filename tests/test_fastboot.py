@@ -0,0 +1,54 @@
+import hashlib
+import os
+import subprocess
+import sys
+import tempfile
+
+import tester
+
+if __name__ == "__main__":
+    fake = os.path.join(os.path.dirname(os.path.abspath(__file__)), "fake_fastboot.py")
+    bootloader = subprocess.Popen([sys.executable, fake], stdout=subprocess.PIPE)
+    try:
+        addr = "127.0.0.1:%d" % int(bootloader.stdout.readline())
+        fb = tester.Fastboot(addr)
+        fb.set_timeout(10)
+        assert fb.getvar("product") == "fake"
+        assert fb.getvar("max-download-size") == "0x00400000"
+        try:
+            fb.getvar("nothing")
+            assert False, "an unknown variable should fail"
+        except RuntimeError as e:
+            assert "Variable Not found" in str(e)
+
+        with tempfile.TemporaryDirectory() as tmp:
+            image = os.path.join(tmp, "boot.img")
+            # More than one download packet of 1 MiB
+            data = os.urandom(3 * 1024 * 1024 + 123)
+            with open(image, "wb") as f:
+                f.write(data)
+            fb.flash("boot", image)
+            assert fb.command("oem md5 boot") == hashlib.md5(data).hexdigest()
+
+            try:
+                fb.flash("vendor", image)
+                assert False, "flashing a missing partition should fail"
+            except RuntimeError as e:
+                assert "partition does not exist" in str(e)
+
+            with open(image, "wb") as f:
+                f.write(os.urandom(5 * 1024 * 1024))
+            try:
+                fb.flash("boot", image)
+                assert False, "an image over max-download-size should fail"
+            except RuntimeError as e:
+                assert "max-download-size" in str(e)
+            assert fb.command("oem md5 boot") == hashlib.md5(data).hexdigest()
+
+        fb.erase("boot")
+        assert fb.command("oem md5 boot") == hashlib.md5(b"").hexdigest()
+        fb.reboot()
+    finally:
+        bootloader.kill()
+        bootloader.wait()
+    print("All done")
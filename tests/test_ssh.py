@@ -0,0 +1,34 @@
+import os
+import socket
+
+import tester
+
+# Run against a local sshd, e.g.
+#   SSH_HOST=127.0.0.1 SSH_USER=$USER SSH_PASS=xxx python tests/test_ssh.py
+# The jump goes through the same sshd, so no second machine is needed.
+
+if __name__ == "__main__":
+    host = os.environ.get("SSH_HOST", "127.0.0.1")
+    user = os.environ.get("SSH_USER", "root")
+    password = os.environ.get("SSH_PASS")
+    agent = password is None
+
+    hop = f'host = "{host}"\nuser = "{user}"\n'
+    hop += f'password = "{password}"\n' if password else "agent = true\n"
+
+    s = tester.Ssh(host, user, password=password, agent=agent, jump=[hop, hop])
+    e = tester.Exec(s)
+    print(e.script_run("uname -a"))
+
+    out, _, status = s.exec("echo hello; exit 3")
+    assert out == "hello\n" and status == 3, (out, status)
+
+    s.upload(__file__, "/tmp/test_ssh_upload.py")
+    s.download("/tmp/test_ssh_upload.py", "/tmp/test_ssh_download.py")
+    with open(__file__) as a, open("/tmp/test_ssh_download.py") as b:
+        assert a.read() == b.read()
+
+    port = s.forward_local("127.0.0.1", 22)
+    with socket.create_connection(("127.0.0.1", port)) as c:
+        banner = c.recv(64)
+        assert banner.startswith(b"SSH-"), banner